@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    log::warn,
+    reflect::TypeUuid,
+};
+use serde::{de::Error, Deserialize, Deserializer};
+
+use crate::{number_colour_columns, Column};
+
+/// How soon a note can start after the one before it in the same column ends.
+/// Notes any closer than this are more likely written twice by mistake than meant to be hit
+/// separately, as they'd need two presses a couple of frames apart. It's a gap in time rather
+/// than on screen, so at slow scroll speeds targets further apart than this are still drawn
+/// overlapping
+const MIN_NOTE_GAP_MS: u32 = 30;
+
+/// A single note in a chart
+#[derive(Clone, Copy)]
+pub struct Note {
+    /// When the note's target should reach the crosshair, in milliseconds from the start of the
+    /// chart's song. Targets spawn early enough to fall there in time
+    pub time_ms: u32,
+    /// Which column the note falls in, counting from 0 on the left
+    pub column: Column,
+    /// How long the note must be held down for, or 0 for a normal tap note
+    pub duration_ms: u32,
+}
+
+/// A change of tempo part way through a chart's song
+#[derive(Clone, Copy, Deserialize)]
+pub struct TempoChange {
+    /// When the new tempo starts, in milliseconds from the start of the chart's song
+    pub time_ms: u32,
+    /// The new tempo, in beats per minute
+    pub bpm: f32,
+}
+
+/// What's drawn behind a chart's playfield, instead of the usual black
+#[derive(Clone, Deserialize)]
+pub enum Background {
+    /// The asset path of an image, stretched to fill the playfield
+    Image(String),
+    /// A solid colour, as its red, green and blue from 0.0 to 1.0
+    Color(f32, f32, f32),
+}
+
+/// A note as it's written in a chart file, which can be a chord of several columns at once
+#[derive(Deserialize)]
+struct ChartNote {
+    time_ms: u32,
+    #[serde(default, deserialize_with = "deserialize_column")]
+    column: Option<Column>,
+    /// Every column in a chord, as a shorthand for writing a note for each of them
+    #[serde(default)]
+    columns: Vec<Column>,
+    #[serde(default)]
+    duration_ms: u32,
+}
+
+/// Reads a note's column, which is written as a plain number rather than `Some(number)`
+fn deserialize_column<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Column>, D::Error> {
+    Column::deserialize(deserializer).map(Some)
+}
+
+/// Reads a chart's notes, splitting chords up into a [`Note`] for each of their columns.
+/// Every note needs either a `column` or some `columns`, but not both
+fn deserialize_notes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Note>, D::Error> {
+    let chart_notes = Vec::<ChartNote>::deserialize(deserializer)?;
+
+    let mut notes = Vec::with_capacity(chart_notes.len());
+    for chart_note in chart_notes {
+        let columns = match (chart_note.column, chart_note.columns) {
+            (Some(column), columns) if columns.is_empty() => vec![column],
+            (None, columns) if !columns.is_empty() => columns,
+            (Some(_), _) => {
+                return Err(D::Error::custom(format!(
+                    "the note at {}ms has both a column and columns, it needs just one",
+                    chart_note.time_ms
+                )))
+            }
+            (None, _) => {
+                return Err(D::Error::custom(format!(
+                    "the note at {}ms needs a column or columns",
+                    chart_note.time_ms
+                )))
+            }
+        };
+        notes.extend(columns.into_iter().map(|column| Note {
+            time_ms: chart_note.time_ms,
+            column,
+            duration_ms: chart_note.duration_ms,
+        }));
+    }
+    Ok(notes)
+}
+
+/// An authored chart, loaded from a `.ron` file in `assets/charts`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "dd8a7fe0-1c44-4e62-8bea-dd9fae076b46"]
+pub struct Beatmap {
+    /// The name shown in the song select menu, instead of the chart's file name
+    #[serde(default)]
+    pub title: Option<String>,
+    /// How hard the chart is, as described by its author
+    #[serde(default)]
+    pub difficulty: Option<String>,
+    /// The asset path of the song played alongside the chart, if it has one
+    #[serde(default)]
+    pub song: Option<String>,
+    /// Where the song select menu's preview of the song starts, in milliseconds from the start of
+    /// the song. Previews start from the first note if this isn't set
+    #[serde(default)]
+    pub preview_ms: Option<u32>,
+    /// What's drawn behind the playfield while the chart is played. It's black if this isn't set
+    #[serde(default)]
+    pub background: Option<Background>,
+    /// The tempo of the chart's song, in beats per minute, which the metronome ticks along to
+    #[serde(default)]
+    pub bpm: Option<f32>,
+    /// Where the tempo changes, in order. The chart's `bpm` is its tempo until the first change
+    #[serde(default)]
+    pub tempo_changes: Vec<TempoChange>,
+    /// Every note in the chart, sorted by time
+    #[serde(deserialize_with = "deserialize_notes")]
+    pub notes: Vec<Note>,
+}
+
+impl Beatmap {
+    /// When the last note in the chart ends, in milliseconds from the start of the chart
+    pub fn end_ms(&self) -> u32 {
+        self.notes
+            .iter()
+            .map(|note| note.time_ms + note.duration_ms)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// How many beats have passed at a point in the chart, in milliseconds from the start of its
+    /// song, following any tempo changes along the way. `None` if the chart doesn't have a tempo
+    pub fn beats_at(&self, time_ms: f32) -> Option<f32> {
+        let mut bpm = self.bpm?;
+        let mut since_ms = 0.0;
+        let mut beats = 0.0;
+        for change in &self.tempo_changes {
+            let change_ms = change.time_ms as f32;
+            if change_ms > time_ms {
+                break;
+            }
+            beats += (change_ms - since_ms) * bpm / 60_000.0;
+            since_ms = change_ms;
+            bpm = change.bpm;
+        }
+        Some(beats + (time_ms - since_ms) * bpm / 60_000.0)
+    }
+
+    /// Checks that the chart's tempo makes sense, so the beats can be followed through it
+    fn validate(&self) -> Result<(), String> {
+        let positive = |bpm: f32| bpm.is_finite() && bpm > 0.0;
+
+        match self.bpm {
+            Some(bpm) if !positive(bpm) => {
+                return Err(format!("bpm must be positive, not {}", bpm))
+            }
+            None if !self.tempo_changes.is_empty() => {
+                return Err("tempo changes need a bpm to change from".into())
+            }
+            _ => {}
+        }
+
+        let mut previous_ms = None;
+        for change in &self.tempo_changes {
+            if !positive(change.bpm) {
+                return Err(format!(
+                    "the tempo change at {}ms must have a positive bpm, not {}",
+                    change.time_ms, change.bpm
+                ));
+            }
+            if previous_ms.is_some_and(|previous_ms| change.time_ms <= previous_ms) {
+                return Err(format!(
+                    "the tempo change at {}ms is out of order, they must be in time order",
+                    change.time_ms
+                ));
+            }
+            previous_ms = Some(change.time_ms);
+        }
+
+        Ok(())
+    }
+
+    /// Takes out every note that starts too soon after the one before it in the same column,
+    /// within [`MIN_NOTE_GAP_MS`] of it ending, and returns them. The notes must be sorted by time
+    fn remove_colliding_notes(&mut self) -> Vec<Note> {
+        let mut column_ends: HashMap<Column, u32> = HashMap::new();
+        let mut colliding = Vec::new();
+        self.notes.retain(|note| {
+            let free_from = column_ends
+                .get(&note.column)
+                .map(|end| end + MIN_NOTE_GAP_MS);
+            if free_from.is_some_and(|free_from| note.time_ms < free_from) {
+                colliding.push(*note);
+                return false;
+            }
+            column_ends.insert(note.column, note.time_ms + note.duration_ms);
+            true
+        });
+        colliding
+    }
+
+    /// How many notes start in each of `buckets` equal slices of the chart,
+    /// scaled so the busiest slice is 1.0
+    pub fn density(&self, buckets: usize) -> Vec<f32> {
+        let mut counts = vec![0u32; buckets];
+        let end_ms = self.end_ms().max(1) as usize;
+        for note in &self.notes {
+            let bucket = (note.time_ms as usize * buckets / end_ms).min(buckets - 1);
+            counts[bucket] += 1;
+        }
+
+        let busiest = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        counts
+            .into_iter()
+            .map(|count| count as f32 / busiest)
+            .collect()
+    }
+}
+
+/// Loads [`Beatmap`]s from RON files
+#[derive(Default)]
+pub struct BeatmapLoader;
+
+impl AssetLoader for BeatmapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            // Charts written before there could be more than four columns name them by colour
+            let mut beatmap = ron::de::from_bytes::<Beatmap>(bytes).or_else(|err| {
+                let chart = std::str::from_utf8(bytes).map_err(|_| err.clone())?;
+                ron::from_str(&number_colour_columns(chart)).map_err(|_| err)
+            })?;
+            beatmap.validate().map_err(bevy::asset::Error::msg)?;
+            // Charts don't have to be written in order, but the spawner expects them to be
+            beatmap.notes.sort_by_key(|note| note.time_ms);
+
+            // Overlapping notes can't be told apart, so they're left out rather than failing
+            // the whole chart
+            let colliding = beatmap.remove_colliding_notes();
+            if !colliding.is_empty() {
+                let notes: Vec<String> = colliding
+                    .iter()
+                    .map(|note| format!("{}ms in {}", note.time_ms, note.column))
+                    .collect();
+                warn!(
+                    "Skipping {} notes in {} that overlap the note before them in their column: {}",
+                    colliding.len(),
+                    load_context.path().display(),
+                    notes.join(", ")
+                );
+            }
+            load_context.set_default_asset(LoadedAsset::new(beatmap));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
@@ -1,546 +1,10456 @@
-use std::time::Duration;
+// Bevy systems take their inputs as arguments and queries, so these lints don't fit them well
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
 
-use bevy::{app::AppExit, prelude::*, window::close_on_esc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    marker::PhantomData,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    app::AppExit,
+    asset::LoadState,
+    audio::AudioSink,
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    ecs::system::SystemParam,
+    input::InputSystem,
+    prelude::*,
+    sprite::Anchor,
+    time::Stopwatch,
+    transform::TransformSystem,
+    ui::{FocusPolicy, UiSystem},
+    window::{PresentMode, WindowCloseRequested, WindowFocused, WindowResized, WindowSettings},
+};
 
 use iyes_loopless::prelude::*;
-use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, SeqAccess, Visitor},
+    Deserialize, Serialize,
 };
+
+mod beatmap;
+mod persist;
+mod run_code;
+#[cfg(test)]
+mod tests;
+mod wav;
+
+use beatmap::{Background, Beatmap, BeatmapLoader};
+use run_code::RunCode;
 // Heavy code reuse from https://github.com/IyesGames/iyes_loopless/blob/main/examples/menu.rs
 
+/// The width of the playfield, which is scaled to fit inside the window
+const PLAYFIELD_WIDTH: f32 = 450.0;
+
+/// The height of the playfield, which is scaled to fit inside the window
+const PLAYFIELD_HEIGHT: f32 = 700.0;
+
 /// The game's states
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum GameState {
+    Loading,
+    LoadFailed,
     StartMenu,
+    Controls,
+    Calibration,
+    Settings,
+    Modifiers,
+    SongSelect,
+    Replays,
     Playing,
+    Paused,
+    GameOverMenu,
 }
 
 /// Marker component for entities used in the start menu
 #[derive(Component)]
 struct StartMenu;
 
-/// Marker component for entities used in the game
+/// Marker component for entities used in the game.
+///
+/// Everything spawned while playing needs this (children included), otherwise it will be left
+/// behind when the game is despawned
 #[derive(Component)]
 struct Game;
 
+/// Marker component for entities used in the game over menu
+#[derive(Component)]
+struct GameOverMenu;
+
+/// Component for the full-screen overlay that fades in from black after a state change
+#[derive(Component)]
+struct FadeOverlay(Timer);
+
+/// Marker component for entities used in the loading screen
+#[derive(Component)]
+struct LoadingScreen;
+
+/// Marker component for the part of the loading bar that fills up as assets load
+#[derive(Component)]
+struct LoadingBar;
+
+/// Marker component for the loading screen's text, which is added once the font has loaded
+#[derive(Component)]
+struct LoadingLabel;
+
+/// Marker component for entities used in the screen shown when assets fail to load
+#[derive(Component)]
+struct LoadFailedMenu;
+
+/// Marker component for entities used in the controls menu
+#[derive(Component)]
+struct ControlsMenu;
+
+/// Marker component for entities used in the calibration screen
+#[derive(Component)]
+struct CalibrationMenu;
+
+/// Marker component for entities used in the pause menu
+#[derive(Component)]
+struct PauseMenu;
+
+/// Marker component for entities used in the settings menu
+#[derive(Component)]
+struct SettingsMenu;
+
+/// Marker component for entities used in the modifiers menu
+#[derive(Component)]
+struct ModifiersMenu;
+
+/// Marker component for entities used in the song select menu
+#[derive(Component)]
+struct SongSelectMenu;
+
+/// Marker component for entities used in the replays menu
+#[derive(Component)]
+struct ReplaysMenu;
+
 /// Marker component for the start button
 #[derive(Component)]
 struct StartButton;
 
+/// Marker component for the button that starts an endless run
+#[derive(Component)]
+struct EndlessButton;
+
+/// Marker component for the play chart button
+#[derive(Component)]
+struct ChartButton;
+
+/// Marker component for the controls button
+#[derive(Component)]
+struct ControlsButton;
+
+/// Marker component for the calibrate button
+#[derive(Component)]
+struct CalibrateButton;
+
+/// Marker component for the settings button
+#[derive(Component)]
+struct SettingsButton;
+
+/// Marker component for the modifiers button
+#[derive(Component)]
+struct ModifiersButton;
+
+/// Marker component for the button that opens the replays menu
+#[derive(Component)]
+struct ReplaysButton;
+
+/// Marker component for the button that resumes the run that was interrupted last time
+#[derive(Component)]
+struct ResumeSessionButton;
+
+/// Component for the replays menu's buttons, holding the file of the replay each one plays
+#[derive(Component)]
+struct ReplayButton(String);
+
+/// Marker component for the game over menu's button that saves a replay of the run
+#[derive(Component)]
+struct SaveReplayButton;
+
+/// Marker component for the text of the button that saves a replay
+#[derive(Component)]
+struct SaveReplayLabel;
+
+/// Component for the game over menu's button that copies the run's code, holding the code
+#[derive(Component)]
+struct CopyRunCodeButton(String);
+
+/// Marker component for the text of the button that copies the run's code
+#[derive(Component)]
+struct CopyRunCodeLabel;
+
+/// Marker component for the song select menu's button that starts a run from a copied code
+#[derive(Component)]
+struct PasteRunCodeButton;
+
+/// Marker component for the text of the button that starts a run from a copied code
+#[derive(Component)]
+struct PasteRunCodeLabel;
+
 /// Marker component for the exit button
 #[derive(Component)]
 struct ExitButton;
 
-/// Component containing a button's previous interaction state
+/// Marker component for the button that saves a measured audio offset
 #[derive(Component)]
-struct OldInteraction(Interaction);
+struct SaveOffsetButton;
 
+/// Component for the calibration screen's buttons that change the visual offset by an amount
 #[derive(Component)]
-struct Target;
+struct VisualOffsetButton(i32);
 
+/// Marker component for the calibration screen's visual offset text
 #[derive(Component)]
-struct ScoreDisplay;
+struct VisualOffsetLabel;
 
-#[derive(Component, PartialEq, Eq, Clone, Copy)]
-enum Column {
-    Yellow,
-    Red,
-    Blue,
-    Green,
-}
+/// Marker component for the calibration screen's measured offset text
+#[derive(Component)]
+struct CalibrationReadout;
 
-impl Column {
-    const fn index(self) -> u8 {
-        match self {
-            Column::Yellow => 0,
-            Column::Red => 1,
-            Column::Blue => 2,
-            Column::Green => 3,
-        }
-    }
-}
+/// Marker component for the note that falls to the line on every beat of the calibration screen
+#[derive(Component)]
+struct CalibrationNote;
 
-impl Distribution<Column> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Column {
-        match rng.gen_range(0..4) {
-            0 => Column::Yellow,
-            1 => Column::Red,
-            2 => Column::Blue,
-            _ => Column::Green,
-        }
-    }
-}
+/// Marker component for the retry button
+#[derive(Component)]
+struct RetryButton;
 
-#[derive(Default)]
-struct MenuAssetHandles {
-    logo: Option<Handle<Image>>,
-    font: Option<Handle<Font>>,
-}
+/// Marker component for the main menu button
+#[derive(Component)]
+struct MainMenuButton;
 
-#[derive(Default)]
-struct TextureAtlasHandles {
-    crosshairs: Option<Handle<TextureAtlas>>,
-    targets: Option<Handle<TextureAtlas>>,
-}
+/// Marker component for the resume button
+#[derive(Component)]
+struct ResumeButton;
 
-#[derive(Default)]
-struct NoteAudioHandles {
-    yellow: Option<Handle<AudioSource>>,
-    red: Option<Handle<AudioSource>>,
-    blue: Option<Handle<AudioSource>>,
-    green: Option<Handle<AudioSource>>,
-}
+/// Marker component for the quit button
+#[derive(Component)]
+struct QuitButton;
 
-#[derive(Default)]
-struct Scoreboard {
-    pub score: i32,
-    pub combo: i32,
-}
+/// Marker component for buttons that return to the start menu
+#[derive(Component)]
+struct BackButton;
 
-impl Scoreboard {
-    fn hit(&mut self) {
-        if self.combo < 5 {
-            self.combo += 1;
-        }
-        self.score += self.combo;
-    }
+/// Component for buttons that choose the difficulty
+#[derive(Component)]
+struct DifficultyButton(Difficulty);
 
-    fn miss(&mut self) {
-        self.combo = 0;
-        self.score -= self.combo + 1;
-    }
-}
+/// Marker component for the start menu's difficulty text
+#[derive(Component)]
+struct DifficultyLabel;
 
-struct TargetHitEvent(Column);
+/// Marker component for the start menu's scroll speed text
+#[derive(Component)]
+struct ScrollSpeedLabel;
 
-struct TargetMissEvent(Column);
+/// Component for buttons that rebind a column's or action's key
+#[derive(Component)]
+struct RebindButton(Binding);
 
-/// Where all the magic happens
-fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            title: "Rhythm Game".into(),
-            width: 450.0,
-            height: 700.0,
-            resizable: false,
-            ..Default::default()
-        })
-        .insert_resource(ClearColor(Color::BLACK))
-        .add_plugins(DefaultPlugins)
-        .add_event::<TargetHitEvent>()
-        .add_event::<TargetMissEvent>()
-        // Set GameState::StartMenu as the default state
-        .add_loopless_state(GameState::StartMenu)
-        // Setup the start menu when GameState::StartMenu is entered
-        .add_enter_system(GameState::StartMenu, setup_start_menu)
-        .add_system_set(
-            ConditionSet::new()
-                // While the start menu is visible..
-                .run_in_state(GameState::StartMenu)
-                // Quit the game if the player presses escape
-                .with_system(close_on_esc)
-                // Change the colour of the buttons when the player interacts with them
-                .with_system(button_visual_interact)
-                // Run the associated code when the buttons are clicked
-                .with_system(on_start_button.run_if(button_interact::<StartButton>))
-                .with_system(on_exit_button.run_if(button_interact::<ExitButton>))
-                .into(),
-        )
-        // Despawn the entire start menu when it is exited
-        .add_exit_system(GameState::StartMenu, despawn_with::<StartMenu>)
-        // Setup the game when GameState::Playing is entered
-        .add_enter_system(GameState::Playing, setup_game)
-        .add_system_set(
-            ConditionSet::new()
-                // While the game is running
-                .run_in_state(GameState::Playing)
-                // Exit to the menu when the player presses escape
-                .with_system(menu_on_esc)
-                .with_system(update_targets)
-                .with_system(shoot_targets)
-                .with_system(play_hit_sound)
-                .with_system(update_scoreboard)
-                .into(),
-        )
-        .add_stage_before(
-            CoreStage::Update,
-            "SpawnTargets",
-            FixedTimestepStage::new(Duration::from_millis(350)).with_stage(SystemStage::single(
-                spawn_targets.run_in_state(GameState::Playing),
-            )),
-        )
-        // Despawn the entire game when it is exited
-        .add_exit_system(GameState::Playing, despawn_with::<Game>)
-        // Spawn the camera (for the game and for the UI)
-        .add_startup_system(setup_camera)
-        .init_resource::<MenuAssetHandles>()
-        .init_resource::<TextureAtlasHandles>()
-        .init_resource::<NoteAudioHandles>()
-        .init_resource::<Scoreboard>()
-        .add_startup_system(load_assets)
-        .run();
-}
+/// Component for the text showing a column's or action's bound keys
+#[derive(Component)]
+struct BindingLabel(Binding);
 
-/// Recursively despawns every entity with a given component
-fn despawn_with<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
-    for e in q.iter() {
-        commands.entity(e).despawn_recursive();
-    }
-}
+/// Marker component for the controls menu's feedback text
+#[derive(Component)]
+struct ControlsFeedback;
 
-/// Spawn a 2D camera
-fn setup_camera(mut commands: Commands) {
-    commands.spawn_bundle(Camera2dBundle::default());
-}
+/// Component for buttons that turn a volume up or down by an amount
+#[derive(Component)]
+struct VolumeButton(VolumeChannel, f32);
 
-fn load_assets(
-    asset_server: Res<AssetServer>,
-    mut menu_asset_handles: ResMut<MenuAssetHandles>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut atlas_handles: ResMut<TextureAtlasHandles>,
-    mut audio_handles: ResMut<NoteAudioHandles>,
-) {
-    menu_asset_handles.logo = Some(asset_server.load("textures/logo.png"));
-    menu_asset_handles.font = Some(asset_server.load("fonts/comic.ttf"));
+/// Component for the text showing a volume
+#[derive(Component)]
+struct VolumeLabel(VolumeChannel);
 
-    let crosshair_texture_handle = asset_server.load("textures/crosshairs.png");
-    let crosshair_texture_atlas =
-        TextureAtlas::from_grid(crosshair_texture_handle, Vec2::new(64.0, 64.0), 4, 1);
-    let crosshair_atlas_handle = texture_atlases.add(crosshair_texture_atlas);
+/// Component for the settings menu's buttons that raise or lower the judgment line by an amount
+#[derive(Component)]
+struct JudgmentLineButton(i32);
 
-    let target_texture_handle = asset_server.load("textures/targets.png");
-    let target_texture_atlas =
-        TextureAtlas::from_grid(target_texture_handle, Vec2::new(64.0, 64.0), 4, 1);
-    let target_atlas_handle = texture_atlases.add(target_texture_atlas);
+/// Marker component for the settings menu's judgment line text
+#[derive(Component)]
+struct JudgmentLineLabel;
 
-    atlas_handles.crosshairs = Some(crosshair_atlas_handle);
-    atlas_handles.targets = Some(target_atlas_handle);
+/// Component for sliders that set a volume
+#[derive(Component)]
+struct VolumeSlider(VolumeChannel);
 
-    audio_handles.yellow = Some(asset_server.load("sounds/notes/yellow.ogg"));
-    audio_handles.red = Some(asset_server.load("sounds/notes/red.ogg"));
-    audio_handles.blue = Some(asset_server.load("sounds/notes/blue.ogg"));
-    audio_handles.green = Some(asset_server.load("sounds/notes/green.ogg"));
-}
+/// Component for buttons that toggle a modifier
+#[derive(Component)]
+struct ModifierButton(Modifier);
 
-/// Spawn the start menu ui
-fn setup_start_menu(mut commands: Commands, asset_handles: Res<MenuAssetHandles>) {
-    if let MenuAssetHandles {
-        logo: Some(logo),
-        font: Some(font),
-    } = &*asset_handles
-    {
-        let button_style = Style {
-            justify_content: JustifyContent::Center,
-            align_items: AlignItems::Center,
-            padding: UiRect::all(Val::Px(8.0)),
-            margin: UiRect::all(Val::Px(4.0)),
-            flex_grow: 1.0,
-            ..Default::default()
-        };
+/// Component for the text showing whether a modifier is on
+#[derive(Component)]
+struct ModifierLabel(Modifier);
 
-        let button_textstyle = TextStyle {
-            font: font.clone(),
-            font_size: 36.0,
-            color: Color::BLACK,
-        };
+/// Marker component for the button that toggles punishing ghost taps
+#[derive(Component)]
+struct GhostTapButton;
 
-        let menu = commands
-            .spawn_bundle(NodeBundle {
-                color: UiColor(Color::rgb(0.5, 0.5, 0.5)),
-                style: Style {
-                    size: Size::new(Val::Auto, Val::Auto),
-                    margin: UiRect::all(Val::Auto),
-                    align_self: AlignSelf::Center,
-                    flex_direction: FlexDirection::ColumnReverse,
-                    justify_content: JustifyContent::Center,
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .insert(StartMenu)
-            .id();
+/// Marker component for the settings menu's ghost tap text
+#[derive(Component)]
+struct GhostTapLabel;
+
+/// Marker component for the button that switches how hit sounds are played
+#[derive(Component)]
+struct HitSoundButton;
+
+/// Marker component for the settings menu's hit sound text
+#[derive(Component)]
+struct HitSoundLabel;
+
+/// Marker component for the button that changes how many hit sounds a column can play at once
+#[derive(Component)]
+struct HitSoundVoicesButton;
+
+/// Marker component for the settings menu's hit sound voices text
+#[derive(Component)]
+struct HitSoundVoicesLabel;
+
+/// Marker component for the button that toggles screen shake
+#[derive(Component)]
+struct ScreenShakeButton;
+
+/// Marker component for the button that toggles charge scoring
+#[derive(Component)]
+struct ChargeScoringButton;
+
+/// Marker component for the settings menu's charge scoring text
+#[derive(Component)]
+struct ChargeScoringLabel;
+
+/// Marker component for the button that toggles training wheels
+#[derive(Component)]
+struct TrainingWheelsButton;
+
+/// Marker component for the settings menu's training wheels text
+#[derive(Component)]
+struct TrainingWheelsLabel;
+
+/// Marker component for the button that toggles key labels
+#[derive(Component)]
+struct KeyLabelsButton;
+
+/// Marker component for the settings menu's key labels text
+#[derive(Component)]
+struct KeyLabelsLabel;
+
+/// Marker component for the button that toggles streamer mode
+#[derive(Component)]
+struct StreamerModeButton;
+
+/// Marker component for the settings menu's streamer mode text
+#[derive(Component)]
+struct StreamerModeLabel;
+
+/// Marker component for the settings menu's screen shake text
+#[derive(Component)]
+struct ScreenShakeLabel;
+
+/// Marker component for the button that toggles upscroll
+#[derive(Component)]
+struct UpScrollButton;
+
+/// Marker component for the settings menu's upscroll text
+#[derive(Component)]
+struct UpScrollLabel;
+
+/// Marker component for the button that toggles auto play
+#[derive(Component)]
+struct AutoPlayButton;
+
+/// Marker component for the settings menu's auto play text
+#[derive(Component)]
+struct AutoPlayLabel;
+
+/// Marker component for the button that changes how runs start
+#[derive(Component)]
+struct StartModeButton;
+
+/// Marker component for the settings menu's start mode text
+#[derive(Component)]
+struct StartModeLabel;
+
+/// Marker component for the button that toggles column shapes
+#[derive(Component)]
+struct ColumnShapesButton;
+
+/// Marker component for the settings menu's column shapes text
+#[derive(Component)]
+struct ColumnShapesLabel;
+
+/// Marker component for the button that changes the hit window
+#[derive(Component)]
+struct HitWindowButton;
+
+/// Marker component for the settings menu's hit window text
+#[derive(Component)]
+struct HitWindowLabel;
+
+/// Marker component for the button that changes the frame rate limit
+#[derive(Component)]
+struct FrameRateButton;
+
+/// Marker component for the button that changes how many lanes there are
+#[derive(Component)]
+struct LanesButton;
+
+/// Marker component for the settings menu's lane count text
+#[derive(Component)]
+struct LanesLabel;
+
+/// Marker component for the settings menu's frame rate text
+#[derive(Component)]
+struct FrameRateLabel;
+
+/// Marker component for the button that switches the scroll speed mode
+#[derive(Component)]
+struct ScrollModeButton;
+
+/// Marker component for the settings menu's scroll speed mode text
+#[derive(Component)]
+struct ScrollModeLabel;
+
+/// Marker component for the button that toggles mouse and touch input
+#[derive(Component)]
+struct PointerInputButton;
+
+/// Marker component for the settings menu's mouse and touch input text
+#[derive(Component)]
+struct PointerInputLabel;
+
+/// Marker component for the button that toggles timing markers
+#[derive(Component)]
+struct TimingMarkersButton;
+
+/// Marker component for the settings menu's timing markers text
+#[derive(Component)]
+struct TimingMarkersLabel;
+
+/// Marker component for the button that toggles fast/slow labels
+#[derive(Component)]
+struct FastSlowButton;
+
+/// Marker component for the settings menu's fast/slow labels text
+#[derive(Component)]
+struct FastSlowLabel;
+
+/// Marker component for the button that toggles combo ducking
+#[derive(Component)]
+struct ComboDuckingButton;
+
+/// Marker component for the settings menu's combo ducking text
+#[derive(Component)]
+struct ComboDuckingLabel;
+
+/// Marker component for the button that toggles announcements
+#[derive(Component)]
+struct AnnouncementsButton;
+
+/// Marker component for the settings menu's announcements text
+#[derive(Component)]
+struct AnnouncementsLabel;
+
+/// Marker component for the button that toggles the beat pulse
+#[derive(Component)]
+struct BeatPulseButton;
+
+/// Marker component for the settings menu's beat pulse text
+#[derive(Component)]
+struct BeatPulseLabel;
+
+/// Marker component for the button that toggles the metronome
+#[derive(Component)]
+struct MetronomeButton;
+
+/// Marker component for the settings menu's metronome text
+#[derive(Component)]
+struct MetronomeLabel;
+
+/// Component for the song select menu's buttons, one for each chart on a page
+#[derive(Component)]
+struct ChartSlotButton(usize);
+
+/// Component for the text of a song select button
+#[derive(Component)]
+struct ChartSlotLabel(usize);
+
+/// Component for buttons that move between pages of charts, or of settings
+#[derive(Component)]
+struct PageButton(isize);
+
+/// Component for the column of settings on one of the settings menu's pages
+#[derive(Component)]
+struct SettingsPageNode(SettingsPage);
+
+/// Marker component for the settings menu's page name text
+#[derive(Component)]
+struct SettingsPageLabel;
+
+/// Marker component for the song select menu's page number or message text
+#[derive(Component)]
+struct SongSelectMessage;
+
+/// Marker component for the button that toggles practice mode
+#[derive(Component)]
+struct PracticeButton;
+
+/// Marker component for the song select menu's practice mode text
+#[derive(Component)]
+struct PracticeLabel;
+
+/// Component for buttons that move one of the practice loop's bounds by an amount, in milliseconds
+#[derive(Component)]
+struct PracticeBoundButton(PracticeBound, i64);
+
+/// Component for the text showing one of the practice loop's bounds
+#[derive(Component)]
+struct PracticeBoundLabel(PracticeBound);
+
+/// Marker component for the indicator shown in the corner while the game is muted
+#[derive(Component)]
+struct MuteIndicator;
+
+/// Marker component for the FPS and frame time overlay
+#[derive(Component)]
+struct DiagnosticsOverlay;
+
+/// Marker component for the text showing the run's timing statistics
+#[derive(Component)]
+struct TimingStatsDisplay;
+
+/// Component containing a button's previous interaction state
+#[derive(Component)]
+struct OldInteraction(Interaction);
+
+/// Component for a slider's track, holding how far along it the handle is, from 0.0 to 1.0.
+///
+/// Sliders don't know what they're setting, so each use gets its own marker component and
+/// a system that copies the value into the right place when it changes
+#[derive(Component)]
+struct Slider(f32);
+
+/// Marker component for the part of a slider that is dragged along its track
+#[derive(Component)]
+struct SliderHandle;
+
+/// Component for the targets that fall towards the crosshairs
+#[derive(Component, Default)]
+struct Target {
+    /// When [`update_targets`] last moved the target, in seconds since startup
+    moved_at: Option<f64>,
+    /// Whether the target has fallen far enough to be shot, and has a [`HitZoneGlow`]
+    in_hit_zone: bool,
+}
+
+impl Target {
+    /// How much to add to a target's height to get where it was when the player pressed its column.
+    ///
+    /// Input is only seen once a frame, so the press is taken to have happened halfway through it.
+    /// Depending on whether the target has moved yet this frame, it was then either a little
+    /// above or a little below where it is now, and judging it there means the frame rate,
+    /// and the order the systems run in, don't shift every judgment early or late
+    fn press_correction(&self, time: &Time, frame: Duration, scroll_speed: f32) -> f32 {
+        let frame = frame.as_secs_f64();
+        let since_moved = match self.moved_at {
+            // A target can't have moved more than a frame ago unless the game was paused,
+            // in which case it hasn't moved since the frame before the pause
+            Some(moved_at) => (time.seconds_since_startup() - moved_at).min(frame),
+            None => frame / 2.0,
+        };
+        scroll_speed * (frame / 2.0 - since_moved) as f32
+    }
+}
+
+/// Component for targets that must be held down until their tail passes the crosshair
+#[derive(Component)]
+struct HoldNote {
+    duration_ms: u32,
+    /// How accurately the head was hit, once it has been
+    held: Option<Judgment>,
+}
+
+impl HoldNote {
+    /// The length of the note's tail in pixels
+    fn length(&self, scroll_speed: f32) -> f32 {
+        self.duration_ms as f32 / 1000.0 * scroll_speed
+    }
+}
+
+/// How long each ripple of a target's glow takes, once it can be shot
+const HIT_ZONE_PULSE_PERIOD: Duration = Duration::from_millis(250);
+
+/// How much bigger than its target a glow ripples out to
+const HIT_ZONE_PULSE_SCALE: f32 = 0.4;
+
+/// Component for the glow that ripples out from behind a target once it can be shot,
+/// so the player can see it's time to press
+#[derive(Component)]
+struct HitZoneGlow(Timer);
+
+/// Marker component for the crosshairs that targets are shot at
+#[derive(Component)]
+struct Crosshair;
+
+/// Marker component for the outline around the next target to hit, with training wheels on
+#[derive(Component)]
+struct NextTargetHighlight;
+
+/// Component for the text on a crosshair showing the keys bound to its column
+#[derive(Component)]
+struct KeyLabel(Column);
+
+/// How long a crosshair flash takes to fade back to normal
+const FLASH_DURATION: Duration = Duration::from_millis(150);
+
+/// The tint and scale a crosshair flashes to after a hit
+const HIT_FLASH: (Color, f32) = (Color::rgb(0.6, 1.0, 0.6), 1.3);
+
+/// The tint and scale a crosshair flashes to after a miss
+const MISS_FLASH: (Color, f32) = (Color::RED, 1.0);
+
+/// The tint and scale a crosshair flashes to whenever its column is pressed,
+/// kept faint so it can't be mistaken for a hit
+const PRESS_FLASH: (Color, f32) = (Color::rgb(0.8, 0.85, 1.0), 1.1);
+
+/// Component for sprites that briefly change colour and size, then fade back
+#[derive(Component)]
+struct FlashTimer {
+    timer: Timer,
+    color: Color,
+    scale: f32,
+    /// The sprite's normal colour, which it returns to after flashing
+    resting_color: Color,
+    /// The sprite's normal scale, which it returns to after flashing
+    resting_scale: f32,
+}
+
+impl FlashTimer {
+    fn new(resting_color: Color, resting_scale: f32) -> Self {
+        let mut timer = Timer::new(FLASH_DURATION, false);
+        // Start out already faded
+        timer.tick(FLASH_DURATION);
+        Self {
+            timer,
+            color: resting_color,
+            scale: 1.0,
+            resting_color,
+            resting_scale,
+        }
+    }
+
+    /// Restarts the flash with a new tint and scale
+    fn flash(&mut self, (color, scale): (Color, f32)) {
+        self.color = color;
+        self.scale = scale;
+        self.timer.reset();
+    }
+}
+
+/// How long a judgment label floats for before disappearing
+const FLOATING_TEXT_LIFETIME: Duration = Duration::from_millis(500);
+
+/// How long hit particles last before disappearing
+const PARTICLE_LIFETIME: Duration = Duration::from_millis(400);
+
+/// The most particles that can be spawned in a single frame, however many targets were hit
+const MAX_PARTICLES_PER_FRAME: usize = 48;
+
+/// How long a timing marker lasts before disappearing
+const TIMING_MARKER_LIFETIME: Duration = Duration::from_millis(600);
+
+/// How far a timing marker sits from the crosshair for each millisecond early or late, in pixels
+const TIMING_MARKER_SCALE: f32 = 0.4;
+
+/// Component for the small ticks that show how early or late a hit was, which fade out over time
+#[derive(Component)]
+struct TimingMarker(Timer);
+
+/// Component for the small sprites that burst out of a crosshair when its target is hit
+#[derive(Component)]
+struct Particle {
+    lifetime: Timer,
+    velocity: Vec3,
+}
+
+/// How long the camera shakes for after a miss
+const SHAKE_DURATION: Duration = Duration::from_millis(200);
+
+/// How far the camera can be knocked from the centre by a shake, in pixels
+const SHAKE_INTENSITY: f32 = 4.0;
+
+/// Component for the camera, which shakes briefly when the player misses
+#[derive(Component)]
+struct CameraShake {
+    timer: Timer,
+    intensity: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        let mut timer = Timer::new(SHAKE_DURATION, false);
+        // Start out already settled
+        timer.tick(SHAKE_DURATION);
+        Self {
+            timer,
+            intensity: SHAKE_INTENSITY,
+        }
+    }
+}
+
+/// How fast judgment labels float upwards, in pixels per second
+const FLOATING_TEXT_SPEED: f32 = 80.0;
+
+/// Component for text that drifts away and fades out, then despawns itself
+#[derive(Component)]
+struct FloatingText {
+    lifetime: Timer,
+    velocity: Vec3,
+}
+
+impl FloatingText {
+    fn new(velocity: Vec3) -> Self {
+        Self {
+            lifetime: Timer::new(FLOATING_TEXT_LIFETIME, false),
+            velocity,
+        }
+    }
+}
+
+#[derive(Component)]
+struct ScoreDisplay;
+
+/// Marker component for the combo text
+#[derive(Component)]
+struct ComboDisplay;
+
+/// Marker component for the text showing how fast targets spawn with [`Modifier::Adaptive`]
+#[derive(Component)]
+struct SpawnRateDisplay;
+
+/// Marker component for the countdown text shown before the game starts
+#[derive(Component)]
+struct CountdownDisplay;
+
+/// Marker component for the filled part of the health bar
+#[derive(Component)]
+struct HealthBar;
+
+/// Marker component for the bar along the top of the screen showing how far through a chart
+/// the player is
+#[derive(Component)]
+struct ProgressBar;
+
+/// Marker component for the text showing how long an endless run has lasted
+#[derive(Component)]
+struct SurvivalDisplay;
+
+/// Marker component for the bars under the progress bar showing where a chart's notes bunch up
+#[derive(Component)]
+struct DensityBar;
+
+/// Marker component for the line on the density map showing how far into the song the player is
+#[derive(Component)]
+struct DensityMarker;
+
+/// How many slices a chart is split into for its density map
+const DENSITY_MAP_BUCKETS: usize = 90;
+
+/// How tall the density map's bars are at their busiest
+const DENSITY_MAP_HEIGHT: f32 = 10.0;
+
+/// The names and colours of the columns, from left to right
+const COLUMN_THEMES: [(&str, Color); 6] = [
+    ("Yellow", Color::rgb(1.0, 0.85, 0.2)),
+    ("Red", Color::rgb(0.9, 0.2, 0.2)),
+    ("Blue", Color::rgb(0.2, 0.4, 0.9)),
+    ("Green", Color::rgb(0.2, 0.8, 0.3)),
+    ("Purple", Color::rgb(0.6, 0.3, 0.9)),
+    ("Orange", Color::rgb(1.0, 0.5, 0.1)),
+];
+
+/// A column of the playfield, counting from 0 on the left
+#[derive(Component, Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Column(u8);
+
+impl Column {
+    const fn index(self) -> u8 {
+        self.0
+    }
+
+    /// The crosshair and target textures only have four colours, so later columns reuse them
+    const fn sprite_index(self) -> usize {
+        self.0 as usize % 4
+    }
+
+    /// The shape drawn over the column's targets and crosshair when column shapes are on.
+    /// Like the colours, these go in [`ColumnShape::ALL`] order from the left and wrap around
+    const fn shape(self) -> ColumnShape {
+        ColumnShape::ALL[self.0 as usize % ColumnShape::ALL.len()]
+    }
+}
+
+/// Rewrites the colour names columns were saved as, before there could be more than four of them,
+/// as the numbers they're saved as now. Anything inside a string is left alone.
+///
+/// Columns can't be read by either name or number directly, as RON only tells a name apart from
+/// other values when it's told to expect one
+fn number_colour_columns(ron: &str) -> String {
+    let mut numbered = String::with_capacity(ron.len());
+    let mut chars = ron.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            numbered.push(c);
+            match c {
+                '\\' => numbered.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let mut name = String::from(c);
+            while let Some(&next) = chars
+                .peek()
+                .filter(|&&next| next.is_alphanumeric() || next == '_')
+            {
+                name.push(next);
+                chars.next();
+            }
+            match COLUMN_THEMES[..4]
+                .iter()
+                .position(|(colour, _)| *colour == name)
+            {
+                Some(index) => numbered.push_str(&index.to_string()),
+                None => numbered.push_str(&name),
+            }
+        } else {
+            in_string = c == '"';
+            numbered.push(c);
+        }
+    }
+    numbered
+}
+
+/// How opaque column shapes are, so a little of the target shows through them
+const SHAPE_ALPHA: f32 = 0.8;
+
+/// A shape that tells a column apart without relying on its colour
+#[derive(Clone, Copy)]
+enum ColumnShape {
+    Square,
+    Diamond,
+    Plus,
+    Cross,
+    HorizontalBar,
+    VerticalBar,
+}
+
+impl ColumnShape {
+    /// Every shape, in the order they're given to columns
+    const ALL: [ColumnShape; 6] = [
+        ColumnShape::Square,
+        ColumnShape::Diamond,
+        ColumnShape::Plus,
+        ColumnShape::Cross,
+        ColumnShape::HorizontalBar,
+        ColumnShape::VerticalBar,
+    ];
+
+    /// The size and rotation of each rectangle the shape is built from,
+    /// in the units of a target's 200 pixel sprite
+    fn parts(self) -> &'static [(Vec2, f32)] {
+        use std::f32::consts::FRAC_PI_4;
+
+        const SQUARE: Vec2 = Vec2::new(70.0, 70.0);
+        const WIDE: Vec2 = Vec2::new(110.0, 30.0);
+        const TALL: Vec2 = Vec2::new(30.0, 110.0);
+
+        match self {
+            ColumnShape::Square => &[(SQUARE, 0.0)],
+            ColumnShape::Diamond => &[(SQUARE, FRAC_PI_4)],
+            ColumnShape::Plus => &[(WIDE, 0.0), (TALL, 0.0)],
+            ColumnShape::Cross => &[(WIDE, FRAC_PI_4), (TALL, FRAC_PI_4)],
+            ColumnShape::HorizontalBar => &[(WIDE, 0.0)],
+            ColumnShape::VerticalBar => &[(TALL, 0.0)],
+        }
+    }
+
+    /// Spawns the shape as children of a target or crosshair, drawn on top of it
+    fn spawn(self, parent: &mut ChildBuilder) {
+        for &(size, rotation) in self.parts() {
+            parent
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 1.0, 1.0, SHAPE_ALPHA),
+                        custom_size: Some(size),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 0.1)
+                        .with_rotation(Quat::from_rotation_z(rotation)),
+                    ..Default::default()
+                })
+                .insert(Game)
+                .insert(BaseAlpha(SHAPE_ALPHA));
+        }
+    }
+}
+
+/// The file the player's column colours are loaded from
+const THEME_FILE: &str = "theme.ron";
+
+/// The colour of each column, from left to right, which players can change in [`THEME_FILE`].
+/// Columns past the end of the list reuse its colours from the start
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Theme {
+    columns: Vec<[f32; 3]>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            columns: COLUMN_THEMES
+                .iter()
+                .map(|(_, color)| [color.r(), color.g(), color.b()])
+                .collect(),
+        }
+    }
+}
+
+impl Theme {
+    /// The colour a column's lane, tails and particles are drawn in
+    fn color(&self, column: Column) -> Color {
+        match self.columns.len() {
+            0 => COLUMN_THEMES[usize::from(column.0) % COLUMN_THEMES.len()].1,
+            len => {
+                let [r, g, b] = self.columns[usize::from(column.0) % len];
+                Color::rgb(r, g, b)
+            }
+        }
+    }
+
+    /// The tint that turns a column's target and crosshair textures into its colour.
+    ///
+    /// The textures are already drawn in the default colours, so each channel is scaled by how
+    /// far the theme's colour is from the texture's. The default theme leaves them untinted
+    fn tint(&self, column: Column) -> Color {
+        let texture = COLUMN_THEMES[column.sprite_index()].1;
+        let color = self.color(column);
+        Color::rgb(
+            color.r() / texture.r().max(f32::EPSILON),
+            color.g() / texture.g().max(f32::EPSILON),
+            color.b() / texture.b().max(f32::EPSILON),
+        )
+    }
+}
+
+impl std::fmt::Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match COLUMN_THEMES.get(usize::from(self.0)) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "Column {}", self.0 + 1),
+        }
+    }
+}
+
+/// The file the player's key bindings are saved to
+const KEY_BINDINGS_FILE: &str = "key_bindings.ron";
+
+/// How many keys each column can be bound to in the controls menu
+const KEYS_PER_COLUMN: usize = 2;
+
+/// A control other than the columns, which can be bound to a key of the player's choosing
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+enum Action {
+    /// Leaves the current menu or run, or quits the game from the start menu
+    Menu,
+    /// Pauses and unpauses the game
+    Pause,
+    /// Starts the run over from the beginning
+    Restart,
+    /// Mutes and unmutes everything
+    Mute,
+    /// Shows and hides the timing statistics during a run
+    TimingStats,
+}
+
+impl Action {
+    /// Every action, in the order they're listed in the controls menu
+    const ALL: [Action; 5] = [
+        Action::Menu,
+        Action::Pause,
+        Action::Restart,
+        Action::Mute,
+        Action::TimingStats,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Action::Menu => "Menu",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+            Action::Mute => "Mute",
+            Action::TimingStats => "Timing Stats",
+        }
+    }
+
+    const fn default_key(self) -> KeyCode {
+        match self {
+            Action::Menu => KeyCode::Escape,
+            Action::Pause => KeyCode::P,
+            Action::Restart => KeyCode::R,
+            Action::Mute => KeyCode::M,
+            Action::TimingStats => KeyCode::F4,
+        }
+    }
+}
+
+/// Something that can be rebound in the controls menu
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Binding {
+    /// One of a column's keys, by its place in [`KeyBindings::keys`]
+    Column(Column, usize),
+    Action(Action),
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Binding::Column(column, 0) => write!(f, "{}", column),
+            Binding::Column(column, slot) => write!(f, "{} ({})", column, slot + 1),
+            Binding::Action(action) => write!(f, "{}", action.name()),
+        }
+    }
+}
+
+/// The keys and gamepad buttons that shoot each column's targets, and the keys for every [`Action`]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct KeyBindings {
+    columns: HashMap<Column, Vec<KeyCode>>,
+    gamepad: HashMap<Column, Vec<GamepadButtonType>>,
+    actions: HashMap<Action, KeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            columns: HashMap::from([
+                (Column(0), vec![KeyCode::A, KeyCode::H]),
+                (Column(1), vec![KeyCode::S, KeyCode::J]),
+                (Column(2), vec![KeyCode::D, KeyCode::K]),
+                (Column(3), vec![KeyCode::F, KeyCode::L]),
+                (Column(4), vec![KeyCode::G, KeyCode::Semicolon]),
+                (Column(5), vec![KeyCode::B, KeyCode::Apostrophe]),
+            ]),
+            gamepad: HashMap::from([
+                (
+                    Column(0),
+                    vec![GamepadButtonType::West, GamepadButtonType::DPadLeft],
+                ),
+                (
+                    Column(1),
+                    vec![GamepadButtonType::South, GamepadButtonType::DPadDown],
+                ),
+                (
+                    Column(2),
+                    vec![GamepadButtonType::North, GamepadButtonType::DPadUp],
+                ),
+                (
+                    Column(3),
+                    vec![GamepadButtonType::East, GamepadButtonType::DPadRight],
+                ),
+                (
+                    Column(4),
+                    vec![
+                        GamepadButtonType::LeftTrigger,
+                        GamepadButtonType::LeftTrigger2,
+                    ],
+                ),
+                (
+                    Column(5),
+                    vec![
+                        GamepadButtonType::RightTrigger,
+                        GamepadButtonType::RightTrigger2,
+                    ],
+                ),
+            ]),
+            actions: Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_key()))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Binds the default keys and buttons for any columns that have none, as bindings saved
+    /// before there could be more than four columns only have the first four.
+    /// Defaults that are already bound to something else are left out
+    fn with_default_columns(mut self) -> Self {
+        let defaults = Self::default();
+        for (column, keys) in defaults.columns {
+            if !self.columns.contains_key(&column) {
+                let free = keys
+                    .into_iter()
+                    .filter(|&key| self.binding_for(key).is_none())
+                    .collect();
+                self.columns.insert(column, free);
+            }
+        }
+        for (column, buttons) in defaults.gamepad {
+            if !self.gamepad.contains_key(&column) {
+                let free = buttons
+                    .into_iter()
+                    .filter(|&button| self.button_binding_for(button).is_none())
+                    .collect();
+                self.gamepad.insert(column, free);
+            }
+        }
+        self
+    }
+
+    /// The keys bound to a column
+    fn keys(&self, column: Column) -> &[KeyCode] {
+        self.columns.get(&column).map_or(&[], Vec::as_slice)
+    }
+
+    /// The gamepad buttons bound to a column
+    fn buttons(&self, column: Column) -> &[GamepadButtonType] {
+        self.gamepad.get(&column).map_or(&[], Vec::as_slice)
+    }
+
+    /// The column a key is bound to, if any
+    fn column_for(&self, key: KeyCode) -> Option<Column> {
+        self.columns
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(column, _)| *column)
+    }
+
+    /// The key bound to an action
+    fn action_key(&self, action: Action) -> KeyCode {
+        self.actions
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_key())
+    }
+
+    /// The action a key is bound to, if any
+    fn action_for(&self, key: KeyCode) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|&action| self.action_key(action) == key)
+    }
+
+    /// Whether an action's key was pressed this frame
+    fn just_pressed(&self, input: &Input<KeyCode>, action: Action) -> bool {
+        input.just_pressed(self.action_key(action))
+    }
+
+    /// Whatever a key is bound to, if anything
+    fn binding_for(&self, key: KeyCode) -> Option<Binding> {
+        self.columns
+            .iter()
+            .find_map(|(column, keys)| {
+                let slot = keys.iter().position(|&bound| bound == key)?;
+                Some(Binding::Column(*column, slot))
+            })
+            .or_else(|| self.action_for(key).map(Binding::Action))
+    }
+
+    /// Binds a key to a column or action, replacing only the key it had in that place
+    fn bind(&mut self, binding: Binding, key: KeyCode) {
+        match binding {
+            Binding::Column(column, slot) => {
+                let keys = self.columns.entry(column).or_default();
+                match keys.get_mut(slot) {
+                    Some(bound) => *bound = key,
+                    None => keys.push(key),
+                }
+            }
+            Binding::Action(action) => {
+                self.actions.insert(action, key);
+            }
+        }
+    }
+
+    /// The column and place a gamepad button is bound to, if any
+    fn button_binding_for(&self, button: GamepadButtonType) -> Option<Binding> {
+        self.gamepad.iter().find_map(|(column, buttons)| {
+            let slot = buttons.iter().position(|&bound| bound == button)?;
+            Some(Binding::Column(*column, slot))
+        })
+    }
+
+    /// Binds a gamepad button to one of a column's places, replacing only the button it had there
+    fn bind_button(&mut self, column: Column, slot: usize, button: GamepadButtonType) {
+        let buttons = self.gamepad.entry(column).or_default();
+        match buttons.get_mut(slot) {
+            Some(bound) => *bound = button,
+            None => buttons.push(button),
+        }
+    }
+
+    /// A human readable description of what's bound to a column or action
+    fn binding_label(&self, binding: Binding) -> String {
+        match binding {
+            Binding::Column(column, slot) => self.label(column, slot),
+            Binding::Action(action) => format!("{:?}", self.action_key(action)),
+        }
+    }
+
+    /// A human readable list of the keys bound to a column, e.g. "A/H"
+    fn keys_label(&self, column: Column) -> String {
+        self.keys(column)
+            .iter()
+            .map(|key| format!("{:?}", key))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// A human readable description of the key and button in one of a column's places,
+    /// e.g. "A, Pad: West"
+    fn label(&self, column: Column, slot: usize) -> String {
+        let key = self
+            .keys(column)
+            .get(slot)
+            .map_or_else(|| "None".to_string(), |key| format!("{:?}", key));
+        let button = self
+            .buttons(column)
+            .get(slot)
+            .map_or_else(|| "None".to_string(), |button| format!("{:?}", button));
+        format!("{}, Pad: {}", key, button)
+    }
+}
+
+/// Reads the keyboard, every connected gamepad, and the mouse and touchscreen if they're turned on,
+/// to tell when a column is being pressed
+#[derive(SystemParam)]
+struct ColumnInput<'w, 's> {
+    keyboard: Res<'w, Input<KeyCode>>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>,
+    key_bindings: Res<'w, KeyBindings>,
+    mouse_buttons: Res<'w, Input<MouseButton>>,
+    touches: Res<'w, Touches>,
+    windows: Res<'w, Windows>,
+    camera:
+        Query<'w, 's, (&'static GlobalTransform, &'static OrthographicProjection), With<Camera2d>>,
+    config: Res<'w, GameConfig>,
+    pointer_input: Res<'w, PointerInput>,
+    replay: Option<Res<'w, ReplayPlayback>>,
+}
+
+impl<'w, 's> ColumnInput<'w, 's> {
+    /// Every bound button on every connected gamepad for a column.
+    ///
+    /// Only connected gamepads are checked, so a pad that is unplugged mid-press can't leave
+    /// its buttons stuck down, and the keyboard keeps working on its own.
+    fn gamepad_buttons(&self, column: Column) -> impl Iterator<Item = GamepadButton> + '_ {
+        self.gamepads.iter().flat_map(move |&gamepad| {
+            self.key_bindings
+                .buttons(column)
+                .iter()
+                .map(move |&button_type| GamepadButton::new(gamepad, button_type))
+        })
+    }
+
+    /// The column under a position in the window, if any.
+    /// Only the horizontal position matters, so anywhere along a lane counts
+    fn column_at(&self, position: Vec2) -> Option<Column> {
+        let window = self.windows.get_primary()?;
+        let (transform, projection) = self.camera.get_single().ok()?;
+        // The camera is zoomed to fit the window, so undo that to find the position in the game
+        let from_centre = position - Vec2::new(window.width(), window.height()) / 2.0;
+        let x = transform.translation().x + from_centre.x * projection.scale;
+        self.config.column_at(x)
+    }
+
+    /// The column under the mouse cursor, if any
+    fn cursor_column(&self) -> Option<Column> {
+        let cursor = self.windows.get_primary()?.cursor_position()?;
+        self.column_at(cursor)
+    }
+
+    /// Whether a column was clicked or touched this frame.
+    /// Every touch counts separately, so chords can be played with several fingers
+    fn pointer_just_pressed(&self, column: Column) -> bool {
+        if !self.pointer_input.0 {
+            return false;
+        }
+        let clicked = self.mouse_buttons.just_pressed(MouseButton::Left)
+            && self.cursor_column() == Some(column);
+        clicked
+            || self
+                .touches
+                .iter_just_pressed()
+                .any(|touch| self.column_at(touch.position()) == Some(column))
+    }
+
+    /// Whether a column is being clicked or touched
+    fn pointer_pressed(&self, column: Column) -> bool {
+        if !self.pointer_input.0 {
+            return false;
+        }
+        let clicked =
+            self.mouse_buttons.pressed(MouseButton::Left) && self.cursor_column() == Some(column);
+        clicked
+            || self
+                .touches
+                .iter()
+                .any(|touch| self.column_at(touch.position()) == Some(column))
+    }
+
+    /// Whether any of a column's keys or buttons were pressed this frame.
+    /// While a replay is being watched, only its presses count
+    fn just_pressed(&self, column: Column) -> bool {
+        if let Some(replay) = &self.replay {
+            return replay.just_pressed.contains(&column);
+        }
+
+        self.keyboard
+            .any_just_pressed(self.key_bindings.keys(column).iter().copied())
+            || self
+                .gamepad_buttons
+                .any_just_pressed(self.gamepad_buttons(column))
+            || self.pointer_just_pressed(column)
+    }
+
+    /// Whether any of a column's keys or buttons are being held down
+    fn pressed(&self, column: Column) -> bool {
+        if let Some(replay) = &self.replay {
+            return replay.held.contains(&column);
+        }
+
+        self.keyboard
+            .any_pressed(self.key_bindings.keys(column).iter().copied())
+            || self
+                .gamepad_buttons
+                .any_pressed(self.gamepad_buttons(column))
+            || self.pointer_pressed(column)
+    }
+}
+
+/// The columns whose current press has already been used to judge a target.
+///
+/// A column is freed up again when it's pressed anew or released,
+/// so one physical press can never hit more than one target
+#[derive(Default)]
+struct ColumnInputState {
+    consumed: HashSet<Column>,
+    /// The target each column's press is charging, to be judged when it's let go.
+    /// Only used with [`ChargeScoring`] on
+    charging: HashMap<Column, Entity>,
+}
+
+/// The column or action waiting for a new key in the controls menu, if any
+#[derive(Default)]
+struct Rebinding(Option<Binding>);
+
+/// The button picked with the arrow keys, which Enter clicks, if any
+#[derive(Default)]
+struct FocusedButton(Option<Entity>);
+
+#[derive(Default)]
+struct MenuAssetHandles {
+    logo: Option<Handle<Image>>,
+    font: Option<Handle<Font>>,
+}
+
+/// Every asset the game can't run without, which are waited on before leaving the loading state
+#[derive(Default)]
+struct RequiredAssets(Vec<HandleUntyped>);
+
+/// The paths of any required assets that failed to load
+#[derive(Default)]
+struct FailedAssets(Vec<String>);
+
+#[derive(Default)]
+struct TextureAtlasHandles {
+    crosshairs: Option<Handle<TextureAtlas>>,
+    targets: Option<Handle<TextureAtlas>>,
+}
+
+/// The sound each column makes when hit, from left to right
+#[derive(Default)]
+struct NoteAudioHandles(Vec<Handle<AudioSource>>);
+
+impl NoteAudioHandles {
+    /// The sound a column makes, reusing them in order if there are more columns than sounds
+    fn get(&self, column: Column) -> Option<&Handle<AudioSource>> {
+        self.0
+            .get(usize::from(column.index()) % self.0.len().max(1))
+    }
+}
+
+/// How fast targets fall and how often they spawn
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// How fast targets fall, in pixels per second
+    const fn scroll_speed(self) -> f32 {
+        match self {
+            Difficulty::Easy => 120.0,
+            Difficulty::Normal => 150.0,
+            Difficulty::Hard => 200.0,
+        }
+    }
+
+    /// How often a target is spawned when not playing a chart
+    const fn spawn_interval(self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_millis(500),
+            Difficulty::Normal => Duration::from_millis(350),
+            Difficulty::Hard => Duration::from_millis(250),
+        }
+    }
+}
+
+/// The environment variable that fixes the seed used for random spawning
+const SEED_VAR: &str = "RHYTHM_GAME_SEED";
+
+/// The random number generator used for spawning, along with the seed it started from
+/// so that a run can be reproduced
+struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    fn from_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Uses the seed from the environment if one is set, otherwise picks one at random
+    fn from_env_or_entropy() -> Self {
+        let seed = match std::env::var(SEED_VAR).ok().map(|seed| seed.parse()) {
+            Some(Ok(seed)) => seed,
+            Some(Err(err)) => {
+                warn!("Ignoring invalid {}: {}", SEED_VAR, err);
+                rand::random()
+            }
+            None => rand::random(),
+        };
+        Self::from_seed(seed)
+    }
+}
+
+/// The layout of the playfield and the rules of the game, kept in one place.
+///
+/// How fast targets fall and how often they spawn are chosen by the [`Difficulty`] instead
+struct GameConfig {
+    /// How many columns there are
+    lanes: u8,
+    /// The horizontal distance between neighbouring columns
+    column_spacing: f32,
+    /// The height targets spawn at
+    spawn_y: f32,
+    /// The height of the crosshairs, where targets should be hit
+    hit_y: f32,
+    /// Targets above this height are too far away to be shot, with the normal [`HitWindow`]
+    hit_threshold_y: f32,
+    /// Targets below this height have gone too far past the crosshair to be shot,
+    /// with the normal [`HitWindow`]. This is the bottom of the good window
+    late_hit_y: f32,
+    /// Targets that fall below this height have been missed.
+    /// This is kept below the late bound, so a late hit is always judged before the miss
+    despawn_y: f32,
+    /// The scale targets and crosshairs are drawn at
+    target_scale: f32,
+    /// Every time this many targets have been hit in a row, the streak is celebrated
+    combo_milestone: u32,
+    /// The least time a chart's clock runs before its song starts, in milliseconds.
+    /// It runs for longer if the first notes need more time to fall to the crosshairs
+    lead_in_ms: u32,
+    /// Whether targets rise instead of falling.
+    /// All of the heights above are for falling targets, and are mirrored with [`GameConfig::flip`]
+    upscroll: bool,
+    /// Whether each column's targets and crosshair have a [`ColumnShape`] drawn over them
+    column_shapes: bool,
+    /// The heights over which targets fade in with [`Modifier::Hidden`],
+    /// from invisible at the first to fully visible at the second
+    hidden_fade: (f32, f32),
+    /// The heights over which targets fade out with [`Modifier::Sudden`],
+    /// from fully visible at the first to invisible at the second
+    sudden_fade: (f32, f32),
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            lanes: 4,
+            column_spacing: 90.0,
+            spawn_y: 400.0,
+            hit_y: -305.0,
+            hit_threshold_y: -280.0,
+            late_hit_y: -330.0,
+            despawn_y: -350.0,
+            target_scale: 0.3,
+            combo_milestone: 25,
+            lead_in_ms: 1000,
+            upscroll: false,
+            column_shapes: false,
+            hidden_fade: (300.0, 100.0),
+            sudden_fade: (50.0, -150.0),
+        }
+    }
+}
+
+impl GameConfig {
+    /// Moves the crosshairs to a new height, along with the heights targets are judged and missed
+    /// at, so the hit window stays the same around them
+    fn move_judgment_line(&mut self, hit_y: f32) {
+        let shift = hit_y - self.hit_y;
+        self.hit_y += shift;
+        self.hit_threshold_y += shift;
+        self.late_hit_y += shift;
+        self.despawn_y += shift;
+    }
+
+    /// Every column, from left to right
+    fn columns(&self) -> impl Iterator<Item = Column> {
+        (0..self.lanes).map(Column)
+    }
+
+    /// How long targets take to fall from where they spawn to the crosshairs, in milliseconds
+    fn travel_ms(&self, scroll_speed: f32) -> f32 {
+        (self.spawn_y - self.hit_y) / scroll_speed * 1000.0
+    }
+
+    /// Picks one of the columns at random
+    fn random_column(&self, rng: &mut impl Rng) -> Column {
+        Column(rng.gen_range(0..self.lanes))
+    }
+
+    /// Converts a height between the falling layout and the actual layout, in either direction.
+    /// This is a no-op unless upscroll is on, in which case everything is mirrored vertically
+    fn flip(&self, y: f32) -> f32 {
+        if self.upscroll {
+            -y
+        } else {
+            y
+        }
+    }
+
+    /// The anchor that makes a sprite trail behind targets, away from the crosshairs
+    fn trailing_anchor(&self) -> Anchor {
+        if self.upscroll {
+            Anchor::TopCenter
+        } else {
+            Anchor::BottomCenter
+        }
+    }
+
+    /// The horizontal position of a column's targets, crosshair and lane,
+    /// keeping the columns centred on the screen
+    fn column_x(&self, column: Column) -> f32 {
+        (f32::from(column.index()) - f32::from(self.lanes - 1) / 2.0) * self.column_spacing
+    }
+
+    /// The column whose lane covers a horizontal position, if any.
+    /// This is the inverse of [`GameConfig::column_x`], with each lane as wide as the spacing
+    fn column_at(&self, x: f32) -> Option<Column> {
+        let index = (x / self.column_spacing + f32::from(self.lanes - 1) / 2.0).round();
+        (0.0..f32::from(self.lanes))
+            .contains(&index)
+            .then_some(Column(index as u8))
+    }
+}
+
+/// Counts down to the next randomly spawned target
+struct SpawnTimer(Timer);
+
+/// How many targets have to be hit in a row before [`Modifier::Adaptive`] starts speeding up
+const ADAPTIVE_STREAK: u32 = 10;
+
+/// How much the spawn interval is multiplied by for every hit after [`ADAPTIVE_STREAK`]
+const ADAPTIVE_SPEED_UP: f32 = 0.98;
+
+/// How much the spawn interval is multiplied by for every miss
+const ADAPTIVE_EASE_OFF: f32 = 1.1;
+
+/// The shortest and longest the spawn interval can be with [`Modifier::Adaptive`],
+/// as multiples of the difficulty's own interval
+const ADAPTIVE_RANGE: (f32, f32) = (0.5, 1.5);
+
+/// How much faster an endless run gets for every minute it lasts
+const ENDLESS_SPEED_UP_PER_MINUTE: f32 = 0.25;
+
+/// The fastest an endless run gets, as a multiple of its starting pace
+const ENDLESS_MAX_SPEED_UP: f32 = 2.5;
+
+/// Whether random runs are endless, getting faster the longer the player survives.
+/// Like practice mode, this is chosen for a session rather than saved
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct EndlessMode(bool);
+
+/// How long the current endless run has lasted, not counting the countdown or pauses
+#[derive(Default)]
+struct SurvivalTime(Duration);
+
+impl SurvivalTime {
+    /// How many times faster targets fall and spawn than at the start of the run
+    fn speed_up(&self) -> f32 {
+        (1.0 + self.0.as_secs_f32() / 60.0 * ENDLESS_SPEED_UP_PER_MINUTE).min(ENDLESS_MAX_SPEED_UP)
+    }
+}
+
+/// A survival time in minutes and seconds, e.g. "1:23.4"
+fn format_survival_time(time: Duration) -> String {
+    let seconds = time.as_secs_f32();
+    format!("{}:{:04.1}", (seconds / 60.0) as u32, seconds % 60.0)
+}
+
+/// How fast targets are falling right now, which speeds up as an endless run goes on
+#[derive(SystemParam)]
+struct FallSpeed<'w, 's> {
+    difficulty: Res<'w, Difficulty>,
+    scroll_speed: Res<'w, ScrollSpeed>,
+    config: Res<'w, GameConfig>,
+    survival_time: Res<'w, SurvivalTime>,
+    time: Res<'w, Time>,
+    chart: Res<'w, SelectedChart>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl FallSpeed<'_, '_> {
+    /// The speed targets fall at, in pixels per second
+    fn get(&self) -> f32 {
+        self.scroll_speed.apply(*self.difficulty, &self.config) * self.survival_time.speed_up()
+    }
+
+    /// How long targets fall for this frame, which is capped at [`MAX_FRAME_DELTA`] in random runs
+    fn frame(&self) -> Duration {
+        match self.chart.0 {
+            Some(_) => self.time.delta(),
+            None => self.time.delta().min(MAX_FRAME_DELTA),
+        }
+    }
+}
+
+/// How many numbers are counted down before the game starts
+const COUNTDOWN_STEPS: u32 = 3;
+
+/// How long each number of the countdown is shown for
+const COUNTDOWN_STEP: Duration = Duration::from_secs(1);
+
+/// How long "Go!" is shown for after the countdown
+const COUNTDOWN_GO: Duration = Duration::from_millis(500);
+
+/// Holds the game back while it counts down to the start, and is removed once it's over
+struct Countdown {
+    timer: Timer,
+    /// Whether the game is waiting for the player to press a column instead of counting down
+    waiting_for_input: bool,
+}
+
+impl Default for Countdown {
+    fn default() -> Self {
+        Self {
+            timer: Timer::new(COUNTDOWN_STEP * COUNTDOWN_STEPS + COUNTDOWN_GO, false),
+            waiting_for_input: false,
+        }
+    }
+}
+
+impl Countdown {
+    /// The text to show at this point in the countdown
+    fn label(&self) -> String {
+        if self.waiting_for_input {
+            return "Ready?".into();
+        }
+
+        let step = (self.timer.elapsed().as_secs_f32() / COUNTDOWN_STEP.as_secs_f32()) as u32;
+        if step < COUNTDOWN_STEPS {
+            (COUNTDOWN_STEPS - step).to_string()
+        } else {
+            "Go!".into()
+        }
+    }
+
+    /// Smaller text shown under the label, telling the player what to do
+    fn hint(&self) -> &'static str {
+        if self.waiting_for_input {
+            "\nPress any column to start"
+        } else {
+            ""
+        }
+    }
+}
+
+/// The file the start mode setting is saved to
+const START_MODE_FILE: &str = "start_mode.ron";
+
+/// How a run begins once it's been set up
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+enum StartMode {
+    /// Count down from [`COUNTDOWN_STEPS`] and then start
+    #[default]
+    Countdown,
+    /// Wait for the player to press one of the columns, then start straight away
+    PressToStart,
+}
+
+impl StartMode {
+    /// The countdown that holds the game back until the run should start
+    fn countdown(self) -> Countdown {
+        Countdown {
+            waiting_for_input: self == StartMode::PressToStart,
+            ..Default::default()
+        }
+    }
+
+    /// A human readable description of the setting
+    fn label(self) -> &'static str {
+        match self {
+            StartMode::Countdown => "Start: Countdown",
+            StartMode::PressToStart => "Start: Press to start",
+        }
+    }
+}
+
+/// How far a target can be from the crosshair, in pixels, and still be a perfect hit
+const PERFECT_WINDOW: f32 = 10.0;
+
+/// How far a target can be from the crosshair, in pixels, and still be a good hit
+const GOOD_WINDOW: f32 = 25.0;
+
+/// The file the hit window setting is saved to
+const HIT_WINDOW_FILE: &str = "hit_window.ron";
+
+/// How forgiving the timing is, which scales how far from the crosshair targets can be hit,
+/// and the windows for each [`Judgment`] along with it
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+enum HitWindow {
+    Strict,
+    #[default]
+    Normal,
+    Relaxed,
+}
+
+impl HitWindow {
+    /// How much bigger than normal the hit windows are
+    fn scale(self) -> f32 {
+        match self {
+            HitWindow::Strict => 0.75,
+            HitWindow::Normal => 1.0,
+            HitWindow::Relaxed => 1.5,
+        }
+    }
+
+    /// The next setting along, which the settings button cycles through
+    fn next(self) -> Self {
+        match self {
+            HitWindow::Strict => HitWindow::Normal,
+            HitWindow::Normal => HitWindow::Relaxed,
+            HitWindow::Relaxed => HitWindow::Strict,
+        }
+    }
+
+    /// How far a target can be from the crosshair, in pixels, and still be a perfect hit
+    fn perfect(self) -> f32 {
+        PERFECT_WINDOW * self.scale()
+    }
+
+    /// How far a target can be from the crosshair, in pixels, and still be a good hit
+    fn good(self) -> f32 {
+        GOOD_WINDOW * self.scale()
+    }
+
+    /// Targets above this height are too far away to be shot.
+    /// However wide the window is, targets that are still off the top of the screen can't be shot
+    fn threshold_y(self, config: &GameConfig) -> f32 {
+        let threshold_y = config.hit_y + (config.hit_threshold_y - config.hit_y) * self.scale();
+        threshold_y.min(PLAYFIELD_HEIGHT / 2.0)
+    }
+
+    /// Targets below this height have gone too far past the crosshair to be shot.
+    /// However wide the window is, targets can still be shot right up until they're missed
+    fn late_y(self, config: &GameConfig) -> f32 {
+        let late_y = config.hit_y - (config.hit_y - config.late_hit_y) * self.scale();
+        late_y.max(config.despawn_y)
+    }
+
+    /// A human readable description of the setting
+    fn label(self) -> &'static str {
+        match self {
+            HitWindow::Strict => "Hit window: Strict",
+            HitWindow::Normal => "Hit window: Normal",
+            HitWindow::Relaxed => "Hit window: Relaxed",
+        }
+    }
+}
+
+/// The file the frame rate setting is saved to
+const FRAME_RATE_FILE: &str = "frame_rate.ron";
+
+/// The caps the frame rate setting cycles through, in frames per second
+const FRAME_RATE_CAPS: [u32; 3] = [30, 60, 144];
+
+/// How the frame rate is kept down, so the game doesn't draw more frames than it needs to
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+enum FrameRate {
+    /// Wait for the display before drawing each frame
+    #[default]
+    VSync,
+    /// Draw frames as soon as they're ready, but no more than this many a second
+    Capped(u32),
+    /// Draw frames as soon as they're ready
+    Uncapped,
+}
+
+impl FrameRate {
+    /// The next setting along, which the settings button cycles through
+    fn next(self) -> Self {
+        match self {
+            FrameRate::VSync => FrameRate::Capped(FRAME_RATE_CAPS[0]),
+            FrameRate::Capped(fps) => match FRAME_RATE_CAPS.iter().find(|&&cap| cap > fps) {
+                Some(&cap) => FrameRate::Capped(cap),
+                None => FrameRate::Uncapped,
+            },
+            FrameRate::Uncapped => FrameRate::VSync,
+        }
+    }
+
+    fn present_mode(self) -> PresentMode {
+        match self {
+            FrameRate::VSync => PresentMode::AutoVsync,
+            FrameRate::Capped(_) | FrameRate::Uncapped => PresentMode::AutoNoVsync,
+        }
+    }
+
+    /// The shortest each frame can take, if the frame rate is capped
+    fn frame_time(self) -> Option<Duration> {
+        match self {
+            FrameRate::Capped(fps) if fps > 0 => Some(Duration::from_secs(1) / fps),
+            _ => None,
+        }
+    }
+
+    /// A human readable description of the setting
+    fn label(self) -> String {
+        match self {
+            FrameRate::VSync => "Frame rate: VSync".into(),
+            FrameRate::Capped(fps) => format!("Frame rate: {} FPS", fps),
+            FrameRate::Uncapped => "Frame rate: Uncapped".into(),
+        }
+    }
+}
+
+/// How accurately a target was hit
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Judgment {
+    Perfect,
+    Good,
+    Miss,
+}
+
+impl Judgment {
+    /// Judges a hit by how far the target was from the crosshair
+    fn from_distance(distance: f32, hit_window: HitWindow) -> Self {
+        let distance = distance.abs();
+        if distance <= hit_window.perfect() {
+            Judgment::Perfect
+        } else if distance <= hit_window.good() {
+            Judgment::Good
+        } else {
+            Judgment::Miss
+        }
+    }
+
+    /// How early (negative) or late (positive) a press was, in milliseconds,
+    /// from how far its target was above (positive) or below the crosshair
+    fn timing_error_ms(distance: f32, scroll_speed: f32) -> f32 {
+        -distance / scroll_speed.max(f32::EPSILON) * 1000.0
+    }
+
+    /// The text shown above the crosshair for this judgment
+    const fn label(self) -> &'static str {
+        match self {
+            Judgment::Perfect => "Perfect",
+            Judgment::Good => "Good",
+            Judgment::Miss => "Miss",
+        }
+    }
+
+    /// The colour this judgment's label is shown in
+    const fn color(self) -> Color {
+        match self {
+            Judgment::Perfect => Color::rgb(1.0, 0.85, 0.2),
+            Judgment::Good => Color::rgb(0.6, 1.0, 0.6),
+            Judgment::Miss => Color::RED,
+        }
+    }
+
+    /// How many particles burst out of the crosshair for a hit with this judgment
+    const fn particle_count(self) -> usize {
+        match self {
+            Judgment::Perfect => 12,
+            Judgment::Good => 6,
+            Judgment::Miss => 0,
+        }
+    }
+}
+
+/// The sound played for each [`Announcement`]
+#[derive(Default)]
+struct AnnouncementHandles(HashMap<Announcement, Handle<AudioSource>>);
+
+#[derive(Default)]
+struct SoundEffectHandles {
+    tick: Option<Handle<AudioSource>>,
+    miss: Option<Handle<AudioSource>>,
+}
+
+/// The track looped in the menus, and the sink it's playing through while it is
+#[derive(Default)]
+struct MenuMusic {
+    track: Option<Handle<AudioSource>>,
+    sink: Option<Handle<AudioSink>>,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct Scoreboard {
+    pub score: i32,
+    pub combo: i32,
+    /// How many targets have been hit
+    pub hits: u32,
+    /// How many targets have been hit or missed
+    pub total: u32,
+    /// How many hits were judged perfect
+    pub perfects: u32,
+    /// How many hits were judged good
+    pub goods: u32,
+    /// How many targets have been missed
+    pub misses: u32,
+    /// How many targets have been hit in a row, which unlike the combo isn't capped
+    pub streak: u32,
+    /// The longest streak of hits in the game so far
+    pub max_combo: u32,
+}
+
+impl Scoreboard {
+    /// The percentage of targets that have been hit, or `None` if none have been judged yet
+    fn accuracy(&self) -> Option<f32> {
+        if self.total == 0 {
+            None
+        } else {
+            Some(self.hits as f32 / self.total as f32 * 100.0)
+        }
+    }
+
+    /// The accuracy formatted for display, e.g. "93.5%"
+    fn accuracy_label(&self) -> String {
+        match self.accuracy() {
+            Some(accuracy) => format!("{:.1}%", accuracy),
+            None => "—".into(),
+        }
+    }
+
+    fn hit(&mut self, judgment: Judgment, scoring: &ScoringConfig) {
+        if self.combo < scoring.combo_cap {
+            self.combo = (self.combo + scoring.combo_step).min(scoring.combo_cap);
+        }
+        self.score += self.combo * scoring.points(judgment);
+
+        match judgment {
+            Judgment::Perfect => self.perfects += 1,
+            Judgment::Good => self.goods += 1,
+            Judgment::Miss => {}
+        }
+        self.streak += 1;
+        self.max_combo = self.max_combo.max(self.streak);
+    }
+
+    fn miss(&mut self) {
+        let lost_combo = self.combo;
+        self.combo = 0;
+        self.score -= miss_penalty(lost_combo);
+
+        self.misses += 1;
+        self.streak = 0;
+    }
+}
+
+/// The milestone the streak passed on its way from `previous_streak` to `streak`, if any.
+///
+/// Several targets can be hit in one frame, so the streak can pass a milestone without landing
+/// on it. A miss resets the streak, which starts the count towards the next milestone over
+fn combo_milestone(previous_streak: u32, streak: u32, interval: u32) -> Option<u32> {
+    let interval = interval.max(1);
+    if streak / interval > previous_streak / interval {
+        Some(streak / interval * interval)
+    } else {
+        None
+    }
+}
+
+/// Formats a score to fit in the corner of the screen, e.g. "-12,345" or "1.5M".
+///
+/// Scores of a million or more are abbreviated, so the score never runs into the health bar
+fn format_score(score: i32) -> String {
+    if score.unsigned_abs() >= 1_000_000 {
+        return format!("{:.1}M", score as f32 / 1_000_000.0);
+    }
+
+    let digits = score.unsigned_abs().to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+
+    if score < 0 {
+        format!("-{}", formatted)
+    } else {
+        formatted
+    }
+}
+
+/// The file the scoring curve is read from, for players who want to change it
+const SCORING_FILE: &str = "scoring.ron";
+
+/// How hits are scored. Each hit adds to the combo, then is worth the combo
+/// times its judgment's points
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+struct ScoringConfig {
+    /// The highest the combo can go
+    combo_cap: i32,
+    /// How much each hit adds to the combo
+    combo_step: i32,
+    /// How many times the combo a perfect hit is worth
+    perfect_points: i32,
+    /// How many times the combo a good hit is worth
+    good_points: i32,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            combo_cap: 5,
+            combo_step: 1,
+            perfect_points: 2,
+            good_points: 1,
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// How many times the combo a hit with this judgment is worth
+    const fn points(&self, judgment: Judgment) -> i32 {
+        match judgment {
+            Judgment::Perfect => self.perfect_points,
+            Judgment::Good => self.good_points,
+            Judgment::Miss => 0,
+        }
+    }
+
+    /// The curve kept within [`SCORING_LIMIT`], with a combo that always climbs
+    fn clamped(self) -> Self {
+        let combo_cap = self.combo_cap.clamp(1, SCORING_LIMIT);
+        Self {
+            combo_cap,
+            combo_step: self.combo_step.clamp(1, combo_cap),
+            perfect_points: self.perfect_points.clamp(0, SCORING_LIMIT),
+            good_points: self.good_points.clamp(0, SCORING_LIMIT),
+        }
+    }
+}
+
+/// The highest a shared scoring curve's combo cap and points can go, so that a replay or run code
+/// from someone else can't make scores overflow
+const SCORING_LIMIT: i32 = 100;
+
+/// How many points a miss costs, given the combo it broke.
+///
+/// Dropping a long combo costs more than missing with no combo at all,
+/// so a miss always costs at least one point.
+const fn miss_penalty(lost_combo: i32) -> i32 {
+    lost_combo + 1
+}
+
+/// How much bigger the combo text gets when the combo increases
+const COMBO_POP_SCALE: f32 = 1.5;
+
+/// How quickly the combo text shrinks back to its resting size after popping
+const COMBO_POP_DECAY: f32 = 12.0;
+
+/// The file the player's best score is saved to
+const HIGH_SCORE_FILE: &str = "high_score.ron";
+
+/// The best score the player has ever achieved, and the longest they've survived an endless run
+#[derive(Default, Serialize, Deserialize)]
+struct HighScore {
+    pub best: i32,
+    #[serde(default)]
+    pub best_survival_ms: u64,
+}
+
+/// The amount of health the player starts each run with
+const MAX_HEALTH: i32 = 10;
+
+/// The amount of health lost when a target falls past the crosshairs
+const MISS_HEALTH_PENALTY: i32 = 1;
+
+/// The width of the health bar when the player is at full health
+const HEALTH_BAR_WIDTH: f32 = 150.0;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            current: MAX_HEALTH,
+            max: MAX_HEALTH,
+        }
+    }
+}
+
+impl Health {
+    /// The remaining health as a fraction of the maximum, between 0 and 1
+    fn fraction(&self) -> f32 {
+        (self.current as f32 / self.max as f32).clamp(0.0, 1.0)
+    }
+}
+
+/// The chart being played, or `None` to spawn targets at random
+#[derive(Default)]
+struct SelectedChart(Option<Handle<Beatmap>>);
+
+impl SelectedChart {
+    /// The asset path of the chart, e.g. "charts/example.ron", so it can be loaded again later
+    fn path(&self, asset_server: &AssetServer) -> Option<String> {
+        self.0.as_ref().and_then(|chart| {
+            asset_server
+                .get_handle_path(chart)
+                .map(|path| path.path().display().to_string())
+        })
+    }
+}
+
+/// How far the practice loop's bounds move with each press of their buttons, in milliseconds
+const PRACTICE_STEP_MS: u32 = 1000;
+
+/// Either end of the practice loop
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum PracticeBound {
+    Start,
+    End,
+}
+
+/// The section of a chart that practice mode loops, and whether practice mode is on.
+/// Like the difficulty, this is chosen for a session rather than saved
+struct PracticeLoop {
+    enabled: bool,
+    /// Where the loop starts, in milliseconds from the start of the chart
+    start_ms: u32,
+    /// Where the loop jumps back to the start, in milliseconds from the start of the chart
+    end_ms: u32,
+}
+
+impl Default for PracticeLoop {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start_ms: 0,
+            end_ms: 10_000,
+        }
+    }
+}
+
+impl PracticeLoop {
+    /// Moves one of the bounds, keeping the loop at least [`PRACTICE_STEP_MS`] long
+    fn adjust(&mut self, bound: PracticeBound, change: i64) {
+        match bound {
+            PracticeBound::Start => {
+                let start_ms = i64::from(self.start_ms) + change;
+                let latest = i64::from(self.end_ms - PRACTICE_STEP_MS);
+                self.start_ms = start_ms.clamp(0, latest) as u32;
+            }
+            PracticeBound::End => {
+                let end_ms = i64::from(self.end_ms) + change;
+                let earliest = i64::from(self.start_ms + PRACTICE_STEP_MS);
+                self.end_ms = end_ms.clamp(earliest, i64::from(u32::MAX)) as u32;
+            }
+        }
+    }
+
+    /// Keeps the loop within a chart that ends at `chart_end_ms`, shortening or moving it back
+    /// so it's still at least [`PRACTICE_STEP_MS`] long
+    fn clamp_to(&mut self, chart_end_ms: u32) {
+        let end_ms = (chart_end_ms + 1).max(PRACTICE_STEP_MS);
+        self.end_ms = self.end_ms.min(end_ms);
+        self.start_ms = self.start_ms.min(self.end_ms - PRACTICE_STEP_MS);
+    }
+
+    /// A human readable description of whether practice mode is on
+    fn label(&self) -> &'static str {
+        if self.enabled {
+            "Practice: On"
+        } else {
+            "Practice: Off"
+        }
+    }
+
+    /// A human readable description of one of the bounds, e.g. "Start: 4s"
+    fn bound_label(&self, bound: PracticeBound) -> String {
+        let ms = match bound {
+            PracticeBound::Start => self.start_ms,
+            PracticeBound::End => self.end_ms,
+        };
+        format!("{:?}: {}s", bound, ms / 1000)
+    }
+}
+
+/// How far through the selected chart the current run is
+#[derive(Default)]
+struct ChartPlayback {
+    /// Time since the chart started playing, including its lead-in
+    clock: Stopwatch,
+    /// How long the clock runs before the song starts, so the first notes have time to fall
+    lead_in: Duration,
+    next_note: usize,
+    /// Whether the chart has started, which waits for its song to load
+    started: bool,
+    song: Option<Handle<AudioSource>>,
+    song_sink: Option<Handle<AudioSink>>,
+    /// Whether the run was resumed part way through, so its song starts from wherever the chart is
+    resumed: bool,
+    /// Whether the song was paused by muting while the chart carried on without it,
+    /// so it has to start again from wherever the chart has got to
+    song_behind: bool,
+}
+
+impl ChartPlayback {
+    /// How far into the song the chart is, in milliseconds, which is negative during the lead-in
+    fn song_time_ms(&self) -> i64 {
+        self.clock.elapsed().as_millis() as i64 - self.lead_in.as_millis() as i64
+    }
+
+    /// The sink the chart's song is playing through, if it has one
+    fn song_sink<'a>(&self, audio_sinks: &'a Assets<AudioSink>) -> Option<&'a AudioSink> {
+        self.song_sink
+            .as_ref()
+            .and_then(|handle| audio_sinks.get(handle))
+    }
+}
+
+/// Plays a chart's song through the music channel
+#[derive(SystemParam)]
+struct SongPlayer<'w, 's> {
+    audio: Res<'w, Audio>,
+    sources: ResMut<'w, Assets<AudioSource>>,
+    sinks: Res<'w, Assets<AudioSink>>,
+    volume: Res<'w, Volume>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl SongPlayer<'_, '_> {
+    /// Starts a song if it has loaded, returning the sink it's playing through
+    fn play(&self, song: &Handle<AudioSource>) -> Option<Handle<AudioSink>> {
+        self.sources.get(song)?;
+        let settings = PlaybackSettings::ONCE.with_volume(self.volume.music_volume());
+        Some(
+            self.sinks
+                .get_handle(self.audio.play_with_settings(song.clone(), settings)),
+        )
+    }
+
+    /// Whether a song can be started part way through, which only WAV files can be
+    fn can_cut(&self, song: &Handle<AudioSource>) -> bool {
+        self.sources
+            .get(song)
+            .is_some_and(|source| wav::is_wav(&source.bytes))
+    }
+
+    /// Starts a song `start_ms` in, if it has loaded and can be cut.
+    /// Starting past the end of the song plays nothing, instead of starting it again
+    fn play_from(
+        &mut self,
+        song: &Handle<AudioSource>,
+        start_ms: u32,
+    ) -> Option<Handle<AudioSink>> {
+        if start_ms == 0 {
+            return self.play(song);
+        }
+        let clip = wav::clip(&self.sources.get(song)?.bytes, start_ms, u32::MAX)?;
+        let clip = self.sources.add(AudioSource { bytes: clip.into() });
+        self.play(&clip)
+    }
+}
+
+/// How long a song's preview lasts, including fading in and out
+const PREVIEW_LENGTH: Duration = Duration::from_secs(12);
+
+/// How long a song's preview takes to fade in or out
+const PREVIEW_FADE: Duration = Duration::from_millis(400);
+
+/// The preview of the hovered chart's song in the song select menu
+#[derive(Default)]
+struct SongPreview {
+    /// The chart being previewed, and the sink its song is playing through
+    playing: Option<(Handle<Beatmap>, Handle<AudioSink>)>,
+    /// How far the preview has faded in, from 0.0 to 1.0
+    fade: f32,
+    /// How long the preview has been playing
+    elapsed: Duration,
+    /// The song being loaded to be previewed, kept so it stays loaded until it's cut
+    loading: Option<Handle<AudioSource>>,
+    /// The clips cut from each chart's song, so they're only cut once
+    clips: HashMap<Handle<Beatmap>, Handle<AudioSource>>,
+}
+
+/// How many charts are listed on each page of the song select menu
+const CHARTS_PER_PAGE: usize = 5;
+
+/// Every chart in `assets/charts`, and the page of them being shown in the song select menu
+#[derive(Default)]
+struct ChartList {
+    charts: Vec<Handle<Beatmap>>,
+    page: usize,
+}
+
+impl ChartList {
+    fn page_count(&self) -> usize {
+        self.charts.len().div_ceil(CHARTS_PER_PAGE)
+    }
+
+    /// The chart shown in a slot on the current page, if there is one
+    fn chart_in_slot(&self, slot: usize) -> Option<&Handle<Beatmap>> {
+        self.charts.get(self.page * CHARTS_PER_PAGE + slot)
+    }
+}
+
+/// How the last run ended, which decides the game over menu's title
+#[derive(Debug, Clone, Copy)]
+enum GameResult {
+    /// The player made it to the end of a chart
+    Cleared,
+    /// The player ran out of health
+    Failed,
+    /// The player closed the game part way through
+    Quit,
+}
+
+impl GameResult {
+    const fn title(self) -> &'static str {
+        match self {
+            GameResult::Cleared => "Cleared!",
+            GameResult::Failed => "Failed",
+            GameResult::Quit => "Quit",
+        }
+    }
+}
+
+/// Everything needed to record how a run went, in the player's bests and the run stats
+#[derive(SystemParam)]
+struct RunRecord<'w, 's> {
+    score: Res<'w, Scoreboard>,
+    high_score: ResMut<'w, HighScore>,
+    game_rng: Res<'w, GameRng>,
+    chart: Res<'w, SelectedChart>,
+    asset_server: Res<'w, AssetServer>,
+    difficulty: Res<'w, Difficulty>,
+    auto_play: Res<'w, AutoPlay>,
+    replay_playback: Option<Res<'w, ReplayPlayback>>,
+    modifiers: Res<'w, Modifiers>,
+    column_mapping: Res<'w, ColumnMapping>,
+    endless: Res<'w, EndlessMode>,
+    survival_time: Res<'w, SurvivalTime>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl RunRecord<'_, '_> {
+    /// Whether the run was played by the player, rather than by auto play or a replay
+    fn players_own(&self) -> bool {
+        !self.auto_play.0 && self.replay_playback.is_none()
+    }
+
+    /// Whether the run was an endless one
+    fn endless_run(&self) -> bool {
+        self.endless.0 && self.chart.0.is_none()
+    }
+
+    /// Saves the run's score and survival time if they beat the player's best,
+    /// returning whether each of them did
+    fn save_high_score(&mut self) -> (bool, bool) {
+        // Auto play scores show what's possible, and replays have already been scored,
+        // so neither counts as the player's own. Nor do runs that couldn't be failed,
+        // or charts that were shuffled into something other than what was written
+        let counts = self.players_own() && !self.modifiers.no_fail;
+        let new_high_score =
+            self.score.score > self.high_score.best && counts && !self.column_mapping.is_shuffled();
+        if new_high_score {
+            self.high_score.best = self.score.score;
+        }
+
+        let survived_ms = self.survival_time.0.as_millis() as u64;
+        let new_best_time =
+            self.endless_run() && survived_ms > self.high_score.best_survival_ms && counts;
+        if new_best_time {
+            self.high_score.best_survival_ms = survived_ms;
+        }
+
+        if new_high_score || new_best_time {
+            persist::save(HIGH_SCORE_FILE, &*self.high_score);
+        }
+        (new_high_score, new_best_time)
+    }
+
+    /// Adds a row for the run to the run stats
+    fn log_stats(&self, result: GameResult) {
+        // Like high scores, auto play runs and replays aren't the player's own
+        if !self.players_own() {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        // Charts are identified by their path, and random runs by their seed.
+        // Shuffled charts get their seed too, which sets them apart from the chart as written
+        let (chart, seed) = match &self.chart.0 {
+            Some(chart) => (
+                self.asset_server
+                    .get_handle_path(chart)
+                    .map(|path| path.path().display().to_string())
+                    .unwrap_or_default(),
+                if self.column_mapping.is_shuffled() {
+                    self.game_rng.seed.to_string()
+                } else {
+                    String::new()
+                },
+            ),
+            // Endless runs are set apart from other random runs by how long they lasted
+            None if self.endless.0 => (
+                format!("Endless ({})", format_survival_time(self.survival_time.0)),
+                self.game_rng.seed.to_string(),
+            ),
+            None => (String::new(), self.game_rng.seed.to_string()),
+        };
+        let accuracy = self
+            .score
+            .accuracy()
+            .map(|accuracy| format!("{:.1}", accuracy))
+            .unwrap_or_default();
+
+        // The chart's path is quoted, in case it has a comma in it
+        let row = format!(
+            "{},\"{}\",{},{:?},{},{},{},{},{},{},{:?}",
+            timestamp,
+            chart.replace('"', "\"\""),
+            seed,
+            *self.difficulty,
+            self.score.score,
+            self.score.max_combo,
+            accuracy,
+            self.score.perfects,
+            self.score.goods,
+            self.score.misses,
+            result,
+        );
+        persist::append_csv_row(RUN_STATS_FILE, RUN_STATS_HEADER, &row);
+    }
+}
+
+/// The file a row is added to for every run that finishes or is quit, so players can track their
+/// progress
+const RUN_STATS_FILE: &str = "run_stats.csv";
+
+/// The first row of [`RUN_STATS_FILE`], naming each column
+const RUN_STATS_HEADER: &str =
+    "timestamp,chart,seed,difficulty,score,max_combo,accuracy,perfects,goods,misses,result";
+
+/// The start of the name of every replay file, which is followed by when it was saved
+const REPLAY_FILE_PREFIX: &str = "replay_";
+
+/// How many of the most recent replays are listed in the replays menu
+const REPLAYS_SHOWN: usize = 8;
+
+/// A column being pressed or let go during a run
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct ReplayInput {
+    /// When it happened, in milliseconds since the run started
+    time_ms: u32,
+    column: Column,
+    pressed: bool,
+}
+
+/// The settings that change how a run is judged, which a replay has to be watched with
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct ReplaySettings {
+    difficulty: Difficulty,
+    scroll_speed: ScrollSpeed,
+    hit_window: HitWindow,
+    audio_offset: AudioOffset,
+    modifiers: Modifiers,
+    #[serde(default)]
+    endless: EndlessMode,
+    #[serde(default)]
+    charge_scoring: ChargeScoring,
+    #[serde(default)]
+    lanes: Lanes,
+    #[serde(default)]
+    punish_ghost_taps: PunishGhostTaps,
+    #[serde(default)]
+    scoring: ScoringConfig,
+}
+
+/// The settings the current run is judged with
+#[derive(SystemParam)]
+struct RunSettings<'w, 's> {
+    difficulty: Res<'w, Difficulty>,
+    scroll_speed: Res<'w, ScrollSpeed>,
+    hit_window: Res<'w, HitWindow>,
+    audio_offset: Res<'w, AudioOffset>,
+    modifiers: Res<'w, Modifiers>,
+    endless: Res<'w, EndlessMode>,
+    charge_scoring: Res<'w, ChargeScoring>,
+    lanes: Res<'w, Lanes>,
+    punish_ghost_taps: Res<'w, PunishGhostTaps>,
+    scoring: Res<'w, ScoringConfig>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl RunSettings<'_, '_> {
+    /// A copy of the settings, to be recorded or put back later
+    fn snapshot(&self) -> ReplaySettings {
+        ReplaySettings {
+            difficulty: *self.difficulty,
+            scroll_speed: *self.scroll_speed,
+            hit_window: *self.hit_window,
+            audio_offset: *self.audio_offset,
+            modifiers: *self.modifiers,
+            endless: *self.endless,
+            charge_scoring: *self.charge_scoring,
+            lanes: *self.lanes,
+            punish_ghost_taps: *self.punish_ghost_taps,
+            scoring: *self.scoring,
+        }
+    }
+}
+
+impl ReplaySettings {
+    /// These settings kept within what the settings menus allow.
+    ///
+    /// Replays and run codes can come from anyone, and a setting out of range could crash the
+    /// game, like a travel time of 0 making targets fall infinitely fast
+    fn clamped(self) -> Self {
+        Self {
+            scroll_speed: self.scroll_speed.clamped(),
+            audio_offset: AudioOffset(
+                self.audio_offset
+                    .0
+                    .clamp(-AUDIO_OFFSET_LIMIT, AUDIO_OFFSET_LIMIT),
+            ),
+            lanes: Lanes(self.lanes.0.clamp(Lanes::default().0, MAX_LANES)),
+            scoring: self.scoring.clamped(),
+            ..self
+        }
+    }
+
+    /// Swaps these settings in for the player's own
+    fn apply(self, commands: &mut Commands) {
+        commands.insert_resource(self.difficulty);
+        commands.insert_resource(self.scroll_speed);
+        commands.insert_resource(self.hit_window);
+        commands.insert_resource(self.audio_offset);
+        commands.insert_resource(self.modifiers);
+        commands.insert_resource(self.endless);
+        commands.insert_resource(self.charge_scoring);
+        commands.insert_resource(self.lanes);
+        commands.insert_resource(self.punish_ghost_taps);
+        commands.insert_resource(self.scoring);
+    }
+}
+
+/// The file an interrupted run is saved to, so it can be resumed later
+const SESSION_FILE: &str = "session.ron";
+
+/// A target that was still falling when its run was saved
+#[derive(Serialize, Deserialize)]
+struct SavedTarget {
+    column: Column,
+    /// How far the target had fallen, as a height in the falling frame
+    height: f32,
+    duration_ms: u32,
+}
+
+/// An unfinished run, saved when it's paused or the window is closed so it can be resumed.
+///
+/// Everything that's been scored is kept: the scoreboard and health, how far through the chart
+/// the run was, the settings it was judged with, and the targets still falling.
+/// Some things can't be brought back, so a resumed run goes without them:
+/// - the chart's song, unless it's a WAV file, as only they can be started part way through
+/// - the state of the random number generator, so random runs carry on with a new seed
+/// - hold notes that were being held, which are dropped without being judged
+/// - the replay being recorded, as the start of the run is gone
+#[derive(Serialize, Deserialize)]
+struct SavedSession {
+    /// The asset path of the chart being played, or `None` for a random run
+    chart: Option<String>,
+    settings: ReplaySettings,
+    score: Scoreboard,
+    health: Health,
+    /// Time since the chart started, in milliseconds
+    chart_ms: u64,
+    /// The next of the chart's notes to be spawned
+    next_note: usize,
+    /// How often random targets were spawning, which the adaptive modifier changes
+    spawn_interval_ms: u64,
+    /// How long it had been since the last random target spawned
+    spawn_elapsed_ms: u64,
+    /// How the chart's columns were shuffled by [`Modifier::Random`]
+    #[serde(default)]
+    column_mapping: ColumnMapping,
+    /// How long an endless run had lasted
+    #[serde(default)]
+    survived_ms: u64,
+    targets: Vec<SavedTarget>,
+}
+
+impl SavedSession {
+    /// Puts the run's state back, in place of a fresh run's
+    fn restore(&self, commands: &mut Commands) {
+        commands.insert_resource(self.score.clone());
+        commands.insert_resource(self.health.clone());
+
+        let mut playback = ChartPlayback {
+            next_note: self.next_note,
+            resumed: true,
+            ..Default::default()
+        };
+        playback
+            .clock
+            .set_elapsed(Duration::from_millis(self.chart_ms));
+        commands.insert_resource(playback);
+
+        let mut spawn_timer = Timer::new(Duration::from_millis(self.spawn_interval_ms), true);
+        spawn_timer.set_elapsed(Duration::from_millis(self.spawn_elapsed_ms));
+        commands.insert_resource(SpawnTimer(spawn_timer));
+        commands.insert_resource(SurvivalTime(Duration::from_millis(self.survived_ms)));
+    }
+}
+
+/// The saved run that's about to be resumed, which the game is set up from instead of afresh
+struct ResumedSession(SavedSession);
+
+/// Every press and release from a run, along with everything needed to play it back
+#[derive(Serialize, Deserialize)]
+struct Replay {
+    /// The asset path of the chart that was played, or `None` for a random run
+    chart: Option<String>,
+    /// The seed random targets were spawned with
+    seed: u64,
+    settings: ReplaySettings,
+    inputs: Vec<ReplayInput>,
+}
+
+impl Replay {
+    /// What was played, e.g. "charts/example.ron" or "Random (seed 42)"
+    fn label(&self, streamer_mode: &StreamerMode) -> String {
+        match &self.chart {
+            Some(chart) => streamer_mode.path(chart).into(),
+            None => format!("Random (seed {})", self.seed),
+        }
+    }
+}
+
+/// The replay being recorded of the current run.
+///
+/// It's left in place after the run ends, so it can be saved from the game over menu
+struct ReplayRecorder {
+    replay: Replay,
+    /// Time since a random run started, which only counts while targets are moving.
+    /// Charted runs go by [`ChartPlayback`]'s clock instead
+    clock: Stopwatch,
+    /// The columns that were held down last frame
+    held: HashSet<Column>,
+}
+
+/// The replay being watched, which stands in for the player's input until they return to the menu
+struct ReplayPlayback {
+    replay: Replay,
+    /// The player's own settings, which are put back once they're done watching
+    previous_settings: ReplaySettings,
+    clock: Stopwatch,
+    next_input: usize,
+    held: HashSet<Column>,
+    just_pressed: HashSet<Column>,
+}
+
+impl ReplayPlayback {
+    /// Goes back to the start of the replay, for when the run is restarted
+    fn restart(&mut self) {
+        self.clock.reset();
+        self.next_input = 0;
+        self.held.clear();
+        self.just_pressed.clear();
+    }
+}
+
+/// A run set up from someone else's run code, to try to beat their score
+struct Rematch {
+    /// The seed their random targets were spawned with
+    seed: u64,
+    /// The score to beat
+    score: i32,
+    /// The player's own settings, which are put back once they return to the start menu
+    previous_settings: ReplaySettings,
+}
+
+/// An optional change to how a run is played, which doesn't change how targets are judged
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Modifier {
+    /// Targets fade in as they fall, so they can't be read far ahead
+    Hidden,
+    /// Targets fade out as they near the crosshairs, so they have to be read ahead
+    Sudden,
+    /// Running out of health doesn't end the run, so a whole chart can be played through
+    NoFail,
+    /// Random targets spawn faster while the player keeps their streak going,
+    /// and slower again after they miss
+    Adaptive,
+    /// A chart's columns are shuffled, the same way for the whole run
+    Random,
+}
+
+impl Modifier {
+    /// Every modifier, in the order they're listed in the modifiers menu
+    const ALL: [Modifier; 5] = [
+        Modifier::Hidden,
+        Modifier::Sudden,
+        Modifier::NoFail,
+        Modifier::Adaptive,
+        Modifier::Random,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Modifier::Hidden => "Hidden",
+            Modifier::Sudden => "Sudden",
+            Modifier::NoFail => "No Fail",
+            Modifier::Adaptive => "Adaptive",
+            Modifier::Random => "Random",
+        }
+    }
+}
+
+/// Which modifiers are turned on.
+/// Like the difficulty, these are chosen for a session rather than saved
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct Modifiers {
+    hidden: bool,
+    sudden: bool,
+    no_fail: bool,
+    adaptive: bool,
+    /// Replays saved before this modifier was added don't have it
+    #[serde(default)]
+    random: bool,
+}
+
+impl Modifiers {
+    fn get(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::Hidden => self.hidden,
+            Modifier::Sudden => self.sudden,
+            Modifier::NoFail => self.no_fail,
+            Modifier::Adaptive => self.adaptive,
+            Modifier::Random => self.random,
+        }
+    }
+
+    fn toggle(&mut self, modifier: Modifier) {
+        let enabled = match modifier {
+            Modifier::Hidden => &mut self.hidden,
+            Modifier::Sudden => &mut self.sudden,
+            Modifier::NoFail => &mut self.no_fail,
+            Modifier::Adaptive => &mut self.adaptive,
+            Modifier::Random => &mut self.random,
+        };
+        *enabled = !*enabled;
+    }
+
+    /// A human readable description of a modifier, e.g. "Hidden: On"
+    fn label(&self, modifier: Modifier) -> String {
+        let state = if self.get(modifier) { "On" } else { "Off" };
+        format!("{}: {}", modifier.name(), state)
+    }
+
+    /// How opaque a target should be at a height, in the falling layout
+    fn opacity(&self, config: &GameConfig, y: f32) -> f32 {
+        let mut opacity = 1.0;
+        if self.hidden {
+            let (invisible, visible) = config.hidden_fade;
+            opacity *= ((invisible - y) / (invisible - visible)).clamp(0.0, 1.0);
+        }
+        if self.sudden {
+            let (visible, invisible) = config.sudden_fade;
+            opacity *= ((y - invisible) / (visible - invisible)).clamp(0.0, 1.0);
+        }
+        opacity
+    }
+}
+
+/// Which column each of a chart's columns has been moved to by [`Modifier::Random`],
+/// or empty if they're left where they are
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct ColumnMapping(Vec<Column>);
+
+impl ColumnMapping {
+    /// A random order for the playfield's columns
+    fn shuffled(config: &GameConfig, rng: &mut impl Rng) -> Self {
+        let mut columns: Vec<Column> = config.columns().collect();
+        columns.shuffle(rng);
+        Self(columns)
+    }
+
+    fn is_shuffled(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    /// The column a chart's note is moved to
+    fn apply(&self, column: Column) -> Column {
+        self.0
+            .get(usize::from(column.index()))
+            .copied()
+            .unwrap_or(column)
+    }
+
+    /// Where each column went, e.g. "Random: 3 1 4 2" when the first column moved to the third
+    fn label(&self) -> String {
+        let columns: Vec<String> = self
+            .0
+            .iter()
+            .map(|column| (column.index() + 1).to_string())
+            .collect();
+        format!("Random: {}", columns.join(" "))
+    }
+}
+
+/// The alpha a sprite attached to a target is drawn with, before any modifiers fade it
+#[derive(Component)]
+struct BaseAlpha(f32);
+
+/// The file the player's audio offset is saved to
+const AUDIO_OFFSET_FILE: &str = "audio_offset.ron";
+
+/// How late, in milliseconds, the player hears the game's audio.
+///
+/// Charts with a song are delayed by this much so their notes line up with what the player hears,
+/// and without a song the judgment line is shifted instead, so it is only ever applied once.
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct AudioOffset(i32);
+
+/// The furthest a replay or run code's audio offset can be either way, in milliseconds
+const AUDIO_OFFSET_LIMIT: i32 = 1000;
+
+/// The file the player's visual offset is saved to
+const VISUAL_OFFSET_FILE: &str = "visual_offset.ron";
+
+/// How much the calibration screen's buttons change the visual offset by, in milliseconds
+const VISUAL_OFFSET_STEP: i32 = 5;
+
+/// The furthest the visual offset can be set either way, in milliseconds
+const VISUAL_OFFSET_LIMIT: i32 = 200;
+
+/// How late, in milliseconds, the player sees the game, separately from how late they hear it.
+///
+/// Targets are drawn this much further along than they really are, so they look like they reach
+/// the crosshair when they should be hit. Only where they're drawn moves, they're still judged
+/// where they really are
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct VisualOffset(i32);
+
+impl VisualOffset {
+    /// A human readable description of the offset, e.g. "Visual offset: 15 ms"
+    fn label(self) -> String {
+        format!("Visual offset: {} ms", self.0)
+    }
+}
+
+/// The file the player's volume settings are saved to
+const VOLUME_FILE: &str = "volume.ron";
+
+/// How much the volume buttons change a volume by
+const VOLUME_STEP: f32 = 0.1;
+
+/// The two separately adjustable kinds of audio
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum VolumeChannel {
+    Music,
+    Sfx,
+}
+
+/// How loud the music and sound effects are, from 0.0 (muted) to 1.0 (full volume)
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Volume {
+    music: f32,
+    sfx: f32,
+    /// Silences everything without forgetting the volumes, only for this session
+    #[serde(skip)]
+    muted: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            music: 1.0,
+            sfx: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl Volume {
+    fn get(&self, channel: VolumeChannel) -> f32 {
+        match channel {
+            VolumeChannel::Music => self.music,
+            VolumeChannel::Sfx => self.sfx,
+        }
+    }
+
+    /// Sets a volume, keeping it between 0.0 and 1.0
+    fn set(&mut self, channel: VolumeChannel, value: f32) {
+        let volume = match channel {
+            VolumeChannel::Music => &mut self.music,
+            VolumeChannel::Sfx => &mut self.sfx,
+        };
+        *volume = value.clamp(0.0, 1.0);
+    }
+
+    /// Changes a volume, keeping it between 0.0 and 1.0
+    fn adjust(&mut self, channel: VolumeChannel, amount: f32) {
+        let volume = match channel {
+            VolumeChannel::Music => &mut self.music,
+            VolumeChannel::Sfx => &mut self.sfx,
+        };
+        // Round to the nearest step so repeated presses don't drift away from 0.0
+        *volume = ((*volume + amount) / VOLUME_STEP).round() * VOLUME_STEP;
+        *volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// A human readable volume, e.g. "Music: 80%"
+    fn label(&self, channel: VolumeChannel) -> String {
+        format!("{:?}: {:.0}%", channel, self.get(channel) * 100.0)
+    }
+
+    /// The volume music should actually play at, taking muting into account
+    fn music_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.music
+        }
+    }
+
+    /// Playback settings for a sound effect at the current volume
+    fn sfx_settings(&self) -> PlaybackSettings {
+        PlaybackSettings::ONCE.with_volume(if self.muted { 0.0 } else { self.sfx })
+    }
+}
+
+/// The file the ghost tap setting is saved to
+const GHOST_TAPS_FILE: &str = "ghost_taps.ron";
+
+/// Whether pressing a column with no target in range counts as a miss.
+/// Off by default, so mashing is forgiven unless the player asks otherwise
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct PunishGhostTaps(bool);
+
+impl PunishGhostTaps {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Ghost taps: Punished"
+        } else {
+            "Ghost taps: Forgiven"
+        }
+    }
+}
+
+/// The file the hit sound setting is saved to
+const HIT_SOUNDS_FILE: &str = "hit_sounds.ron";
+
+/// How far above the base note each column is pitched, in semitones, when hit sounds are pitched.
+/// Columns past the end of this go up an octave and start again, so the columns form a major chord
+const HIT_SOUND_CHORD: [i32; 3] = [0, 4, 7];
+
+/// How each column's hit sound is chosen
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+enum HitSounds {
+    /// Every column plays its own recording
+    #[default]
+    PerColumn,
+    /// Every column plays the first column's recording, pitched up to fit a chord
+    Pitched,
+    /// Hits are silent, leaving only the music and the miss sound
+    Off,
+}
+
+impl HitSounds {
+    /// How much faster than normal a column's hit sound should be played, to change its pitch
+    fn speed(self, column: Column) -> f32 {
+        match self {
+            HitSounds::PerColumn | HitSounds::Off => 1.0,
+            HitSounds::Pitched => {
+                let index = usize::from(column.index());
+                let semitones = HIT_SOUND_CHORD[index % HIT_SOUND_CHORD.len()]
+                    + 12 * (index / HIT_SOUND_CHORD.len()) as i32;
+                2f32.powf(semitones as f32 / 12.0)
+            }
+        }
+    }
+
+    /// A human readable description of the setting
+    fn label(self) -> &'static str {
+        match self {
+            HitSounds::PerColumn => "Hit sounds: Per column",
+            HitSounds::Pitched => "Hit sounds: Pitched",
+            HitSounds::Off => "Hit sounds: Off",
+        }
+    }
+}
+
+/// The file the hit sound voices setting is saved to
+const HIT_SOUND_VOICES_FILE: &str = "hit_sound_voices.ron";
+
+/// The caps the hit sound voices setting cycles through, in sounds per column
+const HIT_SOUND_VOICE_CAPS: [u32; 3] = [1, 2, 4];
+
+/// How many of a column's hit sounds can play over each other.
+///
+/// Fast streams in one column would otherwise pile up sounds until the mix clips, so once a column
+/// is at its cap, its oldest sound is stopped to make way for the new one. A cap of 1 restarts the
+/// sound on every hit. Each column has its own cap, so chords still play a sound for every column
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+enum HitSoundVoices {
+    Capped(u32),
+    #[default]
+    Unlimited,
+}
+
+impl HitSoundVoices {
+    /// The next setting along, which the settings button cycles through
+    fn next(self) -> Self {
+        match self {
+            HitSoundVoices::Capped(cap) => {
+                match HIT_SOUND_VOICE_CAPS.iter().find(|&&next| next > cap) {
+                    Some(&next) => HitSoundVoices::Capped(next),
+                    None => HitSoundVoices::Unlimited,
+                }
+            }
+            HitSoundVoices::Unlimited => HitSoundVoices::Capped(HIT_SOUND_VOICE_CAPS[0]),
+        }
+    }
+
+    /// A human readable description of the setting
+    fn label(self) -> String {
+        match self {
+            HitSoundVoices::Capped(cap) => format!("Hit sound voices: {} per column", cap),
+            HitSoundVoices::Unlimited => "Hit sound voices: Unlimited".into(),
+        }
+    }
+}
+
+/// The hit sounds each column has started, oldest first, so the oldest can be cut off once the
+/// column reaches its [`HitSoundVoices`] cap. Sounds that have already finished are counted until
+/// they're pushed out, which is harmless as stopping them does nothing
+#[derive(Default)]
+struct HitSoundSinks(HashMap<Column, VecDeque<Handle<AudioSink>>>);
+
+/// The file the screen shake setting is saved to
+const SCREEN_SHAKE_FILE: &str = "screen_shake.ron";
+
+/// Whether the camera shakes when the player misses, which some players find uncomfortable
+#[derive(Serialize, Deserialize)]
+struct ScreenShake(bool);
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+impl ScreenShake {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Screen shake: On"
+        } else {
+            "Screen shake: Off"
+        }
+    }
+}
+
+/// The file the charge scoring setting is saved to
+const CHARGE_SCORING_FILE: &str = "charge_scoring.ron";
+
+/// Whether normal notes are judged when their press is let go, rather than when it starts.
+///
+/// With this on, pressing a column while its lowest target is in reach starts charging that target
+/// instead of hitting it. The target keeps falling, and is judged by how far it is from the
+/// crosshair when the press is let go: a perfect inside the perfect window, good inside the good
+/// window, and a miss anywhere else. Holding on until it falls past the hit window misses it too.
+/// From there hits are scored as usual, so letting go right on the crosshair is worth the most.
+///
+/// Hold notes already have tails to be held through, so they're judged the same either way
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct ChargeScoring(bool);
+
+impl ChargeScoring {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Charge scoring: On"
+        } else {
+            "Charge scoring: Off"
+        }
+    }
+}
+
+/// The file the training wheels setting is saved to
+const TRAINING_WHEELS_FILE: &str = "training_wheels.ron";
+
+/// Whether the next target to hit is outlined, to guide the eyes of players who are just starting
+#[derive(Default, Serialize, Deserialize)]
+struct TrainingWheels(bool);
+
+impl TrainingWheels {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Training wheels: On"
+        } else {
+            "Training wheels: Off"
+        }
+    }
+}
+
+/// The file the key labels setting is saved to
+const KEY_LABELS_FILE: &str = "key_labels.ron";
+
+/// Whether each crosshair shows the keys bound to its column, for players still learning them
+#[derive(Serialize, Deserialize)]
+struct KeyLabels(bool);
+
+impl Default for KeyLabels {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+impl KeyLabels {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Key labels: On"
+        } else {
+            "Key labels: Off"
+        }
+    }
+}
+
+/// The keys shown on each crosshair, if the player wants them shown
+#[derive(SystemParam)]
+struct CrosshairKeys<'w, 's> {
+    key_labels: Res<'w, KeyLabels>,
+    key_bindings: Res<'w, KeyBindings>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl CrosshairKeys<'_, '_> {
+    /// The text to show on a column's crosshair, or `None` if key labels are turned off
+    fn label(&self, column: Column) -> Option<String> {
+        self.key_labels
+            .0
+            .then(|| self.key_bindings.keys_label(column))
+    }
+}
+
+/// The file the streamer mode setting is saved to
+const STREAMER_MODE_FILE: &str = "streamer_mode.ron";
+
+/// Whether paths that could give away who the player is are kept off screen and out of the log,
+/// for players who stream or record the game. While it's on:
+/// - the log names files in the data directory as `<data dir>/file`, hiding the user's home
+///   directory and so usually their username
+/// - the load failure screen lists the file names of missing assets, without their folders
+/// - the replays menu names charts by their file name, without their folders
+#[derive(Default, Serialize, Deserialize)]
+struct StreamerMode(bool);
+
+impl StreamerMode {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Streamer mode: On"
+        } else {
+            "Streamer mode: Off"
+        }
+    }
+
+    /// The path to show for a file, which is only its name while streamer mode is on
+    fn path<'a>(&self, path: &'a str) -> &'a str {
+        if self.0 {
+            path.rsplit(['/', '\\']).next().unwrap_or(path)
+        } else {
+            path
+        }
+    }
+}
+
+/// The file the judgment line setting is saved to
+const JUDGMENT_LINE_FILE: &str = "judgment_line.ron";
+
+/// How much the settings menu's buttons move the judgment line by, in pixels
+const JUDGMENT_LINE_STEP: i32 = 20;
+
+/// The furthest the judgment line can be raised, which keeps it in the bottom half of the
+/// playfield so targets still have room to fall
+const JUDGMENT_LINE_LIMIT: i32 = 300;
+
+/// How far the crosshairs are raised from the bottom of the playfield, in pixels,
+/// for players who want more time to see targets coming
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+struct JudgmentLine(i32);
+
+impl JudgmentLine {
+    /// A human readable description of the setting, e.g. "Judgment line: +40 px"
+    fn label(self) -> String {
+        format!("Judgment line: +{} px", self.0)
+    }
+
+    /// The height of the crosshairs in the falling frame
+    fn hit_y(self) -> f32 {
+        GameConfig::default().hit_y + self.0.clamp(0, JUDGMENT_LINE_LIMIT) as f32
+    }
+}
+
+/// The file the lane count setting is saved to
+const LANES_FILE: &str = "lanes.ron";
+
+/// The most lanes the playfield can have, one for each colour in [`COLUMN_THEMES`]
+const MAX_LANES: u8 = COLUMN_THEMES.len() as u8;
+
+/// How many columns the playfield has. There are always at least four
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+struct Lanes(u8);
+
+impl Default for Lanes {
+    fn default() -> Self {
+        Self(4)
+    }
+}
+
+impl Lanes {
+    /// The next setting along, which the settings button cycles through
+    fn next(self) -> Self {
+        if self.0 >= MAX_LANES {
+            Self::default()
+        } else {
+            Self(self.0 + 1)
+        }
+    }
+
+    /// Every column, from left to right
+    fn columns(self) -> impl Iterator<Item = Column> {
+        (0..self.0).map(Column)
+    }
+
+    /// A human readable description of the setting, e.g. "Lanes: 5"
+    fn label(self) -> String {
+        format!("Lanes: {}", self.0)
+    }
+}
+
+/// The playfield settings that are fixed for a run when it's set up
+#[derive(SystemParam)]
+struct PlayfieldSettings<'w, 's> {
+    up_scroll: Res<'w, UpScroll>,
+    column_shapes: Res<'w, ColumnShapes>,
+    judgment_line: Res<'w, JudgmentLine>,
+    lanes: Res<'w, Lanes>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl PlayfieldSettings<'_, '_> {
+    /// Lays out the playfield with these settings
+    fn apply(&self, config: &mut GameConfig) {
+        config.upscroll = self.up_scroll.0;
+        config.column_shapes = self.column_shapes.0;
+        config.move_judgment_line(self.judgment_line.hit_y());
+        // Extra lanes are squeezed closer together to fit on the playfield
+        config.lanes = self.lanes.0.clamp(Lanes::default().0, MAX_LANES);
+        config.column_spacing = GameConfig::default()
+            .column_spacing
+            .min(PLAYFIELD_WIDTH / f32::from(config.lanes));
+    }
+}
+
+/// The file the upscroll setting is saved to
+const UP_SCROLL_FILE: &str = "up_scroll.ron";
+
+/// Whether targets rise from the bottom of the screen to crosshairs at the top,
+/// instead of falling from the top
+#[derive(Default, Serialize, Deserialize)]
+struct UpScroll(bool);
+
+impl UpScroll {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Scroll: Up"
+        } else {
+            "Scroll: Down"
+        }
+    }
+}
+
+/// The file the column shapes setting is saved to
+const COLUMN_SHAPES_FILE: &str = "column_shapes.ron";
+
+/// Whether columns are marked with shapes as well as colours, for colourblind players
+#[derive(Default, Serialize, Deserialize)]
+struct ColumnShapes(bool);
+
+impl ColumnShapes {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Column shapes: On"
+        } else {
+            "Column shapes: Off"
+        }
+    }
+}
+
+/// The file the mouse and touch input setting is saved to
+const POINTER_INPUT_FILE: &str = "pointer_input.ron";
+
+/// Whether clicking or touching a lane presses its column, for playing with a mouse or touchscreen.
+/// Off by default, so stray clicks don't count when playing with a keyboard
+#[derive(Default, Serialize, Deserialize)]
+struct PointerInput(bool);
+
+impl PointerInput {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Mouse/touch: On"
+        } else {
+            "Mouse/touch: Off"
+        }
+    }
+}
+
+/// The file the metronome setting is saved to
+const METRONOME_FILE: &str = "metronome.ron";
+
+/// Whether a quiet tick is played on every beat while playing, as a practice aid
+#[derive(Default, Serialize, Deserialize)]
+struct Metronome(bool);
+
+impl Metronome {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Metronome: On"
+        } else {
+            "Metronome: Off"
+        }
+    }
+}
+
+/// How quiet the metronome is compared to other sound effects
+const METRONOME_VOLUME: f32 = 0.4;
+
+/// Keeps track of which beats have already passed this run
+#[derive(Default)]
+struct BeatClock {
+    /// Time since the run started, for runs without a chart to keep time
+    stopwatch: Stopwatch,
+    /// How many beats have passed so far
+    beats: u32,
+}
+
+/// The file the timing markers setting is saved to
+const TIMING_MARKERS_FILE: &str = "timing_markers.ron";
+
+/// Whether a marker shows how early or late each hit was, above or below its crosshair
+#[derive(Default, Serialize, Deserialize)]
+struct TimingMarkers(bool);
+
+impl TimingMarkers {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Timing markers: On"
+        } else {
+            "Timing markers: Off"
+        }
+    }
+}
+
+/// The file the fast/slow labels setting is saved to
+const FAST_SLOW_FILE: &str = "fast_slow.ron";
+
+/// Whether hits that aren't perfect are labelled as fast or slow, under their judgment
+#[derive(Default, Serialize, Deserialize)]
+struct FastSlow(bool);
+
+impl FastSlow {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Fast/slow: On"
+        } else {
+            "Fast/slow: Off"
+        }
+    }
+}
+
+/// The file the combo ducking setting is saved to
+const COMBO_DUCKING_FILE: &str = "combo_ducking.ron";
+
+/// How much quieter a chart's song gets at the top of a combo, as a fraction of the music volume
+const COMBO_DUCK_DEPTH: f32 = 0.3;
+
+/// The streak at which a chart's song is ducked as far as it goes.
+/// The combo itself is capped far below this, so it's the uncapped streak that's counted
+const COMBO_DUCK_FULL_COMBO: u32 = 50;
+
+/// How quickly the song is ducked as a combo climbs, as a fraction of the music volume per second
+const COMBO_DUCK_RATE: f32 = 0.1;
+
+/// Whether a chart's song gets a little quieter as the combo climbs, to make a hot streak stand out
+#[derive(Default, Serialize, Deserialize)]
+struct ComboDucking(bool);
+
+impl ComboDucking {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Combo ducking: On"
+        } else {
+            "Combo ducking: Off"
+        }
+    }
+}
+
+/// The file the announcements setting is saved to
+const ANNOUNCEMENTS_FILE: &str = "announcements.ron";
+
+/// Whether the moments listed in [`Announcement`] are announced with sounds of their own
+#[derive(Default, Serialize, Deserialize)]
+struct Announcements(bool);
+
+impl Announcements {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Announcements: On"
+        } else {
+            "Announcements: Off"
+        }
+    }
+}
+
+/// The file the beat pulse setting is saved to
+const BEAT_PULSE_FILE: &str = "beat_pulse.ron";
+
+/// Whether the background pulses on every beat, which players sensitive to flashing can turn off
+#[derive(Serialize, Deserialize)]
+struct BeatPulse(bool);
+
+impl Default for BeatPulse {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+impl BeatPulse {
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Beat pulse: On"
+        } else {
+            "Beat pulse: Off"
+        }
+    }
+}
+
+/// How long the backdrop takes to fade back to black after a beat
+const PULSE_DURATION: Duration = Duration::from_millis(250);
+
+/// How bright the backdrop gets on a beat, kept faint so it doesn't distract from the targets
+const PULSE_BRIGHTNESS: f32 = 0.06;
+
+/// Component for the full-screen backdrop that pulses on every beat
+#[derive(Component)]
+struct Backdrop(Timer);
+
+/// The file the player's scroll speed is saved to
+const SCROLL_SPEED_FILE: &str = "scroll_speed.ron";
+
+/// How much the scroll speed hotkeys change the multiplier by
+const SCROLL_SPEED_STEP: f32 = 0.1;
+
+/// The slowest and fastest the multiplier can be set to
+const SCROLL_SPEED_RANGE: (f32, f32) = (0.5, 3.0);
+
+/// How much the scroll speed hotkeys change the travel time by, in milliseconds
+const TRAVEL_TIME_STEP: u32 = 100;
+
+/// The quickest and slowest the travel time can be set to, in milliseconds
+const TRAVEL_TIME_RANGE: (u32, u32) = (500, 8000);
+
+/// How the player chooses how fast targets fall
+#[derive(Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+enum ScrollMode {
+    /// A multiplier on top of the [`Difficulty`]'s scroll speed
+    #[default]
+    Multiplier,
+    /// How long targets take to fall from where they spawn to the crosshairs,
+    /// whatever the difficulty
+    TravelTime,
+}
+
+/// How fast targets fall, for players who want to read ahead further or less far.
+///
+/// It can only be changed from the start menu, because targets already on screen would jump
+/// if it changed while they were falling.
+///
+/// It's read through [`ScrollSpeedVisitor`], so that speeds saved before there were scroll modes
+/// still load
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default, remote = "Self")]
+struct ScrollSpeed {
+    mode: ScrollMode,
+    multiplier: f32,
+    /// How long targets take to reach the crosshairs in [`ScrollMode::TravelTime`], in milliseconds
+    travel_time_ms: u32,
+}
+
+impl Default for ScrollSpeed {
+    fn default() -> Self {
+        Self {
+            mode: ScrollMode::Multiplier,
+            multiplier: 1.0,
+            travel_time_ms: 4000,
+        }
+    }
+}
+
+impl Serialize for ScrollSpeed {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ScrollSpeed::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScrollSpeed {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ScrollSpeedVisitor)
+    }
+}
+
+/// Reads a [`ScrollSpeed`] as it's saved now, or as the bare multiplier it used to be saved as,
+/// like `(1.5)`
+struct ScrollSpeedVisitor;
+
+impl<'de> Visitor<'de> for ScrollSpeedVisitor {
+    type Value = ScrollSpeed;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a scroll speed")
+    }
+
+    fn visit_f64<E: de::Error>(self, multiplier: f64) -> Result<ScrollSpeed, E> {
+        Ok(ScrollSpeed {
+            multiplier: multiplier as f32,
+            ..Default::default()
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ScrollSpeed, A::Error> {
+        let multiplier = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        Ok(ScrollSpeed {
+            multiplier,
+            ..Default::default()
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ScrollSpeed, A::Error> {
+        ScrollSpeed::deserialize(MapAccessDeserializer::new(map))
+    }
+}
+
+impl ScrollSpeed {
+    /// How fast targets fall at the given difficulty, in pixels per second
+    fn apply(&self, difficulty: Difficulty, config: &GameConfig) -> f32 {
+        match self.mode {
+            ScrollMode::Multiplier => difficulty.scroll_speed() * self.multiplier,
+            ScrollMode::TravelTime => {
+                (config.spawn_y - config.hit_y) / (self.travel_time_ms as f32 / 1000.0)
+            }
+        }
+    }
+
+    /// The speed kept within [`SCROLL_SPEED_RANGE`] and [`TRAVEL_TIME_RANGE`]
+    fn clamped(self) -> Self {
+        let (min_multiplier, max_multiplier) = SCROLL_SPEED_RANGE;
+        let (min_travel_time, max_travel_time) = TRAVEL_TIME_RANGE;
+        Self {
+            // Clamping leaves NaN as it is, so it goes back to the default
+            multiplier: if self.multiplier.is_nan() {
+                ScrollSpeed::default().multiplier
+            } else {
+                self.multiplier.clamp(min_multiplier, max_multiplier)
+            },
+            travel_time_ms: self.travel_time_ms.clamp(min_travel_time, max_travel_time),
+            ..self
+        }
+    }
+
+    /// Makes targets fall a step faster, or slower if `faster` is false,
+    /// keeping within [`SCROLL_SPEED_RANGE`] or [`TRAVEL_TIME_RANGE`]
+    fn adjust(&mut self, faster: bool) {
+        match self.mode {
+            ScrollMode::Multiplier => {
+                let (min, max) = SCROLL_SPEED_RANGE;
+                let change = if faster {
+                    SCROLL_SPEED_STEP
+                } else {
+                    -SCROLL_SPEED_STEP
+                };
+                // Round to the step so repeated adjustments don't drift away from 1.0
+                self.multiplier =
+                    ((self.multiplier + change) / SCROLL_SPEED_STEP).round() * SCROLL_SPEED_STEP;
+                self.multiplier = self.multiplier.clamp(min, max);
+            }
+            ScrollMode::TravelTime => {
+                let (min, max) = TRAVEL_TIME_RANGE;
+                self.travel_time_ms = if faster {
+                    self.travel_time_ms.saturating_sub(TRAVEL_TIME_STEP)
+                } else {
+                    self.travel_time_ms + TRAVEL_TIME_STEP
+                }
+                .clamp(min, max);
+            }
+        }
+    }
+
+    /// A human readable description of the setting
+    fn label(&self) -> String {
+        match self.mode {
+            ScrollMode::Multiplier => format!("Scroll speed: {:.1}x (+/-)", self.multiplier),
+            ScrollMode::TravelTime => format!("Travel time: {}ms (+/-)", self.travel_time_ms),
+        }
+    }
+
+    /// A human readable description of the mode
+    fn mode_label(&self) -> &'static str {
+        match self.mode {
+            ScrollMode::Multiplier => "Scroll speed: Multiplier",
+            ScrollMode::TravelTime => "Scroll speed: Travel time",
+        }
+    }
+}
+
+/// The command line flag that starts the game with auto play turned on
+const AUTO_PLAY_FLAG: &str = "--autoplay";
+
+/// Whether targets are hit perfectly by the game instead of the player,
+/// for checking that charts are playable and recording gameplay
+struct AutoPlay(bool);
+
+impl AutoPlay {
+    /// Turns auto play on if the game was started with [`AUTO_PLAY_FLAG`]
+    fn from_args() -> Self {
+        Self(std::env::args().any(|arg| arg == AUTO_PLAY_FLAG))
+    }
+
+    /// A human readable description of the setting
+    fn label(&self) -> &'static str {
+        if self.0 {
+            "Auto play: On"
+        } else {
+            "Auto play: Off"
+        }
+    }
+}
+
+/// The time between metronome ticks on the calibration screen
+const CALIBRATION_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many of the player's most recent taps are averaged when calibrating
+const CALIBRATION_TAPS: usize = 8;
+
+/// The height of the calibration screen's lane, in pixels
+const CALIBRATION_LANE_HEIGHT: f32 = 160.0;
+
+/// The size of the calibration screen's note, in pixels
+const CALIBRATION_NOTE_SIZE: f32 = 24.0;
+
+/// The state of the calibration screen's metronome
+struct Calibration {
+    metronome: Timer,
+    /// How early (negative) or late (positive) each recent tap was, in milliseconds
+    taps: Vec<i32>,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            metronome: Timer::new(CALIBRATION_INTERVAL, true),
+            taps: Vec::new(),
+        }
+    }
+}
+
+impl Calibration {
+    /// The average offset of the recent taps, if there have been any
+    fn measured_offset(&self) -> Option<i32> {
+        if self.taps.is_empty() {
+            None
+        } else {
+            Some(self.taps.iter().sum::<i32>() / self.taps.len() as i32)
+        }
+    }
+
+    /// How spread out the recent taps are around their average, as a standard deviation
+    fn deviation(&self) -> Option<f32> {
+        let mean = self.measured_offset()? as f32;
+        let variance = self
+            .taps
+            .iter()
+            .map(|&tap| (tap as f32 - mean).powi(2))
+            .sum::<f32>()
+            / self.taps.len() as f32;
+        Some(variance.sqrt())
+    }
+
+    /// The measured offset and how consistent it was, e.g. "Measured offset: 35 ± 12 ms"
+    fn readout(&self) -> String {
+        match (self.measured_offset(), self.deviation()) {
+            (Some(offset), Some(deviation)) => format!(
+                "Measured offset: {} ± {:.0} ms ({} taps)",
+                offset,
+                deviation,
+                self.taps.len()
+            ),
+            _ => "Measured offset: -".into(),
+        }
+    }
+}
+
+/// The state to return to when the game is unpaused.
+///
+/// While this exists, the game is kept around instead of being despawned and set up again.
+struct ResumeState(GameState);
+
+#[derive(Clone, Copy)]
+struct TargetHitEvent(Column, Judgment);
+
+#[derive(Clone, Copy)]
+struct TargetMissEvent(Column);
+
+/// Sent when a press hits a target, with how it was judged and how early (negative)
+/// or late (positive) it was in milliseconds
+#[derive(Clone, Copy)]
+struct HitTimingEvent(Column, Judgment, f32);
+
+/// How many of the most recent hits the timing statistics are worked out from
+const TIMING_STATS_WINDOW: usize = 50;
+
+/// Rolling statistics about how early or late the player's recent hits were, kept for each run.
+///
+/// The sums are updated as hits come in and fall out of the window,
+/// so adding a hit doesn't mean going over the whole window again
+#[derive(Default)]
+struct TimingStats {
+    /// The timing errors of the most recent hits, in milliseconds, oldest first
+    errors: VecDeque<f32>,
+    sum: f32,
+    sum_of_squares: f32,
+    /// How many hits this run were early, and how many were late
+    early: u32,
+    late: u32,
+}
+
+impl TimingStats {
+    /// Adds a hit's timing error, in milliseconds, dropping the oldest one if the window is full
+    fn record(&mut self, error_ms: f32) {
+        if self.errors.len() == TIMING_STATS_WINDOW {
+            if let Some(oldest) = self.errors.pop_front() {
+                self.sum -= oldest;
+                self.sum_of_squares -= oldest * oldest;
+            }
+        }
+        self.errors.push_back(error_ms);
+        self.sum += error_ms;
+        self.sum_of_squares += error_ms * error_ms;
+
+        if error_ms < 0.0 {
+            self.early += 1;
+        } else if error_ms > 0.0 {
+            self.late += 1;
+        }
+    }
+
+    /// The average timing error of the recent hits, and how spread out they are, in milliseconds
+    fn mean_and_deviation(&self) -> Option<(f32, f32)> {
+        if self.errors.is_empty() {
+            return None;
+        }
+
+        let count = self.errors.len() as f32;
+        let mean = self.sum / count;
+        // Rounding errors in the running sums can take this just below zero
+        let variance = (self.sum_of_squares / count - mean * mean).max(0.0);
+        Some((mean, variance.sqrt()))
+    }
+
+    /// A human readable summary, e.g. "Mean: +3.2ms\nSD: 12.1ms\nEarly: 12  Late: 8"
+    fn label(&self) -> String {
+        let (mean, deviation) = match self.mean_and_deviation() {
+            Some((mean, deviation)) => (format!("{:+.1}ms", mean), format!("{:.1}ms", deviation)),
+            None => ("—".into(), "—".into()),
+        };
+        format!(
+            "Mean: {}\nSD: {}\nEarly: {}  Late: {}",
+            mean, deviation, self.early, self.late
+        )
+    }
+}
+
+/// Whether the timing statistics are shown during runs, which they aren't until the player asks
+#[derive(Default)]
+struct ShowTimingStats(bool);
+
+/// Sent on every beat of a run, from the chart's BPM or the spawn interval
+struct BeatEvent;
+
+/// How many targets have to be hit in a row for losing them to be announced as a combo break
+const ANNOUNCED_COMBO_BREAK: u32 = 10;
+
+/// The moments that are announced with a sound of their own when announcements are turned on,
+/// so players who can't easily read the screen can follow how a run is going
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum Announcement {
+    /// The streak reached a combo milestone
+    Milestone,
+    /// A miss broke a combo of at least [`ANNOUNCED_COMBO_BREAK`]
+    ComboBreak,
+    /// The run ended with a new high score or best survival time
+    HighScore,
+    /// The run ended by clearing its chart, without a new best
+    Cleared,
+    /// The run ended by running out of health, without a new best
+    Failed,
+}
+
+impl Announcement {
+    const ALL: [Announcement; 5] = [
+        Announcement::Milestone,
+        Announcement::ComboBreak,
+        Announcement::HighScore,
+        Announcement::Cleared,
+        Announcement::Failed,
+    ];
+
+    /// The asset path of the announcement's sound
+    const fn path(self) -> &'static str {
+        match self {
+            Announcement::Milestone => "sounds/announcements/milestone.wav",
+            Announcement::ComboBreak => "sounds/announcements/combo_break.wav",
+            Announcement::HighScore => "sounds/announcements/high_score.wav",
+            Announcement::Cleared => "sounds/announcements/cleared.wav",
+            Announcement::Failed => "sounds/announcements/failed.wav",
+        }
+    }
+}
+
+/// Sent when something happens that's worth announcing
+#[derive(Clone, Copy)]
+struct AnnouncementEvent(Announcement);
+
+/// Where all the magic happens
+fn main() {
+    // Settings are loaded before any systems run, so the log has to know straight away
+    let streamer_mode = persist::load::<StreamerMode>(STREAMER_MODE_FILE);
+    persist::set_hide_paths(streamer_mode.0);
+
+    App::new()
+        .insert_resource(WindowDescriptor {
+            title: "Rhythm Game".into(),
+            width: PLAYFIELD_WIDTH,
+            height: PLAYFIELD_HEIGHT,
+            ..Default::default()
+        })
+        // Closing the window is handled by exit_on_close_requested, so runs can be saved first
+        .insert_resource(WindowSettings {
+            close_when_requested: false,
+            ..Default::default()
+        })
+        .insert_resource(ClearColor(Color::BLACK))
+        .add_plugins(DefaultPlugins)
+        .add_plugin(FrameTimeDiagnosticsPlugin)
+        .add_event::<TargetHitEvent>()
+        .add_event::<TargetMissEvent>()
+        .add_event::<BeatEvent>()
+        .add_event::<HitTimingEvent>()
+        .add_event::<AnnouncementEvent>()
+        // Start out waiting for the assets to load
+        .add_loopless_state(GameState::Loading)
+        .add_enter_system(GameState::Loading, setup_loading_screen)
+        .add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::Loading)
+                .with_system(check_assets_loaded)
+                .with_system(update_loading_screen)
+                .into(),
+        )
+        .add_exit_system(GameState::Loading, despawn_with::<LoadingScreen>)
+        // If any of them failed, say which instead of panicking later on
+        .add_enter_system(GameState::LoadFailed, setup_load_failed_menu)
+        .add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::LoadFailed)
+                .with_system(exit_on_key)
+                .with_system(button_visual_interact)
+                .with_system(on_exit_button.run_if(button_interact::<ExitButton>))
+                .into(),
+        )
+        // Setup the start menu when GameState::StartMenu is entered
+        .add_enter_system(GameState::StartMenu, setup_start_menu)
+        .add_system_set(
+            ConditionSet::new()
+                // While the start menu is visible..
+                .run_in_state(GameState::StartMenu)
+                // Quit the game if the player presses the menu key
+                .with_system(exit_on_key)
+                // Change the colour of the buttons when the player interacts with them
+                .with_system(button_visual_interact)
+                // Run the associated code when the buttons are clicked
+                .with_system(on_start_button.run_if(button_interact::<StartButton>))
+                .with_system(on_endless_button.run_if(button_interact::<EndlessButton>))
+                .with_system(
+                    on_resume_session_button.run_if(button_interact::<ResumeSessionButton>),
+                )
+                .with_system(on_chart_button.run_if(button_interact::<ChartButton>))
+                .with_system(on_replays_button.run_if(button_interact::<ReplaysButton>))
+                .with_system(on_controls_button.run_if(button_interact::<ControlsButton>))
+                .with_system(on_calibrate_button.run_if(button_interact::<CalibrateButton>))
+                .with_system(on_settings_button.run_if(button_interact::<SettingsButton>))
+                .with_system(on_modifiers_button.run_if(button_interact::<ModifiersButton>))
+                .with_system(on_exit_button.run_if(button_interact::<ExitButton>))
+                .with_system(on_difficulty_button)
+                .with_system(update_difficulty_label)
+                // Nudge the scroll speed with the +/- keys
+                .with_system(adjust_scroll_speed)
+                .with_system(update_scroll_speed_label)
+                // Start the menu music once it has loaded, unless it's already playing
+                .with_system(play_menu_music)
+                .into(),
+        )
+        // Despawn the entire start menu when it is exited
+        .add_exit_system(GameState::StartMenu, despawn_with::<StartMenu>)
+        // Setup the controls menu when GameState::Controls is entered
+        .add_enter_system(GameState::Controls, setup_controls_menu)
+        .add_system_set(
+            ConditionSet::new()
+                // While the controls menu is visible..
+                .run_in_state(GameState::Controls)
+                .with_system(button_visual_interact)
+                .with_system(on_back_button.run_if(button_interact::<BackButton>))
+                .with_system(on_rebind_button)
+                .with_system(capture_rebind_key)
+                .with_system(update_binding_labels)
+                .into(),
+        )
+        // Despawn the entire controls menu when it is exited
+        .add_exit_system(GameState::Controls, despawn_with::<ControlsMenu>)
+        // Setup the calibration screen when GameState::Calibration is entered
+        .add_enter_system(GameState::Calibration, setup_calibration_menu)
+        // The music would drown out the metronome
+        .add_enter_system(GameState::Calibration, stop_menu_music)
+        .add_system_set(
+            ConditionSet::new()
+                // While the calibration screen is visible..
+                .run_in_state(GameState::Calibration)
+                .with_system(button_visual_interact)
+                .with_system(menu_on_key)
+                .with_system(run_calibration)
+                .with_system(move_calibration_note)
+                .with_system(on_back_button.run_if(button_interact::<BackButton>))
+                .with_system(on_save_offset_button.run_if(button_interact::<SaveOffsetButton>))
+                .with_system(on_visual_offset_button)
+                .with_system(update_visual_offset_label)
+                .into(),
+        )
+        // Despawn the entire calibration screen when it is exited
+        .add_exit_system(GameState::Calibration, despawn_with::<CalibrationMenu>)
+        // Setup the settings menu when GameState::Settings is entered
+        .add_enter_system(GameState::Settings, setup_settings_menu)
+        .add_system_set(
+            ConditionSet::new()
+                // While the settings menu is visible..
+                .run_in_state(GameState::Settings)
+                .with_system(button_visual_interact)
+                .with_system(menu_on_key)
+                .with_system(on_back_button.run_if(button_interact::<BackButton>))
+                .with_system(on_settings_page_button)
+                .with_system(update_settings_page)
+                .with_system(on_volume_button)
+                .with_system(drag_sliders)
+                .with_system(update_slider_handles)
+                .with_system(on_volume_slider)
+                .with_system(update_volume_labels)
+                .with_system(on_ghost_tap_button.run_if(button_interact::<GhostTapButton>))
+                .with_system(update_ghost_tap_label)
+                .with_system(on_hit_sound_button.run_if(button_interact::<HitSoundButton>))
+                .with_system(update_hit_sound_label)
+                .with_system(
+                    on_hit_sound_voices_button.run_if(button_interact::<HitSoundVoicesButton>),
+                )
+                .with_system(update_hit_sound_voices_label)
+                .with_system(on_screen_shake_button.run_if(button_interact::<ScreenShakeButton>))
+                .with_system(update_screen_shake_label)
+                .with_system(on_key_labels_button.run_if(button_interact::<KeyLabelsButton>))
+                .with_system(update_key_labels_label)
+                .with_system(
+                    on_training_wheels_button.run_if(button_interact::<TrainingWheelsButton>),
+                )
+                .with_system(update_training_wheels_label)
+                .with_system(
+                    on_charge_scoring_button.run_if(button_interact::<ChargeScoringButton>),
+                )
+                .with_system(update_charge_scoring_label)
+                .with_system(on_judgment_line_button)
+                .with_system(update_judgment_line_label)
+                .with_system(on_streamer_mode_button.run_if(button_interact::<StreamerModeButton>))
+                .with_system(update_streamer_mode_label)
+                .with_system(on_up_scroll_button.run_if(button_interact::<UpScrollButton>))
+                .with_system(update_up_scroll_label)
+                .with_system(on_auto_play_button.run_if(button_interact::<AutoPlayButton>))
+                .with_system(update_auto_play_label)
+                .with_system(on_start_mode_button.run_if(button_interact::<StartModeButton>))
+                .with_system(update_start_mode_label)
+                .with_system(on_column_shapes_button.run_if(button_interact::<ColumnShapesButton>))
+                .with_system(update_column_shapes_label)
+                .with_system(on_hit_window_button.run_if(button_interact::<HitWindowButton>))
+                .with_system(update_hit_window_label)
+                .with_system(on_frame_rate_button.run_if(button_interact::<FrameRateButton>))
+                .with_system(update_frame_rate_label)
+                .with_system(on_lanes_button.run_if(button_interact::<LanesButton>))
+                .with_system(update_lanes_label)
+                .with_system(on_metronome_button.run_if(button_interact::<MetronomeButton>))
+                .with_system(update_metronome_label)
+                .with_system(on_beat_pulse_button.run_if(button_interact::<BeatPulseButton>))
+                .with_system(update_beat_pulse_label)
+                .with_system(
+                    on_timing_markers_button.run_if(button_interact::<TimingMarkersButton>),
+                )
+                .with_system(update_timing_markers_label)
+                .with_system(on_fast_slow_button.run_if(button_interact::<FastSlowButton>))
+                .with_system(update_fast_slow_label)
+                .with_system(on_combo_ducking_button.run_if(button_interact::<ComboDuckingButton>))
+                .with_system(update_combo_ducking_label)
+                .with_system(on_announcements_button.run_if(button_interact::<AnnouncementsButton>))
+                .with_system(update_announcements_label)
+                .with_system(on_scroll_mode_button.run_if(button_interact::<ScrollModeButton>))
+                .with_system(update_scroll_mode_label)
+                .with_system(on_pointer_input_button.run_if(button_interact::<PointerInputButton>))
+                .with_system(update_pointer_input_label)
+                .into(),
+        )
+        // Despawn the entire settings menu when it is exited
+        .add_exit_system(GameState::Settings, despawn_with::<SettingsMenu>)
+        // Setup the modifiers menu when GameState::Modifiers is entered
+        .add_enter_system(GameState::Modifiers, setup_modifiers_menu)
+        .add_system_set(
+            ConditionSet::new()
+                // While the modifiers menu is visible..
+                .run_in_state(GameState::Modifiers)
+                .with_system(button_visual_interact)
+                .with_system(menu_on_key)
+                .with_system(on_back_button.run_if(button_interact::<BackButton>))
+                .with_system(on_modifier_button)
+                .with_system(update_modifier_labels)
+                .into(),
+        )
+        // Despawn the entire modifiers menu when it is exited
+        .add_exit_system(GameState::Modifiers, despawn_with::<ModifiersMenu>)
+        // Find the charts and setup the song select menu when GameState::SongSelect is entered
+        .add_enter_system(GameState::SongSelect, setup_song_select_menu)
+        .add_system_set(
+            ConditionSet::new()
+                // While the song select menu is visible..
+                .run_in_state(GameState::SongSelect)
+                .with_system(button_visual_interact)
+                .with_system(menu_on_key)
+                .with_system(on_back_button.run_if(button_interact::<BackButton>))
+                .with_system(on_chart_slot_button)
+                .with_system(on_page_button)
+                .with_system(update_chart_slots)
+                .with_system(on_practice_button.run_if(button_interact::<PracticeButton>))
+                .with_system(on_practice_bound_button)
+                .with_system(update_practice_labels)
+                .with_system(on_paste_run_code_button.run_if(button_interact::<PasteRunCodeButton>))
+                .into(),
+        )
+        // Despawn the entire song select menu when it is exited
+        .add_exit_system(GameState::SongSelect, despawn_with::<SongSelectMenu>)
+        // Setup the replays menu when GameState::Replays is entered
+        .add_enter_system(GameState::Replays, setup_replays_menu)
+        .add_system_set(
+            ConditionSet::new()
+                // While the replays menu is visible..
+                .run_in_state(GameState::Replays)
+                .with_system(button_visual_interact)
+                .with_system(menu_on_key)
+                .with_system(on_back_button.run_if(button_interact::<BackButton>))
+                .with_system(on_replay_button)
+                .into(),
+        )
+        // Despawn the entire replays menu when it is exited
+        .add_exit_system(GameState::Replays, despawn_with::<ReplaysMenu>)
+        // Give the player their own settings back once they've finished watching a replay
+        .add_enter_system(
+            GameState::StartMenu,
+            end_replay.run_if_resource_exists::<ReplayPlayback>(),
+        )
+        // and once they've finished a rematch
+        .add_enter_system(
+            GameState::StartMenu,
+            end_rematch.run_if_resource_exists::<Rematch>(),
+        )
+        // Setup the game when GameState::Playing is entered, unless it's being resumed
+        .add_enter_system(
+            GameState::Playing,
+            setup_game.run_unless_resource_exists::<ResumeState>(),
+        )
+        // Drop any hits or misses left over from the last run, so they can't count towards this one
+        .add_enter_system(
+            GameState::Playing,
+            clear_events::<TargetHitEvent>.run_unless_resource_exists::<ResumeState>(),
+        )
+        .add_enter_system(
+            GameState::Playing,
+            clear_events::<TargetMissEvent>.run_unless_resource_exists::<ResumeState>(),
+        )
+        .add_enter_system(
+            GameState::Playing,
+            clear_events::<BeatEvent>.run_unless_resource_exists::<ResumeState>(),
+        )
+        .add_enter_system(GameState::Playing, remove_resource::<ResumeState>)
+        .add_enter_system(GameState::Playing, stop_menu_music)
+        .add_system_set(
+            ConditionSet::new()
+                // While the game is running
+                .run_in_state(GameState::Playing)
+                // Exit to the menu when the player presses escape,
+                // or back to song select to pick another section when practicing
+                .with_system(menu_on_key.run_if_not(practicing))
+                .with_system(song_select_on_key.run_if(practicing))
+                .with_system(pause_on_key)
+                .with_system(pause_on_focus_lost)
+                .with_system(restart_on_key)
+                .with_system(run_countdown.run_if_resource_exists::<Countdown>())
+                // Nothing moves or spawns until the countdown is over
+                .with_system(update_targets.run_unless_resource_exists::<Countdown>())
+                .with_system(
+                    shoot_targets
+                        .run_if_not(auto_playing)
+                        .run_unless_resource_exists::<Countdown>(),
+                )
+                .with_system(
+                    auto_play_targets
+                        .run_if(auto_playing)
+                        .run_unless_resource_exists::<Countdown>(),
+                )
+                .with_system(play_hit_sound)
+                .with_system(play_miss_sound)
+                // Beats are only counted while playing,
+                // so the metronome falls silent as soon as the run is paused or over
+                .with_system(track_beats.run_unless_resource_exists::<Countdown>())
+                .with_system(play_metronome.run_if(metronome_enabled))
+                .with_system(pulse_backdrop)
+                .with_system(track_accuracy)
+                .with_system(update_scoreboard)
+                .with_system(update_combo_display)
+                .with_system(update_health_bar)
+                .with_system(flash_crosshairs)
+                .with_system(shake_on_miss)
+                .with_system(spawn_hit_particles)
+                .with_system(spawn_timing_markers.run_if(timing_markers_enabled))
+                .with_system(update_timing_markers)
+                .with_system(record_timing_stats)
+                .with_system(update_timing_stats_display)
+                .with_system(duck_music_on_combo.run_if(combo_ducking_enabled))
+                .with_system(update_particles)
+                .with_system(update_flashes)
+                .with_system(spawn_judgment_text)
+                .with_system(spawn_fast_slow_text.run_if(fast_slow_enabled))
+                .with_system(celebrate_combo_milestones)
+                .with_system(announce_combo_breaks)
+                .with_system(update_floating_text)
+                .with_system(fade_targets)
+                .with_system(glow_targets_in_hit_zone)
+                .with_system(update_key_labels)
+                .with_system(highlight_next_target.run_if(training_wheels_enabled))
+                .with_system(pulse_hit_zone_glows)
+                .with_system(
+                    spawn_targets
+                        .run_if_not(chart_selected)
+                        .run_unless_resource_exists::<Countdown>(),
+                )
+                .with_system(
+                    adapt_spawn_rate
+                        .run_if(adaptive)
+                        .run_if_not(chart_selected)
+                        .run_unless_resource_exists::<Countdown>(),
+                )
+                .with_system(update_spawn_rate_display)
+                .with_system(
+                    track_survival_time
+                        .run_if(endless)
+                        .run_unless_resource_exists::<Countdown>(),
+                )
+                .with_system(
+                    spawn_from_chart
+                        .run_if(chart_selected)
+                        .run_unless_resource_exists::<Countdown>(),
+                )
+                .with_system(pause_song_on_mute.run_if(chart_selected))
+                // Practice runs can't be failed, and loop instead of finishing
+                .with_system(game_over_on_death.run_if_not(practicing))
+                .with_system(update_progress_bar.run_if(chart_selected))
+                // Random mode has no chart to map out
+                .with_system(spawn_density_map.run_if(chart_selected))
+                .with_system(update_density_marker.run_if(chart_selected))
+                .with_system(finish_chart.run_if(chart_selected).run_if_not(practicing))
+                // Closing the window part way through a run saves it, like pausing does
+                .with_system(
+                    save_session
+                        .run_if(window_close_requested)
+                        .run_if(run_can_be_resumed),
+                )
+                // Practice runs never reach the game over menu, so they aren't recorded either
+                .with_system(
+                    record_unfinished_run
+                        .run_if(window_close_requested)
+                        .run_if_not(practicing)
+                        .run_if_not(run_can_be_resumed),
+                )
+                .into(),
+        )
+        // Despawn the entire game when it is exited, unless it's only being paused
+        .add_exit_system(
+            GameState::Playing,
+            despawn_with::<Game>.run_unless_resource_exists::<ResumeState>(),
+        )
+        .add_exit_system(
+            GameState::Playing,
+            stop_song.run_unless_resource_exists::<ResumeState>(),
+        )
+        // Show the pause menu over the frozen game when GameState::Paused is entered
+        .add_enter_system(GameState::Paused, setup_pause_menu)
+        .add_enter_system(GameState::Paused, pause_song)
+        .add_enter_system(GameState::Paused, save_session.run_if(run_can_be_resumed))
+        .add_system_set(
+            ConditionSet::new()
+                // While the game is paused..
+                .run_in_state(GameState::Paused)
+                .with_system(button_visual_interact)
+                .with_system(resume_on_key)
+                .with_system(on_resume_button.run_if(button_interact::<ResumeButton>))
+                .with_system(on_quit_button.run_if(button_interact::<QuitButton>))
+                .with_system(
+                    record_unfinished_run
+                        .run_if(window_close_requested)
+                        .run_if_not(practicing)
+                        .run_if_not(run_can_be_resumed),
+                )
+                .into(),
+        )
+        // Despawn the pause menu when it is exited, and the game too if it was quit
+        .add_exit_system(GameState::Paused, despawn_with::<PauseMenu>)
+        .add_exit_system(
+            GameState::Paused,
+            despawn_with::<Game>.run_unless_resource_exists::<ResumeState>(),
+        )
+        .add_exit_system(
+            GameState::Paused,
+            stop_song.run_unless_resource_exists::<ResumeState>(),
+        )
+        .add_exit_system(
+            GameState::Paused,
+            resume_song.run_if_resource_exists::<ResumeState>(),
+        )
+        // Replays are recorded and played back as soon as input has been read each frame,
+        // so they see the same presses the rest of the game does
+        .add_system_set_to_stage(
+            CoreStage::PreUpdate,
+            ConditionSet::new()
+                .run_in_state(GameState::Playing)
+                .run_unless_resource_exists::<Countdown>()
+                .after(InputSystem)
+                .with_system(record_replay.run_if_resource_exists::<ReplayRecorder>())
+                .with_system(advance_replay.run_if_resource_exists::<ReplayPlayback>())
+                .into(),
+        )
+        // Setup the game over menu when GameState::GameOverMenu is entered
+        .add_enter_system(GameState::GameOverMenu, setup_game_over_menu)
+        .add_enter_system(GameState::GameOverMenu, log_run_stats)
+        .add_enter_system(
+            GameState::GameOverMenu,
+            discard_saved_session.run_if(run_can_be_resumed),
+        )
+        .add_system_set(
+            ConditionSet::new()
+                // While the game over menu is visible..
+                .run_in_state(GameState::GameOverMenu)
+                .with_system(button_visual_interact)
+                .with_system(on_retry_button.run_if(button_interact::<RetryButton>))
+                .with_system(on_main_menu_button.run_if(button_interact::<MainMenuButton>))
+                .with_system(on_save_replay_button.run_if(button_interact::<SaveReplayButton>))
+                .with_system(on_copy_run_code_button.run_if(button_interact::<CopyRunCodeButton>))
+                .into(),
+        )
+        // Despawn the entire game over menu when it is exited
+        .add_exit_system(GameState::GameOverMenu, despawn_with::<GameOverMenu>)
+        // Spawn the camera (for the game and for the UI)
+        .add_startup_system(setup_camera)
+        // Keep the whole playfield visible when the window is resized
+        .add_system(fit_playfield_to_window)
+        // Shakes run in every state, so the camera always settles back to the centre
+        .add_system(update_camera_shake)
+        .init_resource::<MenuAssetHandles>()
+        .init_resource::<TextureAtlasHandles>()
+        .init_resource::<RequiredAssets>()
+        .init_resource::<FailedAssets>()
+        .init_resource::<NoteAudioHandles>()
+        .init_resource::<HitSoundSinks>()
+        .init_resource::<SoundEffectHandles>()
+        .init_resource::<AnnouncementHandles>()
+        .init_resource::<MenuMusic>()
+        .init_resource::<SongPreview>()
+        .init_resource::<Scoreboard>()
+        .init_resource::<SelectedChart>()
+        .init_resource::<Difficulty>()
+        .init_resource::<Modifiers>()
+        .init_resource::<PracticeLoop>()
+        .init_resource::<EndlessMode>()
+        .init_resource::<SurvivalTime>()
+        .init_resource::<TimingStats>()
+        .init_resource::<ShowTimingStats>()
+        .init_resource::<GameConfig>()
+        .insert_resource(persist::load::<ScoringConfig>(SCORING_FILE))
+        .init_resource::<Rebinding>()
+        .init_resource::<FocusedButton>()
+        .insert_resource(
+            persist::load_migrating::<KeyBindings>(KEY_BINDINGS_FILE, number_colour_columns)
+                .with_default_columns(),
+        )
+        .insert_resource(persist::load::<AudioOffset>(AUDIO_OFFSET_FILE))
+        .insert_resource(persist::load::<VisualOffset>(VISUAL_OFFSET_FILE))
+        .insert_resource(persist::load::<Volume>(VOLUME_FILE))
+        .insert_resource(persist::load::<PunishGhostTaps>(GHOST_TAPS_FILE))
+        .insert_resource(persist::load::<HitSounds>(HIT_SOUNDS_FILE))
+        .insert_resource(persist::load::<HitSoundVoices>(HIT_SOUND_VOICES_FILE))
+        .insert_resource(persist::load::<ScreenShake>(SCREEN_SHAKE_FILE))
+        .insert_resource(persist::load::<KeyLabels>(KEY_LABELS_FILE))
+        .insert_resource(persist::load::<TrainingWheels>(TRAINING_WHEELS_FILE))
+        .insert_resource(persist::load::<ChargeScoring>(CHARGE_SCORING_FILE))
+        .insert_resource(persist::load::<JudgmentLine>(JUDGMENT_LINE_FILE))
+        .insert_resource(streamer_mode)
+        .insert_resource(persist::load::<UpScroll>(UP_SCROLL_FILE))
+        .insert_resource(persist::load::<ScrollSpeed>(SCROLL_SPEED_FILE))
+        .insert_resource(persist::load::<Metronome>(METRONOME_FILE))
+        .insert_resource(persist::load::<HitWindow>(HIT_WINDOW_FILE))
+        .insert_resource(persist::load::<FrameRate>(FRAME_RATE_FILE))
+        .insert_resource(persist::load::<Lanes>(LANES_FILE))
+        .insert_resource(persist::load::<ColumnShapes>(COLUMN_SHAPES_FILE))
+        .insert_resource(persist::load::<StartMode>(START_MODE_FILE))
+        .insert_resource(persist::load::<BeatPulse>(BEAT_PULSE_FILE))
+        .insert_resource(persist::load::<TimingMarkers>(TIMING_MARKERS_FILE))
+        .insert_resource(persist::load::<FastSlow>(FAST_SLOW_FILE))
+        .insert_resource(persist::load::<ComboDucking>(COMBO_DUCKING_FILE))
+        .insert_resource(persist::load::<Announcements>(ANNOUNCEMENTS_FILE))
+        .insert_resource(persist::load::<PointerInput>(POINTER_INPUT_FILE))
+        .insert_resource(persist::load::<Theme>(THEME_FILE))
+        .insert_resource(AutoPlay::from_args())
+        .add_asset::<Beatmap>()
+        .init_asset_loader::<BeatmapLoader>()
+        .add_startup_system(load_assets)
+        .add_startup_system(load_high_score)
+        // Let the player know when a controller comes or goes
+        .add_system(log_gamepad_connections)
+        // Mute and unmute everything with M, in every state
+        .add_startup_system(setup_mute_indicator)
+        .add_system(toggle_mute)
+        .add_system(apply_music_volume)
+        // Show the FPS and frame time with F3, in every state
+        .add_startup_system(setup_diagnostics_overlay)
+        .add_system(toggle_diagnostics_overlay)
+        .add_system(update_diagnostics_overlay)
+        .add_system(toggle_timing_stats)
+        .add_system(exit_on_close_requested)
+        // Previews run outside the song select menu too, so they can fade out after leaving it
+        .add_system(preview_hovered_song)
+        // Announce how runs are going, for players who can't easily follow the screen
+        .add_system(play_announcements.run_if(announcements_enabled))
+        // Keep the frame rate to the player's choice, in every state
+        .add_system(apply_frame_rate)
+        .add_system_to_stage(CoreStage::Last, limit_frame_rate)
+        // Fade in from black after every state change
+        .add_system(fade_in_on_state_change)
+        .add_system(update_fade_overlay)
+        // Move between and click menu buttons with the keyboard, in every menu.
+        // This runs right after the UI has checked the mouse, so a button clicked with Enter
+        // isn't reset before the menu's systems see it
+        .add_system_to_stage(CoreStage::PreUpdate, navigate_menus.after(UiSystem::Focus))
+        // Targets are only moved where they're drawn, once their real positions are settled
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            apply_visual_offset.after(TransformSystem::TransformPropagate),
+        )
+        .run();
+}
+
+/// How long the screen takes to fade in from black after a state change
+const FADE_DURATION: Duration = Duration::from_millis(150);
+
+/// Whether a state change should be faded.
+/// Pausing and resuming happen instantly, so the game is never hidden behind a fade
+fn fades_between(from: GameState, to: GameState) -> bool {
+    from != GameState::Paused && to != GameState::Paused
+}
+
+/// Covers the screen in black when the state changes, to fade in from.
+///
+/// The state changes straight away rather than waiting for a fade to black, so the old state's
+/// input, scoring and buttons are never left running behind the fade
+fn fade_in_on_state_change(
+    mut commands: Commands,
+    current_state: Res<CurrentState<GameState>>,
+    mut previous_state: Local<Option<GameState>>,
+    overlays: Query<Entity, With<FadeOverlay>>,
+) {
+    if !current_state.is_changed() {
+        return;
+    }
+    let previous = previous_state.replace(current_state.0);
+    if previous.is_some_and(|previous| !fades_between(previous, current_state.0)) {
+        return;
+    }
+
+    for overlay in &overlays {
+        commands.entity(overlay).despawn_recursive();
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor(Color::BLACK),
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            // Clicks go straight through, so the fade never gets in the way
+            focus_policy: FocusPolicy::Pass,
+            ..Default::default()
+        })
+        .insert(FadeOverlay(Timer::new(FADE_DURATION, false)));
+}
+
+/// Fades the overlay out, despawning it once it's clear
+fn update_fade_overlay(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut overlays: Query<(Entity, &mut FadeOverlay, &mut UiColor)>,
+) {
+    for (entity, mut fade, mut colour) in &mut overlays {
+        fade.0.tick(time.delta());
+        colour.0.set_a(1.0 - fade.0.percent());
+
+        if fade.0.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Recursively despawns every entity with a given component
+fn despawn_with<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
+    for e in q.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+/// Removes a resource if it exists
+fn remove_resource<T: Send + Sync + 'static>(mut commands: Commands) {
+    commands.remove_resource::<T>();
+}
+
+/// Clears any events of a type that haven't been read yet
+fn clear_events<T: Send + Sync + 'static>(mut events: ResMut<Events<T>>) {
+    events.clear();
+}
+
+/// Spawn a 2D camera
+fn setup_camera(mut commands: Commands) {
+    commands
+        .spawn_bundle(Camera2dBundle::default())
+        .insert(CameraShake::default());
+}
+
+/// Zooms the camera so the playfield fills as much of the window as it can without being cut off.
+///
+/// Everything in the game is positioned for a window the size of the playfield, so scaling the
+/// view rather than the positions keeps targets lined up with the crosshairs at any size
+fn fit_playfield_to_window(
+    mut resize_events: EventReader<WindowResized>,
+    mut projection_query: Query<&mut OrthographicProjection, With<Camera2d>>,
+) {
+    if let Some(resize) = resize_events.iter().rfind(|resize| resize.id.is_primary()) {
+        let scale = (PLAYFIELD_WIDTH / resize.width).max(PLAYFIELD_HEIGHT / resize.height);
+        for mut projection in projection_query.iter_mut() {
+            projection.scale = scale;
+        }
+    }
+}
+
+fn load_assets(
+    asset_server: Res<AssetServer>,
+    mut menu_asset_handles: ResMut<MenuAssetHandles>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut atlas_handles: ResMut<TextureAtlasHandles>,
+    mut audio_handles: ResMut<NoteAudioHandles>,
+    mut sfx_handles: ResMut<SoundEffectHandles>,
+    mut announcement_handles: ResMut<AnnouncementHandles>,
+    mut menu_music: ResMut<MenuMusic>,
+    mut required_assets: ResMut<RequiredAssets>,
+) {
+    let logo = asset_server.load("textures/logo.png");
+    let font = asset_server.load("fonts/comic.ttf");
+    required_assets.0.push(logo.clone_untyped());
+    required_assets.0.push(font.clone_untyped());
+    menu_asset_handles.logo = Some(logo);
+    menu_asset_handles.font = Some(font);
+
+    let crosshair_texture_handle: Handle<Image> = asset_server.load("textures/crosshairs.png");
+    required_assets
+        .0
+        .push(crosshair_texture_handle.clone_untyped());
+    let crosshair_texture_atlas =
+        TextureAtlas::from_grid(crosshair_texture_handle, Vec2::new(64.0, 64.0), 4, 1);
+    let crosshair_atlas_handle = texture_atlases.add(crosshair_texture_atlas);
+
+    let target_texture_handle: Handle<Image> = asset_server.load("textures/targets.png");
+    required_assets
+        .0
+        .push(target_texture_handle.clone_untyped());
+    let target_texture_atlas =
+        TextureAtlas::from_grid(target_texture_handle, Vec2::new(64.0, 64.0), 4, 1);
+    let target_atlas_handle = texture_atlases.add(target_texture_atlas);
+
+    atlas_handles.crosshairs = Some(crosshair_atlas_handle);
+    atlas_handles.targets = Some(target_atlas_handle);
+
+    audio_handles.0 = ["yellow", "red", "blue", "green"]
+        .into_iter()
+        .map(|name| asset_server.load(format!("sounds/notes/{}.ogg", name).as_str()))
+        .collect();
+
+    sfx_handles.tick = Some(asset_server.load("sounds/sfx/tick.wav"));
+    sfx_handles.miss = Some(asset_server.load("sounds/sfx/miss.wav"));
+
+    required_assets.0.extend(
+        audio_handles
+            .0
+            .iter()
+            .chain(&sfx_handles.tick)
+            .chain(&sfx_handles.miss)
+            .map(|handle| handle.clone_untyped()),
+    );
+
+    // The menu music is left out of the required assets, the menus are fine without it
+    menu_music.track = Some(asset_server.load("sounds/songs/menu.wav"));
+
+    // So are the announcements, which are off unless the player asks for them
+    announcement_handles.0 = Announcement::ALL
+        .into_iter()
+        .map(|announcement| (announcement, asset_server.load(announcement.path())))
+        .collect();
+}
+
+/// The width of the loading bar, in pixels
+const LOADING_BAR_WIDTH: f32 = 300.0;
+
+/// Spawns an empty loading bar, which [`update_loading_screen`] fills in
+fn setup_loading_screen(mut commands: Commands) {
+    let menu = commands
+        .spawn_bundle(menu_node())
+        .insert(LoadingScreen)
+        .id();
+
+    let bar = commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor(Color::rgb(0.2, 0.2, 0.2)),
+            style: Style {
+                size: Size::new(Val::Px(LOADING_BAR_WIDTH), Val::Px(24.0)),
+                margin: UiRect::all(Val::Px(16.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    color: UiColor(Color::rgb(0.9, 0.9, 0.9)),
+                    style: Style {
+                        size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(LoadingBar);
+        })
+        .id();
+
+    commands.entity(menu).add_child(bar);
+}
+
+/// Fills the loading bar with the share of required assets that have loaded,
+/// and adds the loading text as soon as there's a font to write it in
+fn update_loading_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    asset_handles: Res<MenuAssetHandles>,
+    required_assets: Res<RequiredAssets>,
+    menu: Query<Entity, With<LoadingScreen>>,
+    label: Query<(), With<LoadingLabel>>,
+    mut bar: Query<&mut Style, With<LoadingBar>>,
+) {
+    let loaded = required_assets
+        .0
+        .iter()
+        .filter(|handle| asset_server.get_load_state(*handle) == LoadState::Loaded)
+        .count();
+    let progress = loaded as f32 / required_assets.0.len().max(1) as f32;
+    for mut style in &mut bar {
+        style.size.width = Val::Percent(progress * 100.0);
+    }
+
+    if !label.is_empty() {
+        return;
+    }
+    if let Some(font) = &asset_handles.font {
+        if asset_server.get_load_state(font) == LoadState::Loaded {
+            for menu in &menu {
+                let label = spawn_label(&mut commands, font, "Loading…", 36.0);
+                commands.entity(label).insert(LoadingLabel);
+                commands.entity(menu).insert_children(0, &[label]);
+            }
+        }
+    }
+}
+
+/// Moves on to the start menu once every required asset has loaded,
+/// or to the error screen if any of them couldn't be
+fn check_assets_loaded(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    required_assets: Res<RequiredAssets>,
+    mut failed_assets: ResMut<FailedAssets>,
+) {
+    match asset_server.get_group_load_state(required_assets.0.iter().map(|handle| handle.id)) {
+        LoadState::Loaded => commands.insert_resource(NextState(GameState::StartMenu)),
+        LoadState::Failed => {
+            failed_assets.0 = required_assets
+                .0
+                .iter()
+                .filter(|handle| asset_server.get_load_state(*handle) == LoadState::Failed)
+                .map(|handle| {
+                    asset_server
+                        .get_handle_path(handle)
+                        .map(|path| path.path().display().to_string())
+                        .unwrap_or_else(|| "an unknown asset".to_string())
+                })
+                .collect();
+            for path in &failed_assets.0 {
+                error!("Failed to load {}", path);
+            }
+            commands.insert_resource(NextState(GameState::LoadFailed));
+        }
+        // Still loading
+        _ => {}
+    }
+}
+
+/// The style shared by every menu button
+fn button_style() -> Style {
+    Style {
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        padding: UiRect::all(Val::Px(8.0)),
+        margin: UiRect::all(Val::Px(4.0)),
+        flex_grow: 1.0,
+        ..Default::default()
+    }
+}
+
+/// The text style shared by every menu button
+fn button_textstyle(font: &Handle<Font>) -> TextStyle {
+    TextStyle {
+        font: font.clone(),
+        font_size: 36.0,
+        color: Color::BLACK,
+    }
+}
+
+/// The grey box that every menu is laid out in
+fn menu_node() -> NodeBundle {
+    NodeBundle {
+        color: UiColor(Color::rgb(0.5, 0.5, 0.5)),
+        style: Style {
+            size: Size::new(Val::Auto, Val::Auto),
+            margin: UiRect::all(Val::Auto),
+            align_self: AlignSelf::Center,
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Spawns a labelled menu button with the given marker component
+fn spawn_button<B: Component>(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    label: &str,
+    marker: B,
+) -> Entity {
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: button_style(),
+            ..Default::default()
+        })
+        .with_children(|btn| {
+            btn.spawn_bundle(TextBundle {
+                text: Text::from_section(label, button_textstyle(font)),
+                ..Default::default()
+            });
+        })
+        .insert(marker)
+        .insert(OldInteraction(Interaction::None))
+        .id()
+}
+
+/// The width of a slider's track, in pixels
+const SLIDER_WIDTH: f32 = 200.0;
+
+/// The width of a slider's handle, in pixels
+const SLIDER_HANDLE_WIDTH: f32 = 16.0;
+
+/// Spawns a horizontal slider starting at `value`, with the given marker component
+fn spawn_slider<M: Component>(commands: &mut Commands, value: f32, marker: M) -> Entity {
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(SLIDER_WIDTH), Val::Px(16.0)),
+                margin: UiRect::all(Val::Px(12.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|track| {
+            track
+                .spawn_bundle(NodeBundle {
+                    color: UiColor(Color::rgb(0.3, 0.3, 0.3)),
+                    style: Style {
+                        size: Size::new(Val::Px(SLIDER_HANDLE_WIDTH), Val::Px(24.0)),
+                        position_type: PositionType::Absolute,
+                        position: slider_handle_position(value),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(SliderHandle);
+        })
+        .insert(Slider(value))
+        .insert(marker)
+        .insert(OldInteraction(Interaction::None))
+        .id()
+}
+
+/// Where a slider's handle sits on its track, centred on the value
+fn slider_handle_position(value: f32) -> UiRect<Val> {
+    UiRect {
+        left: Val::Px(value * SLIDER_WIDTH - SLIDER_HANDLE_WIDTH / 2.0),
+        top: Val::Px(-4.0),
+        ..Default::default()
+    }
+}
+
+/// Spawns a button for a setting, whose text shows the setting's current value
+/// and is marked with `label_marker` so it can be kept up to date
+fn spawn_setting_button<B: Component, L: Component>(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    label: &str,
+    marker: B,
+    label_marker: L,
+) -> Entity {
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: button_style(),
+            ..Default::default()
+        })
+        .with_children(|btn| {
+            btn.spawn_bundle(TextBundle {
+                text: Text::from_section(
+                    label,
+                    TextStyle {
+                        font_size: 24.0,
+                        ..button_textstyle(font)
+                    },
+                ),
+                ..Default::default()
+            })
+            .insert(label_marker);
+        })
+        .insert(marker)
+        .insert(OldInteraction(Interaction::None))
+        .id()
+}
+
+/// Spawns a line of centred menu text
+fn spawn_label(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    value: impl Into<String>,
+    font_size: f32,
+) -> Entity {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::from_section(
+                value,
+                TextStyle {
+                    font: font.clone(),
+                    font_size,
+                    color: Color::BLACK,
+                },
+            ),
+            style: Style {
+                margin: UiRect::all(Val::Px(8.0)),
+                align_self: AlignSelf::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .id()
+}
+
+/// Loads the player's best score, treating a missing or corrupt file as 0
+fn load_high_score(mut commands: Commands) {
+    let high_score = persist::load::<HighScore>(HIGH_SCORE_FILE);
+    // Write it straight back so that the file exists (and is valid) from now on
+    persist::save(HIGH_SCORE_FILE, &high_score);
+    commands.insert_resource(high_score);
+}
+
+/// Logs gamepads being connected and disconnected.
+/// Disconnected pads drop out of [`Gamepads`], so [`ColumnInput`] falls back to the keyboard
+fn log_gamepad_connections(mut gamepad_events: EventReader<GamepadEvent>) {
+    for event in gamepad_events.iter() {
+        match event.event_type {
+            GamepadEventType::Connected => info!("{:?} connected", event.gamepad),
+            GamepadEventType::Disconnected => {
+                info!(
+                    "{:?} disconnected, falling back to the keyboard",
+                    event.gamepad
+                )
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Spawns the muted indicator in the top right corner, hidden until the game is muted.
+/// It isn't marked with any state's component, so it's never despawned
+fn setup_mute_indicator(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::from_section(
+                "Muted",
+                TextStyle {
+                    font: asset_server.load("fonts/comic.ttf"),
+                    font_size: 20.0,
+                    color: Color::rgb(0.7, 0.7, 0.7),
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(8.0),
+                    right: Val::Px(8.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(MuteIndicator);
+}
+
+/// Toggles muting when the mute key is pressed
+fn toggle_mute(
+    input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    mut volume: ResMut<Volume>,
+    mut indicator_query: Query<&mut Visibility, With<MuteIndicator>>,
+) {
+    // The mute key might be the key the player is trying to bind
+    if rebinding.0.is_some() || !key_bindings.just_pressed(&input, Action::Mute) {
+        return;
+    }
+
+    volume.muted = !volume.muted;
+
+    for mut visibility in indicator_query.iter_mut() {
+        visibility.is_visible = volume.muted;
+    }
+}
+
+/// Updates the volume of any music that's already playing when the volume is changed,
+/// and pauses the menu music while the game is muted.
+///
+/// A chart's song is paused by [`pause_song_on_mute`] instead, as it has to catch up afterwards
+fn apply_music_volume(
+    volume: Res<Volume>,
+    menu_music: Res<MenuMusic>,
+    playback: Option<Res<ChartPlayback>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+) {
+    if !volume.is_changed() {
+        return;
+    }
+
+    let menu_sink = menu_music
+        .sink
+        .as_ref()
+        .and_then(|handle| audio_sinks.get(handle));
+    if let Some(sink) = menu_sink {
+        if volume.muted {
+            sink.pause();
+        } else {
+            sink.play();
+        }
+    }
+
+    let song_sink = playback.and_then(|playback| playback.song_sink(&audio_sinks));
+    for sink in menu_sink.into_iter().chain(song_sink) {
+        sink.set_volume(volume.music_volume());
+    }
+}
+
+/// Pauses the chart's song while the game is muted. The chart carries on without it,
+/// so once it's unmuted the song starts again from wherever the chart has got to.
+///
+/// Songs that can't be cut are only turned down, so they stay in time with the notes
+fn pause_song_on_mute(
+    volume: Res<Volume>,
+    mut playback: ResMut<ChartPlayback>,
+    mut song_player: SongPlayer,
+) {
+    let song = match playback.song.clone() {
+        Some(song) => song,
+        None => return,
+    };
+
+    if volume.muted {
+        if !playback.song_behind && song_player.can_cut(&song) {
+            if let Some(sink) = playback.song_sink(&song_player.sinks) {
+                sink.pause();
+                playback.song_behind = true;
+            }
+        }
+    } else if playback.song_behind {
+        playback.song_behind = false;
+        if let Some(sink) = playback.song_sink(&song_player.sinks) {
+            sink.stop();
+        }
+        let start_ms = playback.song_time_ms().max(0) as u32;
+        playback.song_sink = song_player.play_from(&song, start_ms);
+    }
+}
+
+/// Loops the menu music, once it has loaded, if it isn't playing already
+fn play_menu_music(
+    mut menu_music: ResMut<MenuMusic>,
+    audio: Res<Audio>,
+    audio_sources: Res<Assets<AudioSource>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    volume: Res<Volume>,
+) {
+    if menu_music.sink.is_some() {
+        return;
+    }
+
+    let track = match &menu_music.track {
+        Some(track) if audio_sources.get(track).is_some() => track.clone(),
+        // Try again next frame
+        _ => return,
+    };
+
+    let settings = PlaybackSettings::LOOP.with_volume(volume.music_volume());
+    menu_music.sink = Some(audio_sinks.get_handle(audio.play_with_settings(track, settings)));
+}
+
+/// Plays a preview of the song of whichever chart is hovered or focused in the song select menu,
+/// fading the menu music out under it.
+///
+/// Only one preview plays at a time. When a different chart is hovered, the preview that's
+/// playing fades out before the next one starts, so quickly moving between charts doesn't pile
+/// them up. Songs can only be played from the start, so each preview is cut out of its song
+fn preview_hovered_song(
+    slot_query: Query<(Entity, &Interaction, &ChartSlotButton)>,
+    focused: Res<FocusedButton>,
+    chart_list: Option<Res<ChartList>>,
+    beatmaps: Res<Assets<Beatmap>>,
+    asset_server: Res<AssetServer>,
+    mut audio_sources: ResMut<Assets<AudioSource>>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    volume: Res<Volume>,
+    menu_music: Res<MenuMusic>,
+    time: Res<Time>,
+    mut preview: ResMut<SongPreview>,
+) {
+    // The slots only exist in the song select menu, so nothing's hovered anywhere else
+    let hovered = chart_list.as_ref().and_then(|chart_list| {
+        slot_query
+            .iter()
+            .find(|(entity, interaction, _)| {
+                **interaction != Interaction::None || focused.0 == Some(*entity)
+            })
+            .and_then(|(.., ChartSlotButton(slot))| chart_list.chart_in_slot(*slot))
+            .cloned()
+    });
+
+    let fade_step = time.delta_seconds() / PREVIEW_FADE.as_secs_f32();
+    let preview = &mut *preview;
+    match &preview.playing {
+        Some((chart, sink)) => {
+            preview.elapsed += time.delta();
+            let ending = preview.elapsed + PREVIEW_FADE >= PREVIEW_LENGTH;
+            if hovered.as_ref() == Some(chart) && !ending {
+                preview.fade = (preview.fade + fade_step).min(1.0);
+            } else {
+                preview.fade = (preview.fade - fade_step).max(0.0);
+            }
+
+            let sink = audio_sinks.get(sink);
+            if let Some(sink) = sink {
+                sink.set_volume(volume.music_volume() * preview.fade);
+            }
+            if preview.fade <= 0.0 {
+                if let Some(sink) = sink {
+                    sink.stop();
+                }
+                preview.playing = None;
+            }
+        }
+        None => {
+            let chart = match hovered {
+                Some(chart) => chart,
+                None => {
+                    preview.loading = None;
+                    return;
+                }
+            };
+
+            if !preview.clips.contains_key(&chart) {
+                let beatmap = match beatmaps.get(&chart) {
+                    Some(beatmap) => beatmap,
+                    None => return,
+                };
+                let song = match &beatmap.song {
+                    Some(song) => preview.loading.insert(asset_server.load(song.as_str())),
+                    // There's nothing to preview
+                    None => return,
+                };
+                let source = match audio_sources.get(song) {
+                    Some(source) => source,
+                    // Try again next frame
+                    None => return,
+                };
+
+                let start_ms = beatmap
+                    .preview_ms
+                    .or_else(|| beatmap.notes.first().map(|note| note.time_ms))
+                    .unwrap_or(0);
+                // A preview from past the end of the song would be silent, so it wraps round
+                let start_ms = match wav::length_ms(&source.bytes) {
+                    Some(song_ms) if start_ms >= song_ms => 0,
+                    _ => start_ms,
+                };
+                let length_ms = PREVIEW_LENGTH.as_millis() as u32;
+                // Songs that aren't WAV files can't be cut, so they're previewed from the start
+                let clip = match wav::clip(&source.bytes, start_ms, length_ms) {
+                    Some(clip) => audio_sources.add(AudioSource { bytes: clip.into() }),
+                    None => song.clone(),
+                };
+                preview.clips.insert(chart.clone(), clip);
+                preview.loading = None;
+            }
+
+            let clip = preview.clips[&chart].clone();
+            let sink = audio.play_with_settings(clip, PlaybackSettings::ONCE.with_volume(0.0));
+            preview.playing = Some((chart, audio_sinks.get_handle(sink)));
+            preview.fade = 0.0;
+            preview.elapsed = Duration::ZERO;
+        }
+    }
+
+    // The menu music makes way for the preview, and comes back as it fades out
+    if let Some(menu_sink) = menu_music
+        .sink
+        .as_ref()
+        .and_then(|handle| audio_sinks.get(handle))
+    {
+        menu_sink.set_volume(volume.music_volume() * (1.0 - preview.fade));
+    }
+}
+
+/// Stops the menu music, so that it starts from the beginning next time
+fn stop_menu_music(mut menu_music: ResMut<MenuMusic>, audio_sinks: Res<Assets<AudioSink>>) {
+    if let Some(sink) = menu_music
+        .sink
+        .take()
+        .and_then(|handle| audio_sinks.get(&handle))
+    {
+        sink.stop();
+    }
+}
+
+/// Spawns the FPS and frame time overlay in the top left corner, hidden until F3 is pressed
+fn setup_diagnostics_overlay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/comic.ttf"),
+                    font_size: 16.0,
+                    color: Color::rgb(0.7, 0.7, 0.7),
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(8.0),
+                    left: Val::Px(8.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(DiagnosticsOverlay);
+}
+
+/// Shows or hides the diagnostics overlay when F3 is pressed
+fn toggle_diagnostics_overlay(
+    input: Res<Input<KeyCode>>,
+    rebinding: Res<Rebinding>,
+    mut overlay_query: Query<&mut Visibility, With<DiagnosticsOverlay>>,
+) {
+    // F3 might be the key the player is trying to bind
+    if rebinding.0.is_some() || !input.just_pressed(KeyCode::F3) {
+        return;
+    }
+
+    for mut visibility in overlay_query.iter_mut() {
+        visibility.is_visible = !visibility.is_visible;
+    }
+}
+
+/// Shows or hides the timing statistics when the timing stats key is pressed
+fn toggle_timing_stats(
+    input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    mut show_timing_stats: ResMut<ShowTimingStats>,
+) {
+    // The key might be the one the player is trying to bind
+    if rebinding.0.is_none() && key_bindings.just_pressed(&input, Action::TimingStats) {
+        show_timing_stats.0 = !show_timing_stats.0;
+    }
+}
+
+/// Adds every hit's timing error to the run's statistics
+fn record_timing_stats(
+    mut timing_event_reader: EventReader<HitTimingEvent>,
+    mut timing_stats: ResMut<TimingStats>,
+) {
+    for HitTimingEvent(_, _, error_ms) in timing_event_reader.iter() {
+        timing_stats.record(*error_ms);
+    }
+}
+
+/// Keeps the timing statistics up to date, and shows or hides them
+fn update_timing_stats_display(
+    timing_stats: Res<TimingStats>,
+    show_timing_stats: Res<ShowTimingStats>,
+    mut display_query: Query<(&mut Text, &mut Visibility), With<TimingStatsDisplay>>,
+) {
+    for (mut text, mut visibility) in &mut display_query {
+        if timing_stats.is_changed() {
+            text.sections[0].value = timing_stats.label();
+        }
+        if visibility.is_visible != show_timing_stats.0 {
+            visibility.is_visible = show_timing_stats.0;
+        }
+    }
+}
+
+/// Fills in the diagnostics overlay while it's visible
+fn update_diagnostics_overlay(
+    diagnostics: Res<Diagnostics>,
+    mut overlay_query: Query<(&mut Text, &Visibility), With<DiagnosticsOverlay>>,
+) {
+    let average = |id| {
+        diagnostics
+            .get(id)
+            .and_then(|diagnostic| diagnostic.average())
+            .unwrap_or_default()
+    };
+
+    for (mut text, visibility) in overlay_query.iter_mut() {
+        if visibility.is_visible {
+            text.sections[0].value = format!(
+                "FPS: {:.0}\nFrame time: {:.2}ms",
+                average(FrameTimeDiagnosticsPlugin::FPS),
+                average(FrameTimeDiagnosticsPlugin::FRAME_TIME) * 1000.0,
+            );
+        }
+    }
+}
+
+/// Spawn the start menu ui
+fn setup_start_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    difficulty: Res<Difficulty>,
+    scroll_speed: Res<ScrollSpeed>,
+) {
+    if let MenuAssetHandles {
+        logo: Some(logo),
+        font: Some(font),
+    } = &*asset_handles
+    {
+        let menu = commands.spawn_bundle(menu_node()).insert(StartMenu).id();
+
+        let logo = commands
+            .spawn_bundle(ImageBundle {
+                image: logo.clone().into(),
+                style: Style {
+                    size: Size::new(Val::Px(200.0), Val::Px(200.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+
+        // A run that was interrupted can be picked up from where it was left, next to starting afresh
+        // with or without a chart
+        let start_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        let start_button = spawn_button(&mut commands, font, "Start Game", StartButton);
+        let endless_button = spawn_button(&mut commands, font, "Endless", EndlessButton);
+        commands
+            .entity(start_row)
+            .push_children(&[start_button, endless_button]);
+        if persist::try_load::<SavedSession>(SESSION_FILE).is_some() {
+            let resume_button = spawn_button(
+                &mut commands,
+                font,
+                "Resume Last Session",
+                ResumeSessionButton,
+            );
+            commands.entity(start_row).add_child(resume_button);
+        }
+
+        let difficulty_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        for preset in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+            let button = spawn_button(
+                &mut commands,
+                font,
+                &format!("{:?}", preset),
+                DifficultyButton(preset),
+            );
+            commands.entity(difficulty_row).add_child(button);
+        }
+        let difficulty_label = spawn_label(
+            &mut commands,
+            font,
+            format!("Difficulty: {:?}", *difficulty),
+            24.0,
+        );
+        commands.entity(difficulty_label).insert(DifficultyLabel);
+        let scroll_speed_label = spawn_label(&mut commands, font, scroll_speed.label(), 24.0);
+        commands.entity(scroll_speed_label).insert(ScrollSpeedLabel);
+        // Charts and their replays share a row too
+        let chart_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        let chart_button = spawn_button(&mut commands, font, "Play Chart", ChartButton);
+        let replays_button = spawn_button(&mut commands, font, "Replays", ReplaysButton);
+        commands
+            .entity(chart_row)
+            .push_children(&[chart_button, replays_button]);
+        let controls_button = spawn_button(&mut commands, font, "Controls", ControlsButton);
+        let calibrate_button = spawn_button(&mut commands, font, "Calibrate", CalibrateButton);
+        // Settings and modifiers share a row, so the menu still fits in the window
+        let settings_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        let settings_button = spawn_button(&mut commands, font, "Settings", SettingsButton);
+        let modifiers_button = spawn_button(&mut commands, font, "Modifiers", ModifiersButton);
+        commands
+            .entity(settings_row)
+            .push_children(&[settings_button, modifiers_button]);
+        let exit_button = spawn_button(&mut commands, font, "Exit Game", ExitButton);
+
+        commands.entity(menu).push_children(&[
+            logo,
+            start_row,
+            difficulty_row,
+            difficulty_label,
+            scroll_speed_label,
+            chart_row,
+            controls_button,
+            calibrate_button,
+            settings_row,
+            exit_button,
+        ]);
+    }
+}
+
+/// Spawns a button showing what's bound to a column or action, which rebinds it when clicked
+fn spawn_rebind_button(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    key_bindings: &KeyBindings,
+    binding: Binding,
+) -> Entity {
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: button_style(),
+            ..Default::default()
+        })
+        .with_children(|btn| {
+            btn.spawn_bundle(TextBundle {
+                text: Text::from_section(
+                    format!("{}: {}", binding, key_bindings.binding_label(binding)),
+                    TextStyle {
+                        font_size: 24.0,
+                        ..button_textstyle(font)
+                    },
+                ),
+                ..Default::default()
+            })
+            .insert(BindingLabel(binding));
+        })
+        .insert(RebindButton(binding))
+        .insert(OldInteraction(Interaction::None))
+        .id()
+}
+
+/// Spawn the controls menu ui, with a rebind button for each column and action
+fn setup_controls_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    key_bindings: Res<KeyBindings>,
+    lanes: Res<Lanes>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    rebinding.0 = None;
+
+    if let Some(font) = &asset_handles.font {
+        let menu = commands.spawn_bundle(menu_node()).insert(ControlsMenu).id();
+
+        let title = spawn_label(&mut commands, font, "Controls", 48.0);
+        commands.entity(menu).add_child(title);
+
+        // Each column's keys share a row
+        for column in lanes.columns() {
+            let slot_buttons: Vec<Entity> = (0..KEYS_PER_COLUMN)
+                .map(|slot| {
+                    let binding = Binding::Column(column, slot);
+                    spawn_rebind_button(&mut commands, font, &key_bindings, binding)
+                })
+                .collect();
+            let row = commands
+                .spawn_bundle(NodeBundle {
+                    color: UiColor(Color::NONE),
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .id();
+            commands.entity(row).push_children(&slot_buttons);
+            commands.entity(menu).add_child(row);
+        }
+
+        // The other controls are shorter, so they fit two to a row
+        let action_buttons: Vec<Entity> = Action::ALL
+            .into_iter()
+            .map(|action| {
+                spawn_rebind_button(&mut commands, font, &key_bindings, Binding::Action(action))
+            })
+            .collect();
+        for pair in action_buttons.chunks(2) {
+            let row = commands
+                .spawn_bundle(NodeBundle {
+                    color: UiColor(Color::NONE),
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .id();
+            commands.entity(row).push_children(pair);
+            commands.entity(menu).add_child(row);
+        }
+
+        let feedback = spawn_label(&mut commands, font, "Click a control to rebind it", 24.0);
+        commands.entity(feedback).insert(ControlsFeedback);
+
+        let back_button = spawn_button(&mut commands, font, "Back", BackButton);
+
+        commands
+            .entity(menu)
+            .push_children(&[feedback, back_button]);
+    }
+}
+
+/// Spawn the game over menu ui, showing the final score and saving it if it's a new best
+fn setup_game_over_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    result: Res<GameResult>,
+    replay_recorder: Option<Res<ReplayRecorder>>,
+    rematch: Option<Res<Rematch>>,
+    mut record: RunRecord,
+    mut announcement_writer: EventWriter<AnnouncementEvent>,
+) {
+    let (new_high_score, new_best_time) = record.save_high_score();
+    // A new best is the bigger news, so it's announced instead of how the run ended
+    let announcement = match *result {
+        _ if new_high_score || new_best_time => Some(Announcement::HighScore),
+        GameResult::Cleared => Some(Announcement::Cleared),
+        GameResult::Failed => Some(Announcement::Failed),
+        GameResult::Quit => None,
+    };
+    if let Some(announcement) = announcement {
+        announcement_writer.send(AnnouncementEvent(announcement));
+    }
+    let RunRecord {
+        score,
+        high_score,
+        game_rng,
+        chart,
+        modifiers,
+        column_mapping,
+        survival_time,
+        ..
+    } = &record;
+
+    if let Some(font) = &asset_handles.font {
+        let menu = commands.spawn_bundle(menu_node()).insert(GameOverMenu).id();
+
+        let mut children = vec![spawn_label(&mut commands, font, result.title(), 48.0)];
+
+        if modifiers.no_fail {
+            children.push(spawn_label(&mut commands, font, "No Fail", 24.0));
+        }
+
+        if column_mapping.is_shuffled() {
+            children.push(spawn_label(
+                &mut commands,
+                font,
+                column_mapping.label(),
+                24.0,
+            ));
+        }
+
+        if new_high_score {
+            children.push(spawn_label(&mut commands, font, "New High Score!", 36.0));
+        }
+
+        if record.endless_run() {
+            if new_best_time {
+                children.push(spawn_label(&mut commands, font, "New Best Time!", 36.0));
+            }
+            children.push(spawn_label(
+                &mut commands,
+                font,
+                format!(
+                    "Survived: {} (Best: {})",
+                    format_survival_time(survival_time.0),
+                    format_survival_time(Duration::from_millis(high_score.best_survival_ms))
+                ),
+                24.0,
+            ));
+        }
+
+        children.push(spawn_label(
+            &mut commands,
+            font,
+            format!("Score: {}", format_score(score.score)),
+            36.0,
+        ));
+        children.push(spawn_label(
+            &mut commands,
+            font,
+            format!("Accuracy: {}", score.accuracy_label()),
+            36.0,
+        ));
+        for row in [
+            format!("Perfect: {}", score.perfects),
+            format!("Good: {}", score.goods),
+            format!("Miss: {}", score.misses),
+            format!("Max Combo: {}", score.max_combo),
+        ] {
+            children.push(spawn_label(&mut commands, font, row, 24.0));
+        }
+        children.push(spawn_label(
+            &mut commands,
+            font,
+            format!("Best: {}", format_score(high_score.best)),
+            36.0,
+        ));
+        if let Some(rematch) = &rematch {
+            children.push(spawn_label(
+                &mut commands,
+                font,
+                format!("Score to beat: {}", format_score(rematch.score)),
+                24.0,
+            ));
+        }
+
+        // Charts only use the random seed to shuffle their columns, otherwise there's nothing to share
+        if chart.0.is_none() || column_mapping.is_shuffled() {
+            children.push(spawn_label(
+                &mut commands,
+                font,
+                format!("Seed: {}", game_rng.seed),
+                24.0,
+            ));
+        }
+
+        children.push(spawn_button(&mut commands, font, "Retry", RetryButton));
+        // Only runs that are recorded from the start can be set up again from a code
+        if let Some(replay_recorder) = &replay_recorder {
+            children.push(spawn_setting_button(
+                &mut commands,
+                font,
+                "Save Replay",
+                SaveReplayButton,
+                SaveReplayLabel,
+            ));
+
+            let replay = &replay_recorder.replay;
+            let run_code = RunCode {
+                chart: replay.chart.clone(),
+                seed: replay.seed,
+                settings: replay.settings,
+                score: score.score,
+            };
+            children.push(spawn_setting_button(
+                &mut commands,
+                font,
+                "Copy Run Code",
+                CopyRunCodeButton(run_code.encode()),
+                CopyRunCodeLabel,
+            ));
+        }
+        children.push(spawn_button(
+            &mut commands,
+            font,
+            "Main Menu",
+            MainMenuButton,
+        ));
+
+        commands.entity(menu).push_children(&children);
+    }
+}
+
+/// Spawns the replays menu, with a button for each of the most recent replays
+fn setup_replays_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    streamer_mode: Res<StreamerMode>,
+) {
+    if let Some(font) = &asset_handles.font {
+        let menu = commands.spawn_bundle(menu_node()).insert(ReplaysMenu).id();
+
+        let mut children = vec![spawn_label(&mut commands, font, "Replays", 48.0)];
+
+        // Replay files are named after when they were saved, so the newest sort last
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        let files = persist::list(REPLAY_FILE_PREFIX);
+        for file_name in files.iter().rev().take(REPLAYS_SHOWN) {
+            let replay = match persist::try_load::<Replay>(file_name) {
+                Some(replay) => replay,
+                None => continue,
+            };
+            let saved_at = file_name
+                .trim_start_matches(REPLAY_FILE_PREFIX)
+                .trim_end_matches(".ron")
+                .parse::<u64>()
+                .unwrap_or(now);
+            let label = format!(
+                "{}, {}",
+                replay.label(&streamer_mode),
+                format_age(now.saturating_sub(saved_at))
+            );
+            children.push(spawn_button(
+                &mut commands,
+                font,
+                &label,
+                ReplayButton(file_name.clone()),
+            ));
+        }
+
+        if children.len() == 1 {
+            children.push(spawn_label(
+                &mut commands,
+                font,
+                "Save a replay from the results screen to watch it here",
+                24.0,
+            ));
+        }
+
+        children.push(spawn_button(&mut commands, font, "Back", BackButton));
+
+        commands.entity(menu).push_children(&children);
+    }
+}
+
+/// How long ago something was, e.g. "5 minutes ago"
+fn format_age(seconds: u64) -> String {
+    let (amount, unit) = match seconds {
+        0..=59 => return "just now".into(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{} {}{} ago", amount, unit, plural)
+}
+
+/// Spawns a screen listing the assets that failed to load, with a button to quit
+fn setup_load_failed_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    asset_handles: Res<MenuAssetHandles>,
+    failed_assets: Res<FailedAssets>,
+    streamer_mode: Res<StreamerMode>,
+) {
+    // Without the font there's nothing to write with, so the log will have to do
+    let font = match &asset_handles.font {
+        Some(font) if asset_server.get_load_state(font) == LoadState::Loaded => font,
+        _ => return,
+    };
+
+    let menu = commands
+        .spawn_bundle(menu_node())
+        .insert(LoadFailedMenu)
+        .id();
+
+    let mut children = vec![spawn_label(
+        &mut commands,
+        font,
+        "Couldn't load the game's assets",
+        36.0,
+    )];
+    for path in &failed_assets.0 {
+        children.push(spawn_label(
+            &mut commands,
+            font,
+            streamer_mode.path(path),
+            24.0,
+        ));
+    }
+    children.push(spawn_button(&mut commands, font, "Exit", ExitButton));
+
+    commands.entity(menu).push_children(&children);
+}
+
+/// Adds a row for the run that just finished to the run stats file
+fn log_run_stats(record: RunRecord, result: Res<GameResult>) {
+    record.log_stats(*result);
+}
+
+/// Loads every chart in `assets/charts` and spawns the song select menu ui
+fn setup_song_select_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    asset_server: Res<AssetServer>,
+    practice: Res<PracticeLoop>,
+) {
+    let mut charts: Vec<Handle<Beatmap>> = match asset_server.load_folder("charts") {
+        Ok(handles) => handles
+            .into_iter()
+            .map(|handle| handle.typed::<Beatmap>())
+            .collect(),
+        Err(err) => {
+            warn!("Couldn't list the charts: {:?}", err);
+            Vec::new()
+        }
+    };
+    // Keep the list in a stable order, rather than whatever order the folder was read in
+    charts.sort_by_key(|chart| {
+        asset_server
+            .get_handle_path(chart)
+            .map(|path| path.path().to_path_buf())
+    });
+    commands.insert_resource(ChartList { charts, page: 0 });
+
+    if let Some(font) = &asset_handles.font {
+        let menu = commands
+            .spawn_bundle(menu_node())
+            .insert(SongSelectMenu)
+            .id();
+
+        let title = spawn_label(&mut commands, font, "Song Select", 48.0);
+        commands.entity(menu).add_child(title);
+
+        // The slots are filled in by update_chart_slots once the charts have loaded
+        for slot in 0..CHARTS_PER_PAGE {
+            let chart_button = commands
+                .spawn_bundle(ButtonBundle {
+                    style: button_style(),
+                    ..Default::default()
+                })
+                .with_children(|btn| {
+                    btn.spawn_bundle(TextBundle {
+                        text: Text::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 24.0,
+                                ..button_textstyle(font)
+                            },
+                        ),
+                        ..Default::default()
+                    })
+                    .insert(ChartSlotLabel(slot));
+                })
+                .insert(ChartSlotButton(slot))
+                .insert(OldInteraction(Interaction::None))
+                .id();
+            commands.entity(menu).add_child(chart_button);
+        }
+
+        let message = spawn_label(&mut commands, font, "", 24.0);
+        commands.entity(message).insert(SongSelectMessage);
+
+        let page_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        let previous_button = spawn_button(&mut commands, font, "<", PageButton(-1));
+        let next_button = spawn_button(&mut commands, font, ">", PageButton(1));
+        commands
+            .entity(page_row)
+            .push_children(&[previous_button, next_button]);
+
+        let practice_button = spawn_setting_button(
+            &mut commands,
+            font,
+            practice.label(),
+            PracticeButton,
+            PracticeLabel,
+        );
+
+        // The loop's bounds, each with buttons to move it back and forward
+        let bounds_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        for bound in [PracticeBound::Start, PracticeBound::End] {
+            let step = i64::from(PRACTICE_STEP_MS);
+            let earlier_button =
+                spawn_button(&mut commands, font, "-", PracticeBoundButton(bound, -step));
+            let label = spawn_label(&mut commands, font, practice.bound_label(bound), 24.0);
+            commands.entity(label).insert(PracticeBoundLabel(bound));
+            let later_button =
+                spawn_button(&mut commands, font, "+", PracticeBoundButton(bound, step));
+            commands
+                .entity(bounds_row)
+                .push_children(&[earlier_button, label, later_button]);
+        }
+
+        let paste_button = spawn_setting_button(
+            &mut commands,
+            font,
+            "Paste Run Code",
+            PasteRunCodeButton,
+            PasteRunCodeLabel,
+        );
+
+        let back_button = spawn_button(&mut commands, font, "Back", BackButton);
+
+        commands.entity(menu).push_children(&[
+            message,
+            page_row,
+            practice_button,
+            bounds_row,
+            paste_button,
+            back_button,
+        ]);
+    }
+}
+
+/// The page of the settings menu being shown, as there are too many settings to fit on one
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+enum SettingsPage {
+    #[default]
+    Audio,
+    Display,
+    Guides,
+    Gameplay,
+}
+
+impl SettingsPage {
+    const ALL: [SettingsPage; 4] = [
+        SettingsPage::Audio,
+        SettingsPage::Display,
+        SettingsPage::Guides,
+        SettingsPage::Gameplay,
+    ];
+
+    /// The page `step` pages along, stopping at the first and last
+    fn step(self, step: isize) -> Self {
+        let index = Self::ALL.iter().position(|&page| page == self).unwrap_or(0) as isize;
+        Self::ALL[(index + step).clamp(0, Self::ALL.len() as isize - 1) as usize]
+    }
+
+    /// A human readable description of the page, e.g. "Audio (1/4)"
+    fn label(self) -> String {
+        let index = Self::ALL.iter().position(|&page| page == self).unwrap_or(0);
+        format!("{:?} ({}/{})", self, index + 1, Self::ALL.len())
+    }
+}
+
+/// The settings that change how the game looks, which the settings menu shows together
+#[derive(SystemParam)]
+struct DisplaySettings<'w, 's> {
+    screen_shake: Res<'w, ScreenShake>,
+    key_labels: Res<'w, KeyLabels>,
+    up_scroll: Res<'w, UpScroll>,
+    column_shapes: Res<'w, ColumnShapes>,
+    beat_pulse: Res<'w, BeatPulse>,
+    timing_markers: Res<'w, TimingMarkers>,
+    fast_slow: Res<'w, FastSlow>,
+    judgment_line: Res<'w, JudgmentLine>,
+    frame_rate: Res<'w, FrameRate>,
+    lanes: Res<'w, Lanes>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+/// Spawn the settings menu ui
+fn setup_settings_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    volume: Res<Volume>,
+    punish_ghost_taps: Res<PunishGhostTaps>,
+    hit_sounds: Res<HitSounds>,
+    hit_sound_voices: Res<HitSoundVoices>,
+    auto_play: Res<AutoPlay>,
+    metronome: Res<Metronome>,
+    run_settings: RunSettings,
+    start_mode: Res<StartMode>,
+    pointer_input: Res<PointerInput>,
+    combo_ducking: Res<ComboDucking>,
+    announcements: Res<Announcements>,
+    streamer_mode: Res<StreamerMode>,
+    training_wheels: Res<TrainingWheels>,
+    display: DisplaySettings,
+) {
+    if let Some(font) = &asset_handles.font {
+        let menu = commands.spawn_bundle(menu_node()).insert(SettingsMenu).id();
+
+        commands.insert_resource(SettingsPage::default());
+
+        let title = spawn_label(&mut commands, font, "Settings", 48.0);
+        commands.entity(menu).add_child(title);
+
+        // The settings are split into pages, which are flicked through like the song select menu's
+        let page_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        let previous_button = spawn_button(&mut commands, font, "<", PageButton(-1));
+        let page_label = spawn_label(&mut commands, font, SettingsPage::default().label(), 24.0);
+        commands.entity(page_label).insert(SettingsPageLabel);
+        let next_button = spawn_button(&mut commands, font, ">", PageButton(1));
+        commands
+            .entity(page_row)
+            .push_children(&[previous_button, page_label, next_button]);
+        commands.entity(menu).add_child(page_row);
+
+        // Every page is spawned up front, and all but the one being shown are hidden
+        let pages = SettingsPage::ALL.map(|page| {
+            let node = commands
+                .spawn_bundle(NodeBundle {
+                    color: UiColor(Color::NONE),
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        display: if page == SettingsPage::default() {
+                            Display::Flex
+                        } else {
+                            Display::None
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(SettingsPageNode(page))
+                .id();
+            commands.entity(menu).add_child(node);
+            node
+        });
+        let [audio_page, display_page, guides_page, gameplay_page] = pages;
+
+        for channel in [VolumeChannel::Music, VolumeChannel::Sfx] {
+            let row = commands
+                .spawn_bundle(NodeBundle {
+                    color: UiColor(Color::NONE),
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .id();
+
+            let label = spawn_label(&mut commands, font, volume.label(channel), 24.0);
+            commands.entity(label).insert(VolumeLabel(channel));
+
+            // The music volume is set by dragging, the sound effects a step at a time
+            if channel == VolumeChannel::Music {
+                let slider =
+                    spawn_slider(&mut commands, volume.get(channel), VolumeSlider(channel));
+                commands.entity(row).push_children(&[label, slider]);
+            } else {
+                let down_button = spawn_button(
+                    &mut commands,
+                    font,
+                    "-",
+                    VolumeButton(channel, -VOLUME_STEP),
+                );
+                let up_button =
+                    spawn_button(&mut commands, font, "+", VolumeButton(channel, VOLUME_STEP));
+
+                commands
+                    .entity(row)
+                    .push_children(&[down_button, label, up_button]);
+            }
+            commands.entity(audio_page).add_child(row);
+        }
+
+        let judgment_line_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        let lower_button = spawn_button(
+            &mut commands,
+            font,
+            "-",
+            JudgmentLineButton(-JUDGMENT_LINE_STEP),
+        );
+        let judgment_line_label =
+            spawn_label(&mut commands, font, display.judgment_line.label(), 24.0);
+        commands
+            .entity(judgment_line_label)
+            .insert(JudgmentLineLabel);
+        let raise_button = spawn_button(
+            &mut commands,
+            font,
+            "+",
+            JudgmentLineButton(JUDGMENT_LINE_STEP),
+        );
+        commands.entity(judgment_line_row).push_children(&[
+            lower_button,
+            judgment_line_label,
+            raise_button,
+        ]);
+        commands.entity(guides_page).add_child(judgment_line_row);
+
+        // Each setting's button shows its current value, so the menu takes up less room
+        let ghost_tap_button = spawn_setting_button(
+            &mut commands,
+            font,
+            punish_ghost_taps.label(),
+            GhostTapButton,
+            GhostTapLabel,
+        );
+        let hit_sound_button = spawn_setting_button(
+            &mut commands,
+            font,
+            hit_sounds.label(),
+            HitSoundButton,
+            HitSoundLabel,
+        );
+        let hit_sound_voices_button = spawn_setting_button(
+            &mut commands,
+            font,
+            &hit_sound_voices.label(),
+            HitSoundVoicesButton,
+            HitSoundVoicesLabel,
+        );
+        let screen_shake_button = spawn_setting_button(
+            &mut commands,
+            font,
+            display.screen_shake.label(),
+            ScreenShakeButton,
+            ScreenShakeLabel,
+        );
+        let up_scroll_button = spawn_setting_button(
+            &mut commands,
+            font,
+            display.up_scroll.label(),
+            UpScrollButton,
+            UpScrollLabel,
+        );
+        let auto_play_button = spawn_setting_button(
+            &mut commands,
+            font,
+            auto_play.label(),
+            AutoPlayButton,
+            AutoPlayLabel,
+        );
+        let start_mode_button = spawn_setting_button(
+            &mut commands,
+            font,
+            start_mode.label(),
+            StartModeButton,
+            StartModeLabel,
+        );
+        let column_shapes_button = spawn_setting_button(
+            &mut commands,
+            font,
+            display.column_shapes.label(),
+            ColumnShapesButton,
+            ColumnShapesLabel,
+        );
+        let hit_window_button = spawn_setting_button(
+            &mut commands,
+            font,
+            run_settings.hit_window.label(),
+            HitWindowButton,
+            HitWindowLabel,
+        );
+        let metronome_button = spawn_setting_button(
+            &mut commands,
+            font,
+            metronome.label(),
+            MetronomeButton,
+            MetronomeLabel,
+        );
+
+        let beat_pulse_button = spawn_setting_button(
+            &mut commands,
+            font,
+            display.beat_pulse.label(),
+            BeatPulseButton,
+            BeatPulseLabel,
+        );
+
+        let key_labels_button = spawn_setting_button(
+            &mut commands,
+            font,
+            display.key_labels.label(),
+            KeyLabelsButton,
+            KeyLabelsLabel,
+        );
+
+        let timing_markers_button = spawn_setting_button(
+            &mut commands,
+            font,
+            display.timing_markers.label(),
+            TimingMarkersButton,
+            TimingMarkersLabel,
+        );
+
+        let fast_slow_button = spawn_setting_button(
+            &mut commands,
+            font,
+            display.fast_slow.label(),
+            FastSlowButton,
+            FastSlowLabel,
+        );
+
+        let scroll_mode_button = spawn_setting_button(
+            &mut commands,
+            font,
+            run_settings.scroll_speed.mode_label(),
+            ScrollModeButton,
+            ScrollModeLabel,
+        );
+
+        let pointer_input_button = spawn_setting_button(
+            &mut commands,
+            font,
+            pointer_input.label(),
+            PointerInputButton,
+            PointerInputLabel,
+        );
+
+        let frame_rate_button = spawn_setting_button(
+            &mut commands,
+            font,
+            &display.frame_rate.label(),
+            FrameRateButton,
+            FrameRateLabel,
+        );
+
+        let lanes_button = spawn_setting_button(
+            &mut commands,
+            font,
+            &display.lanes.label(),
+            LanesButton,
+            LanesLabel,
+        );
+
+        let combo_ducking_button = spawn_setting_button(
+            &mut commands,
+            font,
+            combo_ducking.label(),
+            ComboDuckingButton,
+            ComboDuckingLabel,
+        );
+
+        let announcements_button = spawn_setting_button(
+            &mut commands,
+            font,
+            announcements.label(),
+            AnnouncementsButton,
+            AnnouncementsLabel,
+        );
+
+        let training_wheels_button = spawn_setting_button(
+            &mut commands,
+            font,
+            training_wheels.label(),
+            TrainingWheelsButton,
+            TrainingWheelsLabel,
+        );
+
+        let charge_scoring_button = spawn_setting_button(
+            &mut commands,
+            font,
+            run_settings.charge_scoring.label(),
+            ChargeScoringButton,
+            ChargeScoringLabel,
+        );
+
+        let streamer_mode_button = spawn_setting_button(
+            &mut commands,
+            font,
+            streamer_mode.label(),
+            StreamerModeButton,
+            StreamerModeLabel,
+        );
+
+        // One setting to a row, as some of their labels are too wide to fit two side by side
+        let page_buttons = [
+            (
+                audio_page,
+                vec![
+                    hit_sound_button,
+                    hit_sound_voices_button,
+                    combo_ducking_button,
+                    announcements_button,
+                    metronome_button,
+                ],
+            ),
+            (
+                display_page,
+                vec![
+                    screen_shake_button,
+                    up_scroll_button,
+                    column_shapes_button,
+                    key_labels_button,
+                    beat_pulse_button,
+                    frame_rate_button,
+                    streamer_mode_button,
+                ],
+            ),
+            (
+                guides_page,
+                vec![
+                    timing_markers_button,
+                    fast_slow_button,
+                    training_wheels_button,
+                ],
+            ),
+            (
+                gameplay_page,
+                vec![
+                    ghost_tap_button,
+                    pointer_input_button,
+                    scroll_mode_button,
+                    auto_play_button,
+                    start_mode_button,
+                    lanes_button,
+                    hit_window_button,
+                    charge_scoring_button,
+                ],
+            ),
+        ];
+        for (page, buttons) in page_buttons {
+            commands.entity(page).push_children(&buttons);
+        }
+
+        let back_button = spawn_button(&mut commands, font, "Back", BackButton);
+        commands.entity(menu).add_child(back_button);
+    }
+}
+
+/// Spawn the modifiers menu ui
+fn setup_modifiers_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    modifiers: Res<Modifiers>,
+) {
+    if let Some(font) = &asset_handles.font {
+        let menu = commands
+            .spawn_bundle(menu_node())
+            .insert(ModifiersMenu)
+            .id();
+
+        let title = spawn_label(&mut commands, font, "Modifiers", 48.0);
+        commands.entity(menu).add_child(title);
+
+        for modifier in Modifier::ALL {
+            let button = spawn_setting_button(
+                &mut commands,
+                font,
+                &modifiers.label(modifier),
+                ModifierButton(modifier),
+                ModifierLabel(modifier),
+            );
+            commands.entity(menu).add_child(button);
+        }
+
+        let back_button = spawn_button(&mut commands, font, "Back", BackButton);
+        commands.entity(menu).add_child(back_button);
+    }
+}
+
+/// Spawn the calibration screen ui
+fn setup_calibration_menu(
+    mut commands: Commands,
+    asset_handles: Res<MenuAssetHandles>,
+    audio_offset: Res<AudioOffset>,
+    visual_offset: Res<VisualOffset>,
+) {
+    commands.insert_resource(Calibration::default());
+
+    if let Some(font) = &asset_handles.font {
+        let menu = commands
+            .spawn_bundle(menu_node())
+            .insert(CalibrationMenu)
+            .id();
+
+        let title = spawn_label(&mut commands, font, "Calibrate", 48.0);
+        let instructions = spawn_label(
+            &mut commands,
+            font,
+            "Tap space or any column as the note reaches the line",
+            24.0,
+        );
+
+        // A single lane, with a note that falls to the line in time with the metronome
+        let lane = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::rgb(0.2, 0.2, 0.2)),
+                style: Style {
+                    size: Size::new(Val::Px(60.0), Val::Px(CALIBRATION_LANE_HEIGHT)),
+                    margin: UiRect::all(Val::Px(8.0)),
+                    align_self: AlignSelf::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_children(|lane| {
+                lane.spawn_bundle(NodeBundle {
+                    color: UiColor(Color::WHITE),
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Px(4.0)),
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            bottom: Val::Px(0.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+                lane.spawn_bundle(NodeBundle {
+                    color: UiColor(Color::rgb(1.0, 0.85, 0.2)),
+                    style: Style {
+                        size: Size::new(
+                            Val::Px(CALIBRATION_NOTE_SIZE),
+                            Val::Px(CALIBRATION_NOTE_SIZE),
+                        ),
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            left: Val::Px((60.0 - CALIBRATION_NOTE_SIZE) / 2.0),
+                            top: Val::Px(0.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(CalibrationNote);
+            })
+            .id();
+        let current_offset = spawn_label(
+            &mut commands,
+            font,
+            format!("Audio offset: {} ms", audio_offset.0),
+            24.0,
+        );
+        let readout = spawn_label(&mut commands, font, "Measured offset: -", 24.0);
+        commands.entity(readout).insert(CalibrationReadout);
+
+        let save_button = spawn_button(&mut commands, font, "Save", SaveOffsetButton);
+
+        // The visual offset is set by eye, moving the note until it looks in time with the ticks
+        let visual_offset_row = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::NONE),
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .id();
+        let earlier_button = spawn_button(
+            &mut commands,
+            font,
+            "-",
+            VisualOffsetButton(-VISUAL_OFFSET_STEP),
+        );
+        let visual_offset_label = spawn_label(&mut commands, font, visual_offset.label(), 24.0);
+        commands
+            .entity(visual_offset_label)
+            .insert(VisualOffsetLabel);
+        let later_button = spawn_button(
+            &mut commands,
+            font,
+            "+",
+            VisualOffsetButton(VISUAL_OFFSET_STEP),
+        );
+        commands.entity(visual_offset_row).push_children(&[
+            earlier_button,
+            visual_offset_label,
+            later_button,
+        ]);
+
+        let back_button = spawn_button(&mut commands, font, "Back", BackButton);
+
+        commands.entity(menu).push_children(&[
+            title,
+            instructions,
+            lane,
+            current_offset,
+            readout,
+            save_button,
+            visual_offset_row,
+            back_button,
+        ]);
+    }
+}
+
+/// Spawn the pause menu ui over the top of the game
+fn setup_pause_menu(mut commands: Commands, asset_handles: Res<MenuAssetHandles>) {
+    if let Some(font) = &asset_handles.font {
+        let overlay = commands
+            .spawn_bundle(NodeBundle {
+                color: UiColor(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(PauseMenu)
+            .id();
+
+        let menu = commands.spawn_bundle(menu_node()).id();
+
+        let title = spawn_label(&mut commands, font, "Paused", 48.0);
+        let resume_button = spawn_button(&mut commands, font, "Resume", ResumeButton);
+        let quit_button = spawn_button(&mut commands, font, "Quit", QuitButton);
+
+        commands
+            .entity(menu)
+            .push_children(&[title, resume_button, quit_button]);
+        commands.entity(overlay).add_child(menu);
+    }
+}
+
+/// Returns true if any buttons with the given component are being pressed
+fn button_interact<B: Component>(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction),
+        (Changed<Interaction>, With<Button>, With<B>),
+    >,
+) -> bool {
+    for (new_interaction, mut old_interaction) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            return true;
+        }
+        old_interaction.0 = *new_interaction;
+    }
+    false
+}
+
+/// Sets the colour of every button based on player interaction,
+/// showing the focused button as if it were hovered
+fn button_visual_interact(
+    focused: Res<FocusedButton>,
+    mut query: Query<
+        (
+            Entity,
+            &Interaction,
+            ChangeTrackers<Interaction>,
+            &mut UiColor,
+        ),
+        With<Button>,
+    >,
+) {
+    for (entity, interaction, interaction_tracker, mut colour) in &mut query {
+        if !interaction_tracker.is_changed() && !focused.is_changed() {
+            continue;
+        }
+
+        match interaction {
+            Interaction::Clicked => {
+                *colour = UiColor(Color::rgb(0.75, 0.75, 0.75));
+            }
+            Interaction::Hovered => {
+                *colour = UiColor(Color::rgb(0.8, 0.8, 0.8));
+            }
+            Interaction::None if focused.0 == Some(entity) => {
+                *colour = UiColor(Color::rgb(0.8, 0.8, 0.8));
+            }
+            Interaction::None => {
+                *colour = UiColor(Color::rgb(1.0, 1.0, 1.0));
+            }
+        }
+    }
+}
+
+/// Moves the focus between buttons with the up and down arrow keys, and clicks the
+/// focused button with Enter. Hovering a button with the mouse focuses it too,
+/// so the keyboard carries on from wherever the mouse left off
+fn navigate_menus(
+    input: Res<Input<KeyCode>>,
+    rebinding: Res<Rebinding>,
+    mut focused: ResMut<FocusedButton>,
+    mut buttons: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Node,
+            &mut Interaction,
+            ChangeTrackers<Interaction>,
+            &mut OldInteraction,
+        ),
+        With<Button>,
+    >,
+) {
+    // The next key pressed while rebinding is meant for a column, not the menu
+    if rebinding.0.is_some() {
+        return;
+    }
+
+    for (entity, _, _, interaction, interaction_tracker, _) in &buttons {
+        if interaction_tracker.is_changed()
+            && *interaction == Interaction::Hovered
+            && focused.0 != Some(entity)
+        {
+            focused.0 = Some(entity);
+        }
+    }
+
+    let step: isize = if input.just_pressed(KeyCode::Down) {
+        1
+    } else if input.just_pressed(KeyCode::Up) {
+        -1
+    } else {
+        0
+    };
+    if step != 0 {
+        // Buttons are visited in reading order, top to bottom then left to right.
+        // UI heights go up the screen, and are rounded so buttons in a row sort together
+        // Hidden buttons are laid out with no size, and are skipped
+        let mut order: Vec<(Entity, i32, f32)> = buttons
+            .iter()
+            .filter(|(_, _, node, ..)| node.size != Vec2::ZERO)
+            .map(|(entity, transform, ..)| {
+                let position = transform.translation();
+                (entity, -position.y.round() as i32, position.x)
+            })
+            .collect();
+        order.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+
+        let next = match order
+            .iter()
+            .position(|(entity, ..)| focused.0 == Some(*entity))
+        {
+            Some(index) => (index as isize + step).rem_euclid(order.len() as isize) as usize,
+            // Start from the top or bottom if nothing is focused yet
+            None if step > 0 => 0,
+            None => order.len().saturating_sub(1),
+        };
+        if let Some((entity, ..)) = order.get(next) {
+            focused.0 = Some(*entity);
+        }
+    }
+
+    if input.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter]) {
+        if let Some(Ok((_, _, _, mut interaction, _, mut old_interaction))) =
+            focused.0.map(|entity| buttons.get_mut(entity))
+        {
+            // The same change a mouse click ends with, so every button's handler picks it up
+            old_interaction.0 = Interaction::Clicked;
+            *interaction = Interaction::Hovered;
+        }
+    }
+}
+
+/// Sets the value of any slider being dragged from where the cursor is along its track.
+///
+/// A clicked button stays clicked until the mouse is released, so the handle
+/// keeps following the cursor even when it strays off the track
+fn drag_sliders(
+    windows: Res<Windows>,
+    mut sliders: Query<(&Interaction, &Node, &GlobalTransform, &mut Slider)>,
+) {
+    let cursor = match windows
+        .get_primary()
+        .and_then(|window| window.cursor_position())
+    {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    for (interaction, node, transform, mut slider) in &mut sliders {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let left = transform.translation().x - node.size.x / 2.0;
+        let value = ((cursor.x - left) / node.size.x.max(1.0)).clamp(0.0, 1.0);
+        // Only touch the slider when it moves, so change detection means something
+        if slider.0 != value {
+            slider.0 = value;
+        }
+    }
+}
+
+/// Moves each slider's handle to match its value
+fn update_slider_handles(
+    sliders: Query<(&Slider, &Children), Changed<Slider>>,
+    mut handles: Query<&mut Style, With<SliderHandle>>,
+) {
+    for (slider, children) in &sliders {
+        for &child in children {
+            if let Ok(mut style) = handles.get_mut(child) {
+                style.position = slider_handle_position(slider.0);
+            }
+        }
+    }
+}
+
+/// Starts the game with randomly spawned targets
+fn on_start_button(mut commands: Commands) {
+    commands.insert_resource(SelectedChart(None));
+    commands.insert_resource(EndlessMode(false));
+    commands.insert_resource(NextState(GameState::Playing));
+}
+
+/// Starts an endless run, with randomly spawned targets that speed up over time
+fn on_endless_button(mut commands: Commands) {
+    commands.insert_resource(SelectedChart(None));
+    commands.insert_resource(EndlessMode(true));
+    commands.insert_resource(NextState(GameState::Playing));
+}
+
+/// Resumes the run that was saved last, with the settings it was played with
+fn on_resume_session_button(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    audio_offset: Res<AudioOffset>,
+    mut practice: ResMut<PracticeLoop>,
+) {
+    let session = match persist::try_load::<SavedSession>(SESSION_FILE) {
+        Some(session) => session,
+        None => {
+            warn!("Couldn't load the saved session in {}", SESSION_FILE);
+            return;
+        }
+    };
+
+    // The saved file could have been edited, so its settings are kept within the menus' limits.
+    // The audio offset is the player's current one, in case they've calibrated since
+    let mut settings = session.settings.clamped();
+    settings.audio_offset = *audio_offset;
+    settings.apply(&mut commands);
+    // Practice runs aren't saved, so this is never one
+    practice.enabled = false;
+    commands.insert_resource(SelectedChart(
+        session
+            .chart
+            .as_ref()
+            .map(|chart| asset_server.load(chart.as_str())),
+    ));
+    commands.insert_resource(ResumedSession(session));
+    commands.insert_resource(NextState(GameState::Playing));
+}
+
+/// Opens the song select menu
+fn on_chart_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::SongSelect));
+}
+
+/// Opens the replays menu
+fn on_replays_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Replays));
+}
+
+/// Opens the controls menu
+fn on_controls_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Controls));
+}
+
+/// Opens the settings menu
+fn on_settings_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Settings));
+}
+
+/// Opens the modifiers menu
+fn on_modifiers_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Modifiers));
+}
+
+/// Opens the calibration screen
+fn on_calibrate_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Calibration));
+}
+
+/// Saves the offset measured on the calibration screen and returns to the start menu
+fn on_save_offset_button(mut commands: Commands, calibration: Res<Calibration>) {
+    if let Some(offset) = calibration.measured_offset() {
+        let audio_offset = AudioOffset(offset);
+        persist::save(AUDIO_OFFSET_FILE, &audio_offset);
+        commands.insert_resource(audio_offset);
+    }
+    commands.insert_resource(NextState(GameState::StartMenu));
+}
+
+/// Returns to the start menu
+fn on_back_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::StartMenu));
+}
+
+/// Changes the difficulty when a difficulty button is clicked
+fn on_difficulty_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &DifficultyButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    for (new_interaction, mut old_interaction, DifficultyButton(preset)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            *difficulty = *preset;
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Keeps the start menu's difficulty text in sync with the chosen difficulty
+fn update_difficulty_label(
+    difficulty: Res<Difficulty>,
+    mut label_query: Query<&mut Text, With<DifficultyLabel>>,
+) {
+    if difficulty.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = format!("Difficulty: {:?}", *difficulty);
+        }
+    }
+}
+
+/// Changes the scroll speed when the player presses + or -
+fn adjust_scroll_speed(keyboard: Res<Input<KeyCode>>, mut scroll_speed: ResMut<ScrollSpeed>) {
+    let faster = keyboard.any_just_pressed([KeyCode::Equals, KeyCode::NumpadAdd]);
+    let slower = keyboard.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]);
+
+    if faster != slower {
+        scroll_speed.adjust(faster);
+        persist::save(SCROLL_SPEED_FILE, &*scroll_speed);
+    }
+}
+
+/// Keeps the start menu's scroll speed text in sync with the chosen scroll speed
+fn update_scroll_speed_label(
+    scroll_speed: Res<ScrollSpeed>,
+    mut label_query: Query<&mut Text, With<ScrollSpeedLabel>>,
+) {
+    if scroll_speed.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = scroll_speed.label();
+        }
+    }
+}
+
+/// Waits for a key when a rebind button is clicked
+fn on_rebind_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &RebindButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut rebinding: ResMut<Rebinding>,
+    mut feedback_query: Query<&mut Text, With<ControlsFeedback>>,
+) {
+    for (new_interaction, mut old_interaction, RebindButton(binding)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            rebinding.0 = Some(*binding);
+            for mut feedback in feedback_query.iter_mut() {
+                feedback.sections[0].value = format!("Press a key for {}", binding);
+            }
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Binds the next key pressed to the column or action being rebound,
+/// rejecting keys that are already used by another one
+fn capture_rebind_key(
+    mut commands: Commands,
+    mut input: ResMut<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut key_bindings: ResMut<KeyBindings>,
+    mut feedback_query: Query<&mut Text, With<ControlsFeedback>>,
+) {
+    // Only the columns can be played with a gamepad
+    if let (Some(binding @ Binding::Column(column, slot)), Some(button)) =
+        (rebinding.0, gamepad_buttons.get_just_pressed().next())
+    {
+        let button = button.button_type;
+        let message = match key_bindings.button_binding_for(button) {
+            Some(other) if other != binding => {
+                format!("{:?} is already bound to {}", button, other)
+            }
+            _ => {
+                key_bindings.bind_button(column, slot, button);
+                persist::save(KEY_BINDINGS_FILE, &*key_bindings);
+                rebinding.0 = None;
+                format!("{} is now bound to {:?}", binding, button)
+            }
+        };
+
+        for mut feedback in feedback_query.iter_mut() {
+            feedback.sections[0].value = message.clone();
+        }
+        return;
+    }
+
+    let key = match input.get_just_pressed().next() {
+        Some(key) => *key,
+        None => return,
+    };
+
+    let menu_key = key_bindings.action_key(Action::Menu);
+    let binding = match rebinding.0 {
+        Some(binding) => binding,
+        None => {
+            // The menu key leaves the menu when nothing is being rebound
+            if key == menu_key {
+                commands.insert_resource(NextState(GameState::StartMenu));
+            }
+            return;
+        }
+    };
+
+    // The menu key cancels, unless it's the one being rebound
+    let message = if key == menu_key && binding != Binding::Action(Action::Menu) {
+        rebinding.0 = None;
+        "Rebinding cancelled".to_string()
+    } else {
+        match key_bindings.binding_for(key) {
+            Some(other) if other != binding => {
+                format!("{:?} is already bound to {}", key, other)
+            }
+            _ => {
+                key_bindings.bind(binding, key);
+                persist::save(KEY_BINDINGS_FILE, &*key_bindings);
+                rebinding.0 = None;
+                format!("{} is now bound to {:?}", binding, key)
+            }
+        }
+    };
+
+    // Don't let the same press trigger anything else this frame
+    input.clear_just_pressed(key);
+
+    for mut feedback in feedback_query.iter_mut() {
+        feedback.sections[0].value = message.clone();
+    }
+}
+
+/// Keeps the controls menu's labels in sync with the key bindings
+fn update_binding_labels(
+    key_bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    mut label_query: Query<(&mut Text, &BindingLabel)>,
+) {
+    if key_bindings.is_changed() || rebinding.is_changed() {
+        for (mut text, BindingLabel(binding)) in label_query.iter_mut() {
+            text.sections[0].value = if rebinding.0 == Some(*binding) {
+                format!("{}: ...", binding)
+            } else {
+                format!("{}: {}", binding, key_bindings.binding_label(*binding))
+            };
+        }
+    }
+}
+
+/// Starts the game playing the chart whose button was clicked
+fn on_chart_slot_button(
+    mut commands: Commands,
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &ChartSlotButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    chart_list: Res<ChartList>,
+) {
+    for (new_interaction, mut old_interaction, ChartSlotButton(slot)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            if let Some(chart) = chart_list.chart_in_slot(*slot) {
+                commands.insert_resource(SelectedChart(Some(chart.clone())));
+                commands.insert_resource(NextState(GameState::Playing));
+            }
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Starts watching the replay whose button was clicked,
+/// swapping in the settings it was recorded with until the player returns to the start menu
+fn on_replay_button(
+    mut commands: Commands,
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &ReplayButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    asset_server: Res<AssetServer>,
+    run_settings: RunSettings,
+    mut practice: ResMut<PracticeLoop>,
+) {
+    for (new_interaction, mut old_interaction, ReplayButton(file_name)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            let replay = match persist::try_load::<Replay>(file_name) {
+                Some(replay) => replay,
+                None => {
+                    warn!("Couldn't load the replay in {}", file_name);
+                    continue;
+                }
+            };
+
+            replay.settings.clamped().apply(&mut commands);
+            // Replays are always of the whole run
+            practice.enabled = false;
+            commands.insert_resource(SelectedChart(
+                replay
+                    .chart
+                    .as_ref()
+                    .map(|chart| asset_server.load(chart.as_str())),
+            ));
+            commands.insert_resource(ReplayPlayback {
+                replay,
+                previous_settings: run_settings.snapshot(),
+                clock: Stopwatch::new(),
+                next_input: 0,
+                held: HashSet::new(),
+                just_pressed: HashSet::new(),
+            });
+            commands.insert_resource(NextState(GameState::Playing));
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Stops watching a replay, and puts the player's own settings back
+fn end_replay(mut commands: Commands, replay_playback: Res<ReplayPlayback>) {
+    replay_playback.previous_settings.apply(&mut commands);
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+/// Saves the replay of the run that just finished, so it can be watched from the replays menu
+fn on_save_replay_button(
+    mut commands: Commands,
+    replay_recorder: Option<Res<ReplayRecorder>>,
+    mut label_query: Query<&mut Text, With<SaveReplayLabel>>,
+) {
+    // It's only saved once, however many times the button is clicked
+    let replay_recorder = match replay_recorder {
+        Some(replay_recorder) => replay_recorder,
+        None => return,
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    persist::save(
+        &format!("{}{}.ron", REPLAY_FILE_PREFIX, timestamp),
+        &replay_recorder.replay,
+    );
+    commands.remove_resource::<ReplayRecorder>();
+
+    for mut label in &mut label_query {
+        label.sections[0].value = "Replay Saved".into();
+    }
+}
+
+/// Copies the code of the run that just finished to the clipboard, to share with friends
+fn on_copy_run_code_button(
+    button_query: Query<&CopyRunCodeButton>,
+    mut label_query: Query<&mut Text, With<CopyRunCodeLabel>>,
+) {
+    let code = match button_query.iter().next() {
+        Some(CopyRunCodeButton(code)) => code.clone(),
+        None => return,
+    };
+
+    let message = match run_code::copy(code) {
+        Ok(()) => "Run Code Copied",
+        Err(err) => {
+            warn!("Couldn't copy the run code: {}", err);
+            "Couldn't Copy"
+        }
+    };
+    for mut label in &mut label_query {
+        label.sections[0].value = message.into();
+    }
+}
+
+/// Starts a rematch from the run code on the clipboard, with the chart, seed and settings
+/// it was played with. The player's own audio offset is kept, since it's down to their setup
+fn on_paste_run_code_button(
+    mut commands: Commands,
+    chart_list: Res<ChartList>,
+    asset_server: Res<AssetServer>,
+    run_settings: RunSettings,
+    mut practice: ResMut<PracticeLoop>,
+    mut label_query: Query<&mut Text, With<PasteRunCodeLabel>>,
+) {
+    let run_code = match run_code::paste().and_then(|code| RunCode::decode(&code)) {
+        Ok(run_code) => run_code,
+        Err(err) => {
+            warn!("Couldn't read a run code from the clipboard: {}", err);
+            for mut label in &mut label_query {
+                label.sections[0].value = "No Run Code Copied".into();
+            }
+            return;
+        }
+    };
+
+    // The chart has to be one of the player's own, under the same path
+    let chart = match &run_code.chart {
+        Some(path) => {
+            let chart = chart_list.charts.iter().find(|chart| {
+                asset_server
+                    .get_handle_path(*chart)
+                    .is_some_and(|chart_path| chart_path.path().display().to_string() == *path)
+            });
+            match chart {
+                Some(chart) => Some(chart.clone()),
+                None => {
+                    warn!("The run code's chart, {}, isn't installed", path);
+                    for mut label in &mut label_query {
+                        label.sections[0].value = "Chart Not Found".into();
+                    }
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let previous_settings = run_settings.snapshot();
+    let mut settings = run_code.settings;
+    settings.audio_offset = previous_settings.audio_offset;
+    settings.apply(&mut commands);
+    // Rematches are always of the whole run
+    practice.enabled = false;
+    commands.insert_resource(SelectedChart(chart));
+    commands.insert_resource(Rematch {
+        seed: run_code.seed,
+        score: run_code.score,
+        previous_settings,
+    });
+    commands.insert_resource(NextState(GameState::Playing));
+}
+
+/// Ends a rematch, and puts the player's own settings back
+fn end_rematch(mut commands: Commands, rematch: Res<Rematch>) {
+    rematch.previous_settings.apply(&mut commands);
+    commands.remove_resource::<Rematch>();
+}
+
+/// Toggles practice mode
+fn on_practice_button(mut practice: ResMut<PracticeLoop>) {
+    practice.enabled = !practice.enabled;
+}
+
+/// Moves one of the practice loop's bounds when its button is clicked
+fn on_practice_bound_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &PracticeBoundButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut practice: ResMut<PracticeLoop>,
+) {
+    for (new_interaction, mut old_interaction, PracticeBoundButton(bound, change)) in
+        &mut interactions
+    {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            practice.adjust(*bound, *change);
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Keeps the song select menu's practice mode text up to date
+fn update_practice_labels(
+    practice: Res<PracticeLoop>,
+    mut toggle_query: Query<&mut Text, With<PracticeLabel>>,
+    mut bound_query: Query<(&mut Text, &PracticeBoundLabel), Without<PracticeLabel>>,
+) {
+    if practice.is_changed() {
+        for mut label in toggle_query.iter_mut() {
+            label.sections[0].value = practice.label().into();
+        }
+        for (mut label, PracticeBoundLabel(bound)) in bound_query.iter_mut() {
+            label.sections[0].value = practice.bound_label(*bound);
+        }
+    }
+}
+
+/// Moves to the previous or next page of charts
+fn on_page_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &PageButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut chart_list: ResMut<ChartList>,
+) {
+    for (new_interaction, mut old_interaction, PageButton(step)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            let last_page = chart_list.page_count().saturating_sub(1) as isize;
+            chart_list.page = (chart_list.page as isize + step).clamp(0, last_page) as usize;
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Moves to the previous or next page of settings
+fn on_settings_page_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &PageButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut page: ResMut<SettingsPage>,
+) {
+    for (new_interaction, mut old_interaction, PageButton(step)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            *page = page.step(*step);
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Shows the settings on the current page, hiding the rest
+fn update_settings_page(
+    page: Res<SettingsPage>,
+    mut page_query: Query<(&mut Style, &SettingsPageNode)>,
+    mut label_query: Query<&mut Text, With<SettingsPageLabel>>,
+) {
+    if page.is_changed() {
+        for (mut style, SettingsPageNode(node_page)) in page_query.iter_mut() {
+            style.display = if node_page == &*page {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = page.label();
+        }
+    }
+}
+
+/// Fills the song select menu's buttons with the charts on the current page,
+/// hiding any that are left over
+fn update_chart_slots(
+    chart_list: Res<ChartList>,
+    beatmaps: Res<Assets<Beatmap>>,
+    asset_server: Res<AssetServer>,
+    mut button_query: Query<(&mut Style, &ChartSlotButton)>,
+    mut label_query: Query<(&mut Text, &ChartSlotLabel)>,
+    mut message_query: Query<&mut Text, (With<SongSelectMessage>, Without<ChartSlotLabel>)>,
+) {
+    // Only write to things that changed, so the ui isn't laid out again every frame
+    for (mut style, ChartSlotButton(slot)) in button_query.iter_mut() {
+        let display = if chart_list.chart_in_slot(*slot).is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if style.display != display {
+            style.display = display;
+        }
+    }
+
+    for (mut text, ChartSlotLabel(slot)) in label_query.iter_mut() {
+        let chart = match chart_list.chart_in_slot(*slot) {
+            Some(chart) => chart,
+            None => continue,
+        };
+
+        // Charts are named after their file until they've loaded and we know their title
+        let file_name = asset_server
+            .get_handle_path(chart)
+            .and_then(|path| {
+                path.path()
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default();
+
+        let label = match beatmaps.get(chart) {
+            Some(beatmap) => {
+                let title = beatmap.title.clone().unwrap_or(file_name);
+                match &beatmap.difficulty {
+                    Some(difficulty) => format!("{} ({})", title, difficulty),
+                    None => title,
+                }
+            }
+            None if asset_server.get_load_state(chart) == LoadState::Failed => {
+                format!("{} (failed to load)", file_name)
+            }
+            None => file_name,
+        };
+        if text.sections[0].value != label {
+            text.sections[0].value = label;
+        }
+    }
+
+    let message = if chart_list.charts.is_empty() {
+        "No charts found in assets/charts".to_string()
+    } else {
+        format!("Page {}/{}", chart_list.page + 1, chart_list.page_count())
+    };
+    for mut text in message_query.iter_mut() {
+        if text.sections[0].value != message {
+            text.sections[0].value = message.clone();
+        }
+    }
+}
+
+/// Turns a volume up or down when its button is clicked, and saves it
+fn on_volume_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &VolumeButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut volume: ResMut<Volume>,
+) {
+    for (new_interaction, mut old_interaction, VolumeButton(channel, amount)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            volume.adjust(*channel, *amount);
+            persist::save(VOLUME_FILE, &*volume);
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Sets a volume from its slider, saving it once the slider is let go
+fn on_volume_slider(
+    mut sliders: Query<
+        (&Slider, &Interaction, &mut OldInteraction, &VolumeSlider),
+        Or<(Changed<Slider>, Changed<Interaction>)>,
+    >,
+    mut volume: ResMut<Volume>,
+) {
+    for (slider, interaction, mut old_interaction, VolumeSlider(channel)) in &mut sliders {
+        if volume.get(*channel) != slider.0 {
+            volume.set(*channel, slider.0);
+        }
+        // Saving on every frame of a drag would be a lot of writes for nothing
+        if *interaction != Interaction::Clicked && old_interaction.0 == Interaction::Clicked {
+            persist::save(VOLUME_FILE, &*volume);
+        }
+        old_interaction.0 = *interaction;
+    }
+}
+
+/// Keeps the settings menu's volume text up to date
+fn update_volume_labels(volume: Res<Volume>, mut label_query: Query<(&mut Text, &VolumeLabel)>) {
+    if volume.is_changed() {
+        for (mut label, VolumeLabel(channel)) in label_query.iter_mut() {
+            label.sections[0].value = volume.label(*channel);
+        }
+    }
+}
+
+/// Toggles a modifier when its button is clicked
+fn on_modifier_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &ModifierButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut modifiers: ResMut<Modifiers>,
+) {
+    for (new_interaction, mut old_interaction, ModifierButton(modifier)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            modifiers.toggle(*modifier);
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Keeps the modifiers menu's text up to date
+fn update_modifier_labels(
+    modifiers: Res<Modifiers>,
+    mut label_query: Query<(&mut Text, &ModifierLabel)>,
+) {
+    if modifiers.is_changed() {
+        for (mut label, ModifierLabel(modifier)) in label_query.iter_mut() {
+            label.sections[0].value = modifiers.label(*modifier);
+        }
+    }
+}
+
+/// Toggles whether ghost taps are punished, and saves it
+fn on_ghost_tap_button(mut punish_ghost_taps: ResMut<PunishGhostTaps>) {
+    punish_ghost_taps.0 = !punish_ghost_taps.0;
+    persist::save(GHOST_TAPS_FILE, &*punish_ghost_taps);
+}
+
+/// Keeps the settings menu's ghost tap text up to date
+fn update_ghost_tap_label(
+    punish_ghost_taps: Res<PunishGhostTaps>,
+    mut label_query: Query<&mut Text, With<GhostTapLabel>>,
+) {
+    if punish_ghost_taps.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = punish_ghost_taps.label().into();
+        }
+    }
+}
+
+/// Switches between per column and pitched hit sounds, and saves it
+fn on_hit_sound_button(mut hit_sounds: ResMut<HitSounds>) {
+    *hit_sounds = match *hit_sounds {
+        HitSounds::PerColumn => HitSounds::Pitched,
+        HitSounds::Pitched => HitSounds::Off,
+        HitSounds::Off => HitSounds::PerColumn,
+    };
+    persist::save(HIT_SOUNDS_FILE, &*hit_sounds);
+}
+
+/// Keeps the settings menu's hit sound text up to date
+fn update_hit_sound_label(
+    hit_sounds: Res<HitSounds>,
+    mut label_query: Query<&mut Text, With<HitSoundLabel>>,
+) {
+    if hit_sounds.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = hit_sounds.label().into();
+        }
+    }
+}
+
+/// Moves on to the next hit sound voices cap, and saves it
+fn on_hit_sound_voices_button(mut hit_sound_voices: ResMut<HitSoundVoices>) {
+    *hit_sound_voices = hit_sound_voices.next();
+    persist::save(HIT_SOUND_VOICES_FILE, &*hit_sound_voices);
+}
+
+/// Keeps the settings menu's hit sound voices text up to date
+fn update_hit_sound_voices_label(
+    hit_sound_voices: Res<HitSoundVoices>,
+    mut label_query: Query<&mut Text, With<HitSoundVoicesLabel>>,
+) {
+    if hit_sound_voices.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = hit_sound_voices.label();
+        }
+    }
+}
+
+/// Toggles screen shake, and saves it
+fn on_screen_shake_button(mut screen_shake: ResMut<ScreenShake>) {
+    screen_shake.0 = !screen_shake.0;
+    persist::save(SCREEN_SHAKE_FILE, &*screen_shake);
+}
+
+/// Keeps the settings menu's screen shake text up to date
+fn update_screen_shake_label(
+    screen_shake: Res<ScreenShake>,
+    mut label_query: Query<&mut Text, With<ScreenShakeLabel>>,
+) {
+    if screen_shake.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = screen_shake.label().into();
+        }
+    }
+}
+
+/// Switches between starting with a countdown and waiting for a press, and saves it
+fn on_start_mode_button(mut start_mode: ResMut<StartMode>) {
+    *start_mode = match *start_mode {
+        StartMode::Countdown => StartMode::PressToStart,
+        StartMode::PressToStart => StartMode::Countdown,
+    };
+    persist::save(START_MODE_FILE, &*start_mode);
+}
+
+/// Keeps the settings menu's start mode text up to date
+fn update_start_mode_label(
+    start_mode: Res<StartMode>,
+    mut label_query: Query<&mut Text, With<StartModeLabel>>,
+) {
+    if start_mode.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = start_mode.label().into();
+        }
+    }
+}
+
+/// Toggles column shapes, and saves them
+fn on_column_shapes_button(mut column_shapes: ResMut<ColumnShapes>) {
+    column_shapes.0 = !column_shapes.0;
+    persist::save(COLUMN_SHAPES_FILE, &*column_shapes);
+}
+
+/// Keeps the settings menu's column shapes text up to date
+fn update_column_shapes_label(
+    column_shapes: Res<ColumnShapes>,
+    mut label_query: Query<&mut Text, With<ColumnShapesLabel>>,
+) {
+    if column_shapes.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = column_shapes.label().into();
+        }
+    }
+}
+
+/// Switches between setting the scroll speed as a multiplier and as a travel time, and saves it
+fn on_scroll_mode_button(mut scroll_speed: ResMut<ScrollSpeed>) {
+    scroll_speed.mode = match scroll_speed.mode {
+        ScrollMode::Multiplier => ScrollMode::TravelTime,
+        ScrollMode::TravelTime => ScrollMode::Multiplier,
+    };
+    persist::save(SCROLL_SPEED_FILE, &*scroll_speed);
+}
+
+/// Keeps the settings menu's scroll speed mode text up to date
+fn update_scroll_mode_label(
+    scroll_speed: Res<ScrollSpeed>,
+    mut label_query: Query<&mut Text, With<ScrollModeLabel>>,
+) {
+    if scroll_speed.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = scroll_speed.mode_label().into();
+        }
+    }
+}
+
+/// Toggles mouse and touch input, and saves it
+fn on_pointer_input_button(mut pointer_input: ResMut<PointerInput>) {
+    pointer_input.0 = !pointer_input.0;
+    persist::save(POINTER_INPUT_FILE, &*pointer_input);
+}
+
+/// Keeps the settings menu's mouse and touch input text up to date
+fn update_pointer_input_label(
+    pointer_input: Res<PointerInput>,
+    mut label_query: Query<&mut Text, With<PointerInputLabel>>,
+) {
+    if pointer_input.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = pointer_input.label().into();
+        }
+    }
+}
+
+/// Toggles the beat pulse, and saves it
+fn on_beat_pulse_button(mut beat_pulse: ResMut<BeatPulse>) {
+    beat_pulse.0 = !beat_pulse.0;
+    persist::save(BEAT_PULSE_FILE, &*beat_pulse);
+}
+
+/// Keeps the settings menu's beat pulse text up to date
+fn update_beat_pulse_label(
+    beat_pulse: Res<BeatPulse>,
+    mut label_query: Query<&mut Text, With<BeatPulseLabel>>,
+) {
+    if beat_pulse.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = beat_pulse.label().into();
+        }
+    }
+}
+
+/// Toggles timing markers, and saves them
+fn on_timing_markers_button(mut timing_markers: ResMut<TimingMarkers>) {
+    timing_markers.0 = !timing_markers.0;
+    persist::save(TIMING_MARKERS_FILE, &*timing_markers);
+}
+
+/// Keeps the settings menu's timing markers text up to date
+fn update_timing_markers_label(
+    timing_markers: Res<TimingMarkers>,
+    mut label_query: Query<&mut Text, With<TimingMarkersLabel>>,
+) {
+    if timing_markers.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = timing_markers.label().into();
+        }
+    }
+}
+
+/// Toggles fast/slow labels, and saves them
+fn on_fast_slow_button(mut fast_slow: ResMut<FastSlow>) {
+    fast_slow.0 = !fast_slow.0;
+    persist::save(FAST_SLOW_FILE, &*fast_slow);
+}
+
+/// Keeps the settings menu's fast/slow labels text up to date
+fn update_fast_slow_label(
+    fast_slow: Res<FastSlow>,
+    mut label_query: Query<&mut Text, With<FastSlowLabel>>,
+) {
+    if fast_slow.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = fast_slow.label().into();
+        }
+    }
+}
+
+/// Toggles combo ducking, and saves it
+fn on_combo_ducking_button(mut combo_ducking: ResMut<ComboDucking>) {
+    combo_ducking.0 = !combo_ducking.0;
+    persist::save(COMBO_DUCKING_FILE, &*combo_ducking);
+}
+
+/// Keeps the settings menu's combo ducking text up to date
+fn update_combo_ducking_label(
+    combo_ducking: Res<ComboDucking>,
+    mut label_query: Query<&mut Text, With<ComboDuckingLabel>>,
+) {
+    if combo_ducking.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = combo_ducking.label().into();
+        }
+    }
+}
+
+/// Toggles announcements, and saves them
+fn on_announcements_button(mut announcements: ResMut<Announcements>) {
+    announcements.0 = !announcements.0;
+    persist::save(ANNOUNCEMENTS_FILE, &*announcements);
+}
+
+/// Keeps the settings menu's announcements text up to date
+fn update_announcements_label(
+    announcements: Res<Announcements>,
+    mut label_query: Query<&mut Text, With<AnnouncementsLabel>>,
+) {
+    if announcements.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = announcements.label().into();
+        }
+    }
+}
+
+/// Switches to the next hit window, and saves it
+fn on_hit_window_button(mut hit_window: ResMut<HitWindow>) {
+    *hit_window = hit_window.next();
+    persist::save(HIT_WINDOW_FILE, &*hit_window);
+}
+
+/// Keeps the settings menu's hit window text up to date
+fn update_hit_window_label(
+    hit_window: Res<HitWindow>,
+    mut label_query: Query<&mut Text, With<HitWindowLabel>>,
+) {
+    if hit_window.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = hit_window.label().into();
+        }
+    }
+}
+
+/// Switches to the next lane count, and saves it
+fn on_lanes_button(mut lanes: ResMut<Lanes>) {
+    *lanes = lanes.next();
+    persist::save(LANES_FILE, &*lanes);
+}
+
+/// Keeps the settings menu's lane count text up to date
+fn update_lanes_label(lanes: Res<Lanes>, mut label_query: Query<&mut Text, With<LanesLabel>>) {
+    if lanes.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = lanes.label();
+        }
+    }
+}
+
+/// Switches to the next frame rate limit, and saves it
+fn on_frame_rate_button(mut frame_rate: ResMut<FrameRate>) {
+    *frame_rate = frame_rate.next();
+    persist::save(FRAME_RATE_FILE, &*frame_rate);
+}
+
+/// Keeps the settings menu's frame rate text up to date
+fn update_frame_rate_label(
+    frame_rate: Res<FrameRate>,
+    mut label_query: Query<&mut Text, With<FrameRateLabel>>,
+) {
+    if frame_rate.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = frame_rate.label();
+        }
+    }
+}
+
+/// Turns vsync on or off to match the frame rate setting, as soon as it changes
+fn apply_frame_rate(frame_rate: Res<FrameRate>, mut windows: ResMut<Windows>) {
+    if frame_rate.is_changed() {
+        if let Some(window) = windows.get_primary_mut() {
+            window.set_present_mode(frame_rate.present_mode());
+        }
+    }
+}
+
+/// Sleeps out the rest of each frame while the frame rate is capped.
+///
+/// Targets move by however long the last frame took, so frames are spaced from one deadline
+/// to the next rather than from whenever the sleep ended, which keeps the pacing even
+fn limit_frame_rate(frame_rate: Res<FrameRate>, mut next_frame: Local<Option<Instant>>) {
+    let frame_time = match frame_rate.frame_time() {
+        Some(frame_time) => frame_time,
+        None => {
+            *next_frame = None;
+            return;
+        }
+    };
+
+    if let Some(remaining) =
+        next_frame.and_then(|deadline| deadline.checked_duration_since(Instant::now()))
+    {
+        thread::sleep(remaining);
+    }
+
+    let now = Instant::now();
+    *next_frame = Some(match *next_frame {
+        // A frame that ran long starts afresh, rather than rushing the next ones to catch up
+        Some(deadline) if now.duration_since(deadline) < frame_time => deadline + frame_time,
+        _ => now + frame_time,
+    });
+}
+
+/// Toggles the metronome, and saves it
+fn on_metronome_button(mut metronome: ResMut<Metronome>) {
+    metronome.0 = !metronome.0;
+    persist::save(METRONOME_FILE, &*metronome);
+}
+
+/// Keeps the settings menu's metronome text up to date
+fn update_metronome_label(
+    metronome: Res<Metronome>,
+    mut label_query: Query<&mut Text, With<MetronomeLabel>>,
+) {
+    if metronome.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = metronome.label().into();
+        }
+    }
+}
+
+/// Toggles streamer mode, and saves it
+fn on_streamer_mode_button(mut streamer_mode: ResMut<StreamerMode>) {
+    streamer_mode.0 = !streamer_mode.0;
+    persist::set_hide_paths(streamer_mode.0);
+    persist::save(STREAMER_MODE_FILE, &*streamer_mode);
+}
+
+/// Keeps the settings menu's streamer mode text up to date
+fn update_streamer_mode_label(
+    streamer_mode: Res<StreamerMode>,
+    mut label_query: Query<&mut Text, With<StreamerModeLabel>>,
+) {
+    if streamer_mode.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = streamer_mode.label().into();
+        }
+    }
+}
+
+/// Raises or lowers the judgment line when one of its buttons is clicked, and saves it
+fn on_judgment_line_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &JudgmentLineButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut judgment_line: ResMut<JudgmentLine>,
+) {
+    for (new_interaction, mut old_interaction, JudgmentLineButton(amount)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            judgment_line.0 = (judgment_line.0 + amount).clamp(0, JUDGMENT_LINE_LIMIT);
+            persist::save(JUDGMENT_LINE_FILE, &*judgment_line);
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Keeps the settings menu's judgment line text up to date
+fn update_judgment_line_label(
+    judgment_line: Res<JudgmentLine>,
+    mut label_query: Query<&mut Text, With<JudgmentLineLabel>>,
+) {
+    if judgment_line.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = judgment_line.label();
+        }
+    }
+}
+
+/// Toggles charge scoring, and saves it
+fn on_charge_scoring_button(mut charge_scoring: ResMut<ChargeScoring>) {
+    charge_scoring.0 = !charge_scoring.0;
+    persist::save(CHARGE_SCORING_FILE, &*charge_scoring);
+}
+
+/// Keeps the settings menu's charge scoring text up to date
+fn update_charge_scoring_label(
+    charge_scoring: Res<ChargeScoring>,
+    mut label_query: Query<&mut Text, With<ChargeScoringLabel>>,
+) {
+    if charge_scoring.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = charge_scoring.label().into();
+        }
+    }
+}
+
+/// Toggles training wheels, and saves them
+fn on_training_wheels_button(mut training_wheels: ResMut<TrainingWheels>) {
+    training_wheels.0 = !training_wheels.0;
+    persist::save(TRAINING_WHEELS_FILE, &*training_wheels);
+}
+
+/// Keeps the settings menu's training wheels text up to date
+fn update_training_wheels_label(
+    training_wheels: Res<TrainingWheels>,
+    mut label_query: Query<&mut Text, With<TrainingWheelsLabel>>,
+) {
+    if training_wheels.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = training_wheels.label().into();
+        }
+    }
+}
+
+/// Toggles key labels, and saves them
+fn on_key_labels_button(mut key_labels: ResMut<KeyLabels>) {
+    key_labels.0 = !key_labels.0;
+    persist::save(KEY_LABELS_FILE, &*key_labels);
+}
+
+/// Keeps the settings menu's key labels text up to date
+fn update_key_labels_label(
+    key_labels: Res<KeyLabels>,
+    mut label_query: Query<&mut Text, With<KeyLabelsLabel>>,
+) {
+    if key_labels.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = key_labels.label().into();
+        }
+    }
+}
+
+/// Toggles upscroll, and saves it
+fn on_up_scroll_button(mut up_scroll: ResMut<UpScroll>) {
+    up_scroll.0 = !up_scroll.0;
+    persist::save(UP_SCROLL_FILE, &*up_scroll);
+}
+
+/// Keeps the settings menu's upscroll text up to date
+fn update_up_scroll_label(
+    up_scroll: Res<UpScroll>,
+    mut label_query: Query<&mut Text, With<UpScrollLabel>>,
+) {
+    if up_scroll.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = up_scroll.label().into();
+        }
+    }
+}
+
+/// Toggles auto play for the rest of the session
+fn on_auto_play_button(mut auto_play: ResMut<AutoPlay>) {
+    auto_play.0 = !auto_play.0;
+}
+
+/// Keeps the settings menu's auto play text up to date
+fn update_auto_play_label(
+    auto_play: Res<AutoPlay>,
+    mut label_query: Query<&mut Text, With<AutoPlayLabel>>,
+) {
+    if auto_play.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = auto_play.label().into();
+        }
+    }
+}
+
+/// Plays the calibration metronome and measures how far off the beat the player's taps are.
+/// Columns are tapped with the same keys, buttons and pointer as in a run, so the offset measured
+/// includes any delay in them too
+fn run_calibration(
+    mut calibration: ResMut<Calibration>,
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    column_input: ColumnInput,
+    config: Res<GameConfig>,
+    audio: Res<Audio>,
+    volume: Res<Volume>,
+    sfx_handles: Res<SoundEffectHandles>,
+    mut readout_query: Query<&mut Text, With<CalibrationReadout>>,
+) {
+    if calibration.metronome.tick(time.delta()).just_finished() {
+        if let Some(tick) = &sfx_handles.tick {
+            audio.play_with_settings(tick.clone(), volume.sfx_settings());
+        }
+    }
+
+    let tapped = input.just_pressed(KeyCode::Space)
+        || config
+            .columns()
+            .any(|column| column_input.just_pressed(column));
+    if tapped {
+        let interval = CALIBRATION_INTERVAL.as_millis() as i32;
+        let mut offset = calibration.metronome.elapsed().as_millis() as i32;
+        // Taps closer to the next tick than the last one were early
+        if offset > interval / 2 {
+            offset -= interval;
+        }
+
+        calibration.taps.push(offset);
+        if calibration.taps.len() > CALIBRATION_TAPS {
+            calibration.taps.remove(0);
+        }
+
+        for mut readout in readout_query.iter_mut() {
+            readout.sections[0].value = calibration.readout();
+        }
+    }
+}
+
+/// Moves the calibration screen's note down its lane, so it reaches the line on every tick.
+/// Like targets in a run, it's drawn ahead by the visual offset
+fn move_calibration_note(
+    calibration: Res<Calibration>,
+    visual_offset: Res<VisualOffset>,
+    mut notes: Query<&mut Style, With<CalibrationNote>>,
+) {
+    let interval = CALIBRATION_INTERVAL.as_millis() as i32;
+    let elapsed = calibration.metronome.elapsed().as_millis() as i32 + visual_offset.0;
+    let percent = elapsed.rem_euclid(interval) as f32 / interval as f32;
+
+    let travel = CALIBRATION_LANE_HEIGHT - CALIBRATION_NOTE_SIZE;
+    for mut style in &mut notes {
+        style.position.top = Val::Px(percent * travel);
+    }
+}
+
+/// Changes the visual offset when one of the calibration screen's buttons is clicked, and saves it
+fn on_visual_offset_button(
+    mut interactions: Query<
+        (&Interaction, &mut OldInteraction, &VisualOffsetButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut visual_offset: ResMut<VisualOffset>,
+) {
+    for (new_interaction, mut old_interaction, VisualOffsetButton(amount)) in &mut interactions {
+        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
+            visual_offset.0 =
+                (visual_offset.0 + amount).clamp(-VISUAL_OFFSET_LIMIT, VISUAL_OFFSET_LIMIT);
+            persist::save(VISUAL_OFFSET_FILE, &*visual_offset);
+        }
+        old_interaction.0 = *new_interaction;
+    }
+}
+
+/// Keeps the calibration screen's visual offset text up to date
+fn update_visual_offset_label(
+    visual_offset: Res<VisualOffset>,
+    mut label_query: Query<&mut Text, With<VisualOffsetLabel>>,
+) {
+    if visual_offset.is_changed() {
+        for mut label in label_query.iter_mut() {
+            label.sections[0].value = visual_offset.label();
+        }
+    }
+}
+
+/// Draws targets further along than they really are by the visual offset, without moving them.
+///
+/// Only their [`GlobalTransform`]s are changed, which is what's drawn, so everything that judges
+/// them still sees their real [`Transform`]s. Transform propagation skips targets that haven't
+/// moved, so their drawn positions are always worked out afresh rather than nudged again
+fn apply_visual_offset(
+    visual_offset: Res<VisualOffset>,
+    fall_speed: FallSpeed,
+    config: Res<GameConfig>,
+    targets: Query<(Entity, &Transform), With<Target>>,
+    transforms: Query<(&Transform, Option<&Children>)>,
+    mut global_transforms: Query<&mut GlobalTransform>,
+) {
+    if visual_offset.0 == 0 {
+        return;
+    }
+
+    let shift = visual_offset.0 as f32 / 1000.0 * fall_speed.get();
+    for (target, transform) in &targets {
+        let mut drawn = *transform;
+        drawn.translation.y -= config.flip(shift);
+        draw_with_children(
+            target,
+            GlobalTransform::from(drawn),
+            &transforms,
+            &mut global_transforms,
+        );
+    }
+}
+
+/// Sets where an entity is drawn, and moves its children along with it
+fn draw_with_children(
+    entity: Entity,
+    drawn: GlobalTransform,
+    transforms: &Query<(&Transform, Option<&Children>)>,
+    global_transforms: &mut Query<&mut GlobalTransform>,
+) {
+    if let Ok(mut global_transform) = global_transforms.get_mut(entity) {
+        *global_transform = drawn;
+    }
+
+    if let Ok((_, Some(children))) = transforms.get(entity) {
+        for &child in children.iter() {
+            if let Ok((child_transform, _)) = transforms.get(child) {
+                let child_drawn = drawn.mul_transform(*child_transform);
+                draw_with_children(child, child_drawn, transforms, global_transforms);
+            }
+        }
+    }
+}
+
+/// Returns to the paused state
+fn on_resume_button(mut commands: Commands, resume_state: Res<ResumeState>) {
+    commands.insert_resource(NextState(resume_state.0));
+}
+
+/// Abandons the paused run and returns to the start menu
+fn on_quit_button(mut commands: Commands) {
+    // Without a state to resume, the game gets despawned on the way out
+    commands.remove_resource::<ResumeState>();
+    commands.insert_resource(NextState(GameState::StartMenu));
+}
+
+/// Starts a new run from the game over menu
+fn on_retry_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::Playing));
+}
+
+/// Returns to the start menu from the game over menu
+fn on_main_menu_button(mut commands: Commands) {
+    commands.insert_resource(NextState(GameState::StartMenu));
+}
+
+/// Exits the game
+fn on_exit_button(mut exit_writer: EventWriter<AppExit>) {
+    exit_writer.send(AppExit);
+}
+
+/// Sets up the game
+fn setup_game(
+    mut commands: Commands,
+    atlas_handles: Res<TextureAtlasHandles>,
+    asset_server: Res<AssetServer>,
+    mut config: ResMut<GameConfig>,
+    theme: Res<Theme>,
+    playfield: PlayfieldSettings,
+    start_mode: Res<StartMode>,
+    chart: Res<SelectedChart>,
+    beatmaps: Res<Assets<Beatmap>>,
+    practice: Res<PracticeLoop>,
+    auto_play: Res<AutoPlay>,
+    run_settings: RunSettings,
+    replay_playback: Option<ResMut<ReplayPlayback>>,
+    resumed_session: Option<Res<ResumedSession>>,
+    rematch: Option<Res<Rematch>>,
+    crosshair_keys: CrosshairKeys,
+) {
+    let difficulty = *run_settings.difficulty;
+    let modifiers = *run_settings.modifiers;
+
+    // Like the difficulty, the playfield's layout can only be changed from the menus
+    playfield.apply(&mut config);
+
+    match resumed_session.as_deref() {
+        Some(ResumedSession(session)) => session.restore(&mut commands),
+        None => {
+            // The scoreboard is only reset here so that the game over menu can still read it
+            commands.insert_resource(Scoreboard::default());
+            commands.insert_resource(Health::default());
+            commands.insert_resource(ChartPlayback::default());
+            // The difficulty can only be changed from the start menu, so it's fixed for the whole run
+            commands.insert_resource(SpawnTimer(Timer::new(difficulty.spawn_interval(), true)));
+            commands.insert_resource(SurvivalTime::default());
+        }
+    }
+    commands.insert_resource(ColumnInputState::default());
+    commands.insert_resource(TimingStats::default());
+    commands.insert_resource(BeatClock::default());
+
+    let mut game_rng = match replay_playback {
+        // Replays spawn the same targets as the run they were recorded from, and can't
+        // wait for the player to press a column before starting
+        Some(mut replay_playback) => {
+            replay_playback.restart();
+            commands.remove_resource::<ReplayRecorder>();
+            commands.insert_resource(StartMode::Countdown.countdown());
+            GameRng::from_seed(replay_playback.replay.seed)
+        }
+        None => {
+            // Rematches spawn the same targets as the run their code came from
+            let game_rng = match rematch {
+                Some(rematch) => GameRng::from_seed(rematch.seed),
+                None => GameRng::from_env_or_entropy(),
+            };
+            // Practice runs and auto play aren't worth keeping, and a resumed run is missing
+            // its start
+            if practice.enabled || auto_play.0 || resumed_session.is_some() {
+                commands.remove_resource::<ReplayRecorder>();
+            } else {
+                commands.insert_resource(ReplayRecorder {
+                    replay: Replay {
+                        chart: chart.path(&asset_server),
+                        seed: game_rng.seed,
+                        settings: run_settings.snapshot(),
+                        inputs: Vec::new(),
+                    },
+                    clock: Stopwatch::new(),
+                    held: HashSet::new(),
+                });
+                // This run takes the place of the last one, so there's nothing left to resume
+                persist::remove(SESSION_FILE);
+            }
+            commands.insert_resource(start_mode.countdown());
+            game_rng
+        }
+    };
+
+    // The shuffle is picked once, so the chart still fits together for the whole run.
+    // A resumed run carries on with the shuffle it started with
+    let column_mapping = match resumed_session.as_deref() {
+        Some(ResumedSession(session)) => session.column_mapping.clone(),
+        None if modifiers.random && chart.0.is_some() => {
+            ColumnMapping::shuffled(&config, &mut game_rng.rng)
+        }
+        None => ColumnMapping::default(),
+    };
+    commands.insert_resource(column_mapping);
+    commands.insert_resource(game_rng);
+
+    // The chart's own background, behind even the backdrop. An image that can't be loaded
+    // is never drawn, so the black clear colour shows through instead
+    let background = chart
+        .0
+        .as_ref()
+        .and_then(|chart| beatmaps.get(chart))
+        .and_then(|beatmap| beatmap.background.clone());
+    if let Some(background) = background {
+        let mut bundle = SpriteBundle {
+            transform: Transform::from_xyz(0.0, 0.0, -3.0),
+            ..Default::default()
+        };
+        match background {
+            Background::Image(path) => {
+                bundle.texture = asset_server.load(&path);
+                bundle.sprite.custom_size = Some(Vec2::new(PLAYFIELD_WIDTH, PLAYFIELD_HEIGHT));
+            }
+            Background::Color(r, g, b) => {
+                bundle.sprite.color = Color::rgb(r, g, b);
+                // Oversized like the backdrop, so it covers the whole window
+                bundle.sprite.custom_size =
+                    Some(Vec2::new(PLAYFIELD_WIDTH, PLAYFIELD_HEIGHT) * 3.0);
+            }
+        }
+        commands.spawn_bundle(bundle).insert(Game);
+    }
+
+    // A backdrop behind the lanes and targets, which pulses on every beat.
+    // It's oversized so it still covers the window when it's a different shape to the playfield
+    let mut pulse = Timer::new(PULSE_DURATION, false);
+    pulse.tick(PULSE_DURATION);
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+                custom_size: Some(Vec2::new(PLAYFIELD_WIDTH, PLAYFIELD_HEIGHT) * 3.0),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, -2.0),
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(Backdrop(pulse));
+
+    // Faint lanes running from where targets spawn down to the crosshairs
+    for column in config.columns() {
+        let mut lane_color = theme.color(column);
+        lane_color.set_a(0.15);
+
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: lane_color,
+                    custom_size: Some(Vec2::new(60.0, config.spawn_y - config.hit_y)),
+                    anchor: config.trailing_anchor(),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(
+                    config.column_x(column),
+                    config.flip(config.hit_y),
+                    -1.0,
+                ),
+                ..Default::default()
+            })
+            .insert(Game);
+    }
+
+    // A resumed run's targets carry on falling from where they were
+    if let Some(ResumedSession(session)) = resumed_session.as_deref() {
+        match &atlas_handles.targets {
+            Some(atlas_handle) => {
+                let scroll_speed = run_settings.scroll_speed.apply(difficulty, &config);
+                for target in &session.targets {
+                    if target.column.index() >= config.lanes {
+                        warn!(
+                            "Skipping a resumed target in {}, which isn't on the playfield",
+                            target.column
+                        );
+                        continue;
+                    }
+                    spawn_target(
+                        &mut commands,
+                        atlas_handle,
+                        &config,
+                        &theme,
+                        target.column,
+                        target.duration_ms,
+                        scroll_speed,
+                        target.height,
+                    );
+                }
+            }
+            None => warn!("The target textures haven't been loaded, so targets can't be resumed"),
+        }
+        commands.remove_resource::<ResumedSession>();
+    }
+
+    // Without their textures the crosshairs are left out, but the rest of the playfield isn't
+    if atlas_handles.crosshairs.is_none() {
+        warn!("The crosshair textures haven't been loaded, so they can't be shown");
+    }
+
+    for column in config.columns() {
+        if let Some(atlas_handle) = &atlas_handles.crosshairs {
+            let mut crosshair = commands.spawn_bundle(SpriteSheetBundle {
+                transform: Transform::from_xyz(
+                    config.column_x(column),
+                    config.flip(config.hit_y),
+                    0.0,
+                )
+                .with_scale(Vec3::splat(config.target_scale)),
+                sprite: TextureAtlasSprite {
+                    index: column.sprite_index(),
+                    color: theme.tint(column),
+                    custom_size: Some(Vec2::splat(200.0)),
+                    ..Default::default()
+                },
+                texture_atlas: atlas_handle.clone(),
+                ..Default::default()
+            });
+            crosshair
+                .insert(Game)
+                .insert(Crosshair)
+                .insert(FlashTimer::new(theme.tint(column), config.target_scale))
+                .insert(column);
+
+            if config.column_shapes {
+                crosshair.with_children(|crosshair| column.shape().spawn(crosshair));
+            }
+        }
+
+        if let Some(label) = crosshair_keys.label(column) {
+            commands
+                .spawn_bundle(Text2dBundle {
+                    text: Text::from_section(
+                        label,
+                        TextStyle {
+                            font: asset_server.load("fonts/comic.ttf"),
+                            font_size: 18.0,
+                            color: Color::WHITE,
+                        },
+                    )
+                    .with_alignment(TextAlignment::CENTER),
+                    // Over the crosshair, but under the targets falling through it
+                    transform: Transform::from_xyz(
+                        config.column_x(column),
+                        config.flip(config.hit_y),
+                        0.5,
+                    ),
+                    ..Default::default()
+                })
+                .insert(Game)
+                .insert(KeyLabel(column));
+        }
+    }
+
+    // The score and health sit at the opposite end of the screen from the crosshairs
+    let hud_y = if config.upscroll { -230.0 } else { 300.0 };
+
+    let score_textstyle = TextStyle {
+        font: asset_server.load("fonts/comic.ttf"),
+        font_size: 36.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::from_sections([
+                TextSection {
+                    value: "Score: ".into(),
+                    style: score_textstyle.clone(),
+                },
+                TextSection {
+                    value: "0".into(),
+                    style: score_textstyle.clone(),
+                },
+                TextSection {
+                    value: "\nAccuracy: ".into(),
+                    style: TextStyle {
+                        font_size: 24.0,
+                        ..score_textstyle.clone()
+                    },
+                },
+                TextSection {
+                    value: "—".into(),
+                    style: TextStyle {
+                        font_size: 24.0,
+                        ..score_textstyle
+                    },
+                },
+            ]),
+            transform: Transform::from_xyz(-200.0, hud_y, 0.0),
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(ScoreDisplay);
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/comic.ttf"),
+                    font_size: 48.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_alignment(TextAlignment::CENTER),
+            transform: Transform::from_xyz(0.0, 0.0, 2.0),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(ComboDisplay);
+
+    let countdown = start_mode.countdown();
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::from_sections([
+                TextSection::new(
+                    countdown.label(),
+                    TextStyle {
+                        font: asset_server.load("fonts/comic.ttf"),
+                        font_size: 96.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    countdown.hint(),
+                    TextStyle {
+                        font: asset_server.load("fonts/comic.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ),
+            ])
+            .with_alignment(TextAlignment::CENTER),
+            transform: Transform::from_xyz(0.0, 0.0, 3.0),
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(CountdownDisplay);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.3, 0.3, 0.3),
+                custom_size: Some(Vec2::new(HEALTH_BAR_WIDTH, 16.0)),
+                anchor: Anchor::CenterLeft,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(60.0, hud_y - 18.0, 0.0),
+            ..Default::default()
+        })
+        .insert(Game);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.2, 0.8, 0.2),
+                custom_size: Some(Vec2::new(HEALTH_BAR_WIDTH, 16.0)),
+                anchor: Anchor::CenterLeft,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(60.0, hud_y - 18.0, 1.0),
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(HealthBar);
+
+    // With the adaptive modifier, show how fast targets are spawning where the practice loop
+    // would be, which only charts have
+    if modifiers.adaptive && chart.0.is_none() {
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    spawn_rate_label(difficulty.spawn_interval()),
+                    TextStyle {
+                        font: asset_server.load("fonts/comic.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                transform: Transform::from_xyz(60.0, hud_y + 20.0, 0.0),
+                ..Default::default()
+            })
+            .insert(Game)
+            .insert(SpawnRateDisplay);
+    }
+
+    // The timing statistics go under the score, or above it when the crosshairs are at the top
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::from_section(
+                TimingStats::default().label(),
+                TextStyle {
+                    font: asset_server.load("fonts/comic.ttf"),
+                    font_size: 18.0,
+                    color: Color::rgb(0.7, 0.7, 0.7),
+                },
+            ),
+            transform: Transform::from_xyz(
+                -200.0,
+                if config.upscroll {
+                    hud_y + 80.0
+                } else {
+                    hud_y - 80.0
+                },
+                0.0,
+            ),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(TimingStatsDisplay);
+
+    // Endless runs show how long they've lasted under the health bar
+    if run_settings.endless.0 && chart.0.is_none() {
+        let survived = match resumed_session.as_deref() {
+            Some(ResumedSession(session)) => Duration::from_millis(session.survived_ms),
+            None => Duration::ZERO,
+        };
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    format!("Time: {}", format_survival_time(survived)),
+                    TextStyle {
+                        font: asset_server.load("fonts/comic.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                transform: Transform::from_xyz(60.0, hud_y - 30.0, 0.0),
+                ..Default::default()
+            })
+            .insert(Game)
+            .insert(SurvivalDisplay);
+    }
+
+    // Show which section is being looped next to the health bar
+    if practice.enabled && chart.0.is_some() {
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    format!(
+                        "Practice: {}s - {}s",
+                        practice.start_ms / 1000,
+                        practice.end_ms / 1000
+                    ),
+                    TextStyle {
+                        font: asset_server.load("fonts/comic.ttf"),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                transform: Transform::from_xyz(60.0, hud_y + 20.0, 0.0),
+                ..Default::default()
+            })
+            .insert(Game);
+    }
+
+    // Random mode never ends, so there's no progress to show
+    if chart.0.is_some() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.8, 0.8, 0.8),
+                    custom_size: Some(Vec2::new(PLAYFIELD_WIDTH, 4.0)),
+                    anchor: Anchor::CenterLeft,
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(
+                    -PLAYFIELD_WIDTH / 2.0,
+                    PLAYFIELD_HEIGHT / 2.0 - 2.0,
+                    1.0,
+                )
+                .with_scale(Vec3::new(0.0, 1.0, 1.0)),
+                ..Default::default()
+            })
+            .insert(Game)
+            .insert(ProgressBar);
+    }
+}
+
+/// Exit to the start menu if the player pressed the menu key
+fn menu_on_key(mut commands: Commands, input: Res<Input<KeyCode>>, key_bindings: Res<KeyBindings>) {
+    if key_bindings.just_pressed(&input, Action::Menu) {
+        commands.insert_resource(NextState(GameState::StartMenu));
+    }
+}
+
+/// Exit to the song select menu if the player pressed the menu key
+fn song_select_on_key(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+) {
+    if key_bindings.just_pressed(&input, Action::Menu) {
+        commands.insert_resource(NextState(GameState::SongSelect));
+    }
+}
+
+/// Quit the game if the player pressed the menu key
+fn exit_on_key(
+    input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut exit_writer: EventWriter<AppExit>,
+) {
+    if key_bindings.just_pressed(&input, Action::Menu) {
+        exit_writer.send(AppExit);
+    }
+}
+
+/// Spawns a single target at the top of the given column,
+/// with a tail to hold down if it has a duration
+fn spawn_target(
+    commands: &mut Commands,
+    atlas_handle: &Handle<TextureAtlas>,
+    config: &GameConfig,
+    theme: &Theme,
+    column: Column,
+    duration_ms: u32,
+    scroll_speed: f32,
+    height: f32,
+) -> Entity {
+    let mut target = commands.spawn_bundle(SpriteSheetBundle {
+        transform: Transform::from_xyz(config.column_x(column), config.flip(height), 0.0)
+            .with_scale(Vec3::splat(config.target_scale)),
+        sprite: TextureAtlasSprite {
+            index: column.sprite_index(),
+            color: theme.tint(column),
+            custom_size: Some(Vec2::splat(200.0)),
+            ..Default::default()
+        },
+        texture_atlas: atlas_handle.clone(),
+        ..Default::default()
+    });
+    target.insert(Game).insert(Target::default()).insert(column);
+
+    if config.column_shapes {
+        target.with_children(|target| column.shape().spawn(target));
+    }
+
+    if duration_ms > 0 {
+        let hold_note = HoldNote {
+            duration_ms,
+            held: None,
+        };
+        // The tail is a child of the scaled-down target, so its size is scaled back up to match
+        let tail_size = Vec2::new(20.0, hold_note.length(scroll_speed)) / config.target_scale;
+        let mut tail_color = theme.color(column);
+        tail_color.set_a(0.6);
+
+        target.insert(hold_note).with_children(|target| {
+            target
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: tail_color,
+                        custom_size: Some(tail_size),
+                        anchor: config.trailing_anchor(),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, -0.1),
+                    ..Default::default()
+                })
+                .insert(Game)
+                .insert(BaseAlpha(tail_color.a()));
+        });
+    }
+
+    target.id()
+}
+
+/// Ticks down the countdown before the game starts, then lets it begin
+fn run_countdown(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut countdown: ResMut<Countdown>,
+    input: ColumnInput,
+    config: Res<GameConfig>,
+    mut display_query: Query<(Entity, &mut Text), With<CountdownDisplay>>,
+) {
+    // Nothing starts until the countdown is removed, so the song and the first notes
+    // all start together on the frame after the press
+    let started = if countdown.waiting_for_input {
+        config.columns().any(|column| input.just_pressed(column))
+    } else {
+        countdown.timer.tick(time.delta()).finished()
+    };
+
+    if started {
+        commands.remove_resource::<Countdown>();
+        for (entity, _) in display_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    for (_, mut text) in display_query.iter_mut() {
+        text.sections[0].value = countdown.label();
+        text.sections[1].value = countdown.hint().into();
+    }
+}
+
+/// Pause the game if the player pressed the pause key
+fn pause_on_key(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+) {
+    if key_bindings.just_pressed(&input, Action::Pause) {
+        commands.insert_resource(ResumeState(GameState::Playing));
+        commands.insert_resource(NextState(GameState::Paused));
+    }
+}
+
+/// Pause the game when the window loses focus, which it does before being minimized.
+///
+/// Minimized windows can be throttled, so the first frame back would otherwise move targets
+/// by however long the window was away, past the crosshairs all at once
+fn pause_on_focus_lost(mut commands: Commands, mut focus_event_reader: EventReader<WindowFocused>) {
+    if focus_event_reader.iter().any(|event| !event.focused) {
+        commands.insert_resource(ResumeState(GameState::Playing));
+        commands.insert_resource(NextState(GameState::Paused));
+    }
+}
+
+/// Restart the run from the beginning if the player pressed the restart key,
+/// keeping the same chart and difficulty.
+///
+/// Re-entering [`GameState::Playing`] despawns the old run and sets up a new one,
+/// just like retrying from the game over menu
+fn restart_on_key(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+) {
+    // Bindings saved before actions could be rebound might have the restart key on a column,
+    // in which case pressing it is just playing the game
+    let restart_key = key_bindings.action_key(Action::Restart);
+    if input.just_pressed(restart_key) && key_bindings.column_for(restart_key).is_none() {
+        commands.insert_resource(NextState(GameState::Playing));
+    }
+}
+
+/// Resume the game if the player pressed the pause or menu key while paused
+fn resume_on_key(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    resume_state: Res<ResumeState>,
+) {
+    if key_bindings.just_pressed(&input, Action::Pause)
+        || key_bindings.just_pressed(&input, Action::Menu)
+    {
+        commands.insert_resource(NextState(resume_state.0));
+    }
+}
+
+/// Spawns a target in a random column every time the spawn timer finishes
+fn spawn_targets(
+    mut commands: Commands,
+    atlas_handles: Res<TextureAtlasHandles>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+    mut game_rng: ResMut<GameRng>,
+    fall_speed: FallSpeed,
+    survival_time: Res<SurvivalTime>,
+    config: Res<GameConfig>,
+    theme: Res<Theme>,
+    time: Res<Time>,
+    target_query: Query<(&Transform, &Column), With<Target>>,
+) {
+    let atlas_handle = match &atlas_handles.targets {
+        Some(atlas_handle) => atlas_handle,
+        None => return,
+    };
+
+    // Endless runs spawn faster as they go on, on top of whatever the adaptive modifier does
+    spawn_timer
+        .0
+        .tick(time.delta().mul_f32(survival_time.speed_up()));
+    let spawns = spawn_timer.0.times_finished_this_tick();
+    if spawns == 0 {
+        return;
+    }
+
+    // A column is blocked while its newest target is still less than a target's height from
+    // where targets spawn, or a new one would be drawn on top of it
+    let target_size = 200.0 * config.target_scale;
+    let mut blocked: HashSet<Column> = target_query
+        .iter()
+        .filter(|(transform, _)| {
+            (config.flip(transform.translation.y) - config.spawn_y).abs() < target_size
+        })
+        .map(|(_, column)| *column)
+        .collect();
+
+    for _ in 0..spawns {
+        let mut column = config.random_column(&mut game_rng.rng);
+        if blocked.contains(&column) {
+            // The next free column along takes its place. It's found without the random number
+            // generator, so seeded runs draw the same numbers however the columns were blocked
+            let next_free = (1..config.lanes)
+                .map(|step| Column((column.0 + step) % config.lanes))
+                .find(|column| !blocked.contains(column));
+            column = match next_free {
+                Some(column) => column,
+                // Every column is blocked, so this spawn is skipped
+                None => continue,
+            };
+        }
+        blocked.insert(column);
+        spawn_target(
+            &mut commands,
+            atlas_handle,
+            &config,
+            &theme,
+            column,
+            0,
+            fall_speed.get(),
+            config.spawn_y,
+        );
+    }
+}
+
+/// How fast targets are spawning, e.g. "Rate: 2.9/s"
+fn spawn_rate_label(interval: Duration) -> String {
+    format!(
+        "Rate: {:.1}/s",
+        1.0 / interval.as_secs_f32().max(f32::EPSILON)
+    )
+}
+
+/// Nudges the spawn interval down a little for every hit while the player keeps a streak going,
+/// and back up after every miss, within [`ADAPTIVE_RANGE`] of the difficulty's interval.
+/// Each step is small, so the pace changes gradually rather than all at once
+fn adapt_spawn_rate(
+    mut hit_event_reader: EventReader<TargetHitEvent>,
+    mut miss_event_reader: EventReader<TargetMissEvent>,
+    score: Res<Scoreboard>,
+    difficulty: Res<Difficulty>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+) {
+    let hits = hit_event_reader.iter().count() as i32;
+    let misses = miss_event_reader.iter().count() as i32;
+    let speed_ups = if score.streak >= ADAPTIVE_STREAK {
+        hits
+    } else {
+        0
+    };
+    if speed_ups == 0 && misses == 0 {
+        return;
+    }
+
+    let base = difficulty.spawn_interval().as_secs_f32();
+    let interval = spawn_timer.0.duration().as_secs_f32()
+        * ADAPTIVE_SPEED_UP.powi(speed_ups)
+        * ADAPTIVE_EASE_OFF.powi(misses);
+    let interval = interval.clamp(base * ADAPTIVE_RANGE.0, base * ADAPTIVE_RANGE.1);
+    spawn_timer
+        .0
+        .set_duration(Duration::from_secs_f32(interval));
+}
+
+/// Keeps the spawn rate shown with the adaptive modifier up to date
+fn update_spawn_rate_display(
+    spawn_timer: Res<SpawnTimer>,
+    mut display_query: Query<&mut Text, With<SpawnRateDisplay>>,
+) {
+    if spawn_timer.is_changed() {
+        for mut display in &mut display_query {
+            display.sections[0].value = spawn_rate_label(spawn_timer.0.duration());
+        }
+    }
+}
+
+/// Returns true if the adaptive modifier is turned on
+fn adaptive(modifiers: Res<Modifiers>) -> bool {
+    modifiers.adaptive
+}
+
+/// Returns true if the run is an endless one
+fn endless(endless: Res<EndlessMode>, chart: Res<SelectedChart>) -> bool {
+    endless.0 && chart.0.is_none()
+}
+
+/// Counts up how long the player has survived an endless run
+fn track_survival_time(
+    time: Res<Time>,
+    mut survival_time: ResMut<SurvivalTime>,
+    mut display_query: Query<&mut Text, With<SurvivalDisplay>>,
+) {
+    survival_time.0 += time.delta();
+    for mut display in &mut display_query {
+        display.sections[0].value = format!("Time: {}", format_survival_time(survival_time.0));
+    }
+}
+
+/// Returns true if a chart has been selected instead of random spawning
+fn chart_selected(chart: Res<SelectedChart>) -> bool {
+    chart.0.is_some()
+}
+
+/// Returns true if a chart is being practiced
+fn practicing(practice: Res<PracticeLoop>, chart: Res<SelectedChart>) -> bool {
+    practice.enabled && chart.0.is_some()
+}
+
+/// Returns true if the run is the player's own, and can be saved to resume later
+fn run_can_be_resumed(
+    practice: Res<PracticeLoop>,
+    chart: Res<SelectedChart>,
+    auto_play: Res<AutoPlay>,
+    replay_playback: Option<Res<ReplayPlayback>>,
+) -> bool {
+    // Practice runs loop forever, and auto play and replays don't need finishing
+    !(practice.enabled && chart.0.is_some() || auto_play.0 || replay_playback.is_some())
+}
+
+/// Records an unfinished run in the player's bests and the run stats when the window is closed,
+/// since it won't reach the game over menu that normally does it.
+///
+/// Runs that are saved to be resumed aren't over yet, so they're left to be recorded once
+/// they're finished
+fn record_unfinished_run(mut record: RunRecord) {
+    record.save_high_score();
+    record.log_stats(GameResult::Quit);
+}
+
+/// Quits the game when the window is closed, once everything else has seen the request
+/// and had the chance to save
+fn exit_on_close_requested(
+    mut close_events: EventReader<WindowCloseRequested>,
+    mut exit_writer: EventWriter<AppExit>,
+) {
+    if close_events.iter().count() > 0 {
+        exit_writer.send(AppExit);
+    }
+}
+
+/// Returns true if the player is closing the window
+fn window_close_requested(mut close_events: EventReader<WindowCloseRequested>) -> bool {
+    close_events.iter().count() > 0
+}
+
+/// Saves the current run, so it can be resumed from the start menu if it's left unfinished
+fn save_session(
+    score: Res<Scoreboard>,
+    health: Res<Health>,
+    playback: Res<ChartPlayback>,
+    spawn_timer: Res<SpawnTimer>,
+    chart: Res<SelectedChart>,
+    asset_server: Res<AssetServer>,
+    run_settings: RunSettings,
+    column_mapping: Res<ColumnMapping>,
+    survival_time: Res<SurvivalTime>,
+    config: Res<GameConfig>,
+    targets: Query<(&Transform, &Column, Option<&HoldNote>), With<Target>>,
+) {
+    let targets = targets
+        .iter()
+        // A held note's head has already been hit, and its tail can't be held again
+        .filter(|(_, _, hold_note)| hold_note.is_none_or(|hold_note| hold_note.held.is_none()))
+        .map(|(transform, column, hold_note)| SavedTarget {
+            column: *column,
+            height: config.flip(transform.translation.y),
+            duration_ms: hold_note.map_or(0, |hold_note| hold_note.duration_ms),
+        })
+        .collect();
+
+    persist::save(
+        SESSION_FILE,
+        &SavedSession {
+            chart: chart.path(&asset_server),
+            settings: run_settings.snapshot(),
+            score: score.clone(),
+            health: health.clone(),
+            chart_ms: playback.clock.elapsed().as_millis() as u64,
+            next_note: playback.next_note,
+            spawn_interval_ms: spawn_timer.0.duration().as_millis() as u64,
+            spawn_elapsed_ms: spawn_timer.0.elapsed().as_millis() as u64,
+            column_mapping: column_mapping.clone(),
+            survived_ms: survival_time.0.as_millis() as u64,
+            targets,
+        },
+    );
+}
+
+/// Forgets the saved run once it's over, as there's nothing left to resume
+fn discard_saved_session() {
+    persist::remove(SESSION_FILE);
+}
+
+/// Returns true if the game is hitting targets instead of the player
+fn auto_playing(auto_play: Res<AutoPlay>, replay_playback: Option<Res<ReplayPlayback>>) -> bool {
+    // A replay plays itself, so auto play stays out of its way
+    auto_play.0 && replay_playback.is_none()
+}
+
+/// Returns true if the player has turned the metronome on
+fn metronome_enabled(metronome: Res<Metronome>) -> bool {
+    metronome.0
+}
+
+/// Spawns the selected chart's targets as the run's clock passes each note
+fn spawn_from_chart(
+    mut commands: Commands,
+    chart: Res<SelectedChart>,
+    beatmaps: Res<Assets<Beatmap>>,
+    asset_server: Res<AssetServer>,
+    atlas_handles: Res<TextureAtlasHandles>,
+    mut playback: ResMut<ChartPlayback>,
+    time: Res<Time>,
+    mut song_player: SongPlayer,
+    run_settings: RunSettings,
+    column_mapping: Res<ColumnMapping>,
+    config: Res<GameConfig>,
+    theme: Res<Theme>,
+    mut practice: ResMut<PracticeLoop>,
+    mut score: ResMut<Scoreboard>,
+    mut health: ResMut<Health>,
+) {
+    let chart_handle = chart.0.as_ref().unwrap();
+
+    let beatmap = match beatmaps.get(chart_handle) {
+        Some(beatmap) => beatmap,
+        None => {
+            if asset_server.get_load_state(chart_handle) == LoadState::Failed {
+                error!("Failed to load the selected chart, returning to the start menu");
+                commands.insert_resource(NextState(GameState::StartMenu));
+            }
+            // Don't start the clock until the chart is ready
+            return;
+        }
+    };
+
+    let scroll_speed = run_settings
+        .scroll_speed
+        .apply(*run_settings.difficulty, &config);
+    let audio_offset_ms = i64::from(run_settings.audio_offset.0);
+    // Notes are timed for when they reach the crosshair, so they spawn this long before then
+    let travel_ms = config.travel_ms(scroll_speed) as i64;
+
+    if !playback.started {
+        if let Some(song_path) = beatmap.song.as_ref() {
+            let song = playback
+                .song
+                .get_or_insert_with(|| asset_server.load(song_path.as_str()))
+                .clone();
+
+            // Wait for the song to load, so it can start right as the lead-in ends
+            match asset_server.get_load_state(&song) {
+                LoadState::Loaded => {}
+                LoadState::Failed => {
+                    warn!("Failed to load the chart's song, playing without it");
+                    playback.song = None;
+                }
+                _ => return,
+            }
+        }
+
+        // The clock starts early enough for even a note right at the start of the song
+        // to spawn on time
+        let first_note_ms = beatmap
+            .notes
+            .first()
+            .map_or(0, |note| i64::from(note.time_ms));
+        let lead_in_ms = (travel_ms - first_note_ms - audio_offset_ms)
+            .max(i64::from(config.lead_in_ms))
+            .max(0);
+        playback.lead_in = Duration::from_millis(lead_in_ms as u64);
+        playback.started = true;
+
+        // A loop that runs past the end of the chart would sit there with nothing to play
+        if practice.enabled {
+            practice.clamp_to(beatmap.end_ms());
+        }
+    }
+
+    let atlas_handle = match &atlas_handles.targets {
+        Some(atlas_handle) => atlas_handle,
+        None => return,
+    };
+
+    playback.clock.tick(time.delta());
+
+    // The song starts on the frame the lead-in ends. Whatever the frame overshot by is
+    // taken back off the clock, so the notes stay in time with the song.
+    // Practice loops and resumed runs start the song from wherever the chart has got to instead
+    if playback.song_sink.is_none() && playback.song_time_ms() >= 0 {
+        if let Some(song) = playback.song.clone() {
+            if practice.enabled || playback.resumed {
+                let start_ms = playback.song_time_ms() as u32;
+                playback.song_sink = song_player.play_from(&song, start_ms);
+                if playback.song_sink.is_none() {
+                    warn!("Only WAV songs can be started part way through, playing without it");
+                    playback.song = None;
+                }
+            } else {
+                playback.song_sink = song_player.play(&song);
+                let lead_in = playback.lead_in;
+                playback.clock.set_elapsed(lead_in);
+            }
+        }
+    }
+
+    // Notes are delayed by the audio offset so they line up with the song the player hears.
+    // The notes spawning now are the ones due at the crosshair a fall's length from now
+    let mut due_ms = playback.song_time_ms() - audio_offset_ms + travel_ms;
+
+    // Practice runs jump to the start of the loop when they begin, and again whenever they reach
+    // its end. Targets already falling are left to finish, so the loop carries straight on
+    if practice.enabled
+        && !(i64::from(practice.start_ms)..i64::from(practice.end_ms)).contains(&due_ms)
+    {
+        due_ms = i64::from(practice.start_ms);
+        let clock_ms = (due_ms - travel_ms + audio_offset_ms + playback.lead_in.as_millis() as i64)
+            .max(0) as u64;
+        playback.clock.set_elapsed(Duration::from_millis(clock_ms));
+        // The song starts again from the loop's start, once it gets there
+        if let Some(sink) = playback.song_sink.take() {
+            if let Some(sink) = song_player.sinks.get(&sink) {
+                sink.stop();
+            }
+        }
+        playback.next_note = beatmap
+            .notes
+            .partition_point(|note| note.time_ms < practice.start_ms);
+
+        // Every pass through the loop starts afresh, so nothing builds up between them
+        *score = Scoreboard::default();
+        *health = Health::default();
+    }
+
+    while let Some(note) = beatmap.notes.get(playback.next_note) {
+        if i64::from(note.time_ms) > due_ms {
+            break;
+        }
+        if note.column.index() >= config.lanes {
+            warn!(
+                "Skipping a note in {}, which isn't on the playfield",
+                note.column
+            );
+            playback.next_note += 1;
+            continue;
+        }
+        spawn_target(
+            &mut commands,
+            atlas_handle,
+            &config,
+            &theme,
+            column_mapping.apply(note.column),
+            note.duration_ms,
+            scroll_speed,
+            config.spawn_y,
+        );
+        playback.next_note += 1;
+    }
+}
+
+/// The longest a single frame can move targets for in a random run.
+/// A longer hitch would otherwise carry targets so far in one go that they'd jump past the
+/// crosshairs without ever being hittable. Charted runs aren't capped, as their targets have to
+/// keep up with the chart's clock and the song, which don't wait for a hitch
+const MAX_FRAME_DELTA: Duration = Duration::from_millis(100);
+
+fn update_targets(
+    mut commands: Commands,
+    mut targets: Query<(
+        Entity,
+        &mut Target,
+        &mut Transform,
+        &Column,
+        Option<&HoldNote>,
+    )>,
+    time: Res<Time>,
+    fall_speed: FallSpeed,
+    config: Res<GameConfig>,
+    scoring: Res<ScoringConfig>,
+    mut hit_event_writer: EventWriter<TargetHitEvent>,
+    mut miss_event_writer: EventWriter<TargetMissEvent>,
+    mut score: ResMut<Scoreboard>,
+    mut health: ResMut<Health>,
+) {
+    let scroll_speed = fall_speed.get();
+    let delta = fall_speed.frame().as_secs_f32();
+
+    for (target, mut fall, mut transform, column, hold_note) in targets.iter_mut() {
+        fall.moved_at = Some(time.seconds_since_startup());
+        let held =
+            hold_note.and_then(|hold_note| Some((hold_note.length(scroll_speed), hold_note.held?)));
+
+        if let Some((length, judgment)) = held {
+            // Held notes are finished once the end of their tail reaches the crosshair
+            if config.flip(transform.translation.y) + length <= config.hit_y {
+                commands.entity(target).despawn_recursive();
+                hit_event_writer.send(TargetHitEvent(*column, judgment));
+                score.hit(judgment, &scoring);
+            } else {
+                transform.translation.y -= config.flip(scroll_speed * delta);
+            }
+        } else if config.flip(transform.translation.y) < config.despawn_y {
+            commands.entity(target).despawn_recursive();
+            miss_event_writer.send(TargetMissEvent(*column));
+            score.miss();
+            health.current -= MISS_HEALTH_PENALTY;
+        } else {
+            transform.translation.y -= config.flip(scroll_speed * delta);
+        }
+    }
+}
+
+/// Fades targets, along with their tails and shapes, according to the chosen modifiers.
+/// This only changes how they look, so they're judged just the same
+fn fade_targets(
+    modifiers: Res<Modifiers>,
+    config: Res<GameConfig>,
+    mut targets: Query<(&Transform, &mut TextureAtlasSprite, Option<&Children>), With<Target>>,
+    mut attached: Query<(&mut Sprite, &BaseAlpha)>,
+) {
+    if !modifiers.hidden && !modifiers.sudden {
+        return;
+    }
+
+    for (transform, mut sprite, children) in targets.iter_mut() {
+        let opacity = modifiers.opacity(&config, config.flip(transform.translation.y));
+        sprite.color.set_a(opacity);
+
+        for &child in children.iter().flat_map(|children| children.iter()) {
+            if let Ok((mut child_sprite, BaseAlpha(base_alpha))) = attached.get_mut(child) {
+                child_sprite.color.set_a(base_alpha * opacity);
+            }
+        }
+    }
+}
+
+fn shoot_targets(
+    mut commands: Commands,
+    mut targets: Query<(Entity, &Transform, &Column, Option<&mut HoldNote>, &Target)>,
+    column_input: ColumnInput,
+    time: Res<Time>,
+    mut input_state: ResMut<ColumnInputState>,
+    playback: Res<ChartPlayback>,
+    run_settings: RunSettings,
+    fall_speed: FallSpeed,
+    config: Res<GameConfig>,
+    punish_ghost_taps: Res<PunishGhostTaps>,
+    scoring: Res<ScoringConfig>,
+    mut hit_event_writer: EventWriter<TargetHitEvent>,
+    mut miss_event_writer: EventWriter<TargetMissEvent>,
+    mut timing_event_writer: EventWriter<HitTimingEvent>,
+    mut score: ResMut<Scoreboard>,
+) {
+    let scroll_speed = fall_speed.get();
+    // Targets only moved as far as this frame let them, so presses are corrected by the same
+    let frame = fall_speed.frame();
+    let hit_window = *run_settings.hit_window;
+    let charge_scoring = run_settings.charge_scoring.0;
+    let threshold_y = hit_window.threshold_y(&config);
+    let late_y = hit_window.late_y(&config);
+
+    // Songs are already delayed by the audio offset, so only shift the judgment without one
+    let offset = if playback.song.is_none() {
+        run_settings.audio_offset.0 as f32 / 1000.0 * scroll_speed
+    } else {
+        0.0
+    };
+
+    // Heights are compared as if targets were falling, whichever way they're going
+    let judged_y = |transform: &Transform, fall: &Target| {
+        config.flip(transform.translation.y)
+            + offset
+            + fall.press_correction(&time, frame, scroll_speed)
+    };
+
+    // Sort the targets into their columns in one pass, so each column only goes over its own
+    let mut column_targets: Vec<Vec<Entity>> = vec![Vec::new(); usize::from(config.lanes)];
+    for (target, _, column, ..) in targets.iter() {
+        if let Some(column_targets) = column_targets.get_mut(usize::from(column.index())) {
+            column_targets.push(target);
+        }
+    }
+
+    for key_column in config.columns() {
+        let just_pressed = column_input.just_pressed(key_column);
+        let pressed = column_input.pressed(key_column);
+        if just_pressed || !pressed {
+            input_state.consumed.remove(&key_column);
+        }
+
+        // A charging target is judged once its press is let go, by where it's got to
+        let mut released = None;
+        if !pressed {
+            if let Some(target) = input_state.charging.remove(&key_column) {
+                // It may have already fallen past the hit window and been missed. One that's past
+                // the hit window but not yet missed is left for update_targets to miss, so it's
+                // never missed twice
+                let charged = targets
+                    .get(target)
+                    .ok()
+                    .filter(|(_, transform, _, _, fall)| judged_y(transform, fall) >= late_y);
+                if let Some((target, transform, column, _, fall)) = charged {
+                    released = Some(target);
+                    commands.entity(target).despawn_recursive();
+                    let distance = judged_y(transform, fall) - config.hit_y;
+                    match Judgment::from_distance(distance, hit_window) {
+                        Judgment::Miss => {
+                            miss_event_writer.send(TargetMissEvent(*column));
+                            score.miss();
+                        }
+                        judgment => {
+                            hit_event_writer.send(TargetHitEvent(*column, judgment));
+                            timing_event_writer.send(HitTimingEvent(
+                                *column,
+                                judgment,
+                                Judgment::timing_error_ms(distance, scroll_speed),
+                            ));
+                            score.hit(judgment, &scoring);
+                        }
+                    }
+                }
+            }
+        }
+
+        // A column that's still held down from before can't hit a target or let go of one
+        if pressed && !just_pressed {
+            continue;
+        }
+        // Whether this press was used to judge a target
+        let mut judged = false;
+        let column_targets = &column_targets[usize::from(key_column.index())];
+
+        // A press only ever judges the lowest target in its column, the one closest to being missed.
+        // Targets too far past the crosshair are left to be missed, so they don't take the press
+        let lowest_target = if just_pressed {
+            targets
+                .iter_many(column_targets)
+                .filter(|(target, transform, _, hold_note, fall)| {
+                    hold_note.is_none_or(|hold_note| hold_note.held.is_none())
+                        && Some(*target) != released
+                        && judged_y(transform, fall) >= late_y
+                })
+                .min_by(|(_, a, ..), (_, b, ..)| {
+                    config
+                        .flip(a.translation.y)
+                        .total_cmp(&config.flip(b.translation.y))
+                })
+                .map(|(target, ..)| target)
+        } else {
+            None
+        };
+
+        for &target in column_targets {
+            let (target, transform, column, hold_note, fall) = match targets.get_mut(target) {
+                Ok(components) => components,
+                Err(_) => continue,
+            };
+            let y = judged_y(transform, fall);
+
+            match hold_note {
+                // Letting go of a hold note early is a miss,
+                // unless the end of the tail is close enough to the crosshair
+                Some(hold_note) if hold_note.held.is_some() => {
+                    if !pressed {
+                        commands.entity(target).despawn_recursive();
+                        if y + hold_note.length(scroll_speed) - config.hit_y <= hit_window.good() {
+                            let judgment = hold_note.held.unwrap();
+                            hit_event_writer.send(TargetHitEvent(*column, judgment));
+                            score.hit(judgment, &scoring);
+                        } else {
+                            miss_event_writer.send(TargetMissEvent(*column));
+                            score.miss();
+                        }
+                    }
+                }
+                hold_note => {
+                    if !just_pressed
+                        || input_state.consumed.contains(&key_column)
+                        || Some(target) != lowest_target
+                        || y > threshold_y
+                        || y < late_y
+                    {
+                        continue;
+                    }
+                    input_state.consumed.insert(key_column);
+                    judged = true;
+
+                    // Normal notes wait until the press is let go to be judged
+                    if charge_scoring && hold_note.is_none() {
+                        input_state.charging.insert(key_column, target);
+                        continue;
+                    }
+
+                    match Judgment::from_distance(y - config.hit_y, hit_window) {
+                        Judgment::Miss => {
+                            commands.entity(target).despawn_recursive();
+                            miss_event_writer.send(TargetMissEvent(*column));
+                            score.miss();
+                        }
+                        judgment => {
+                            hit_event_writer.send(TargetHitEvent(*column, judgment));
+                            timing_event_writer.send(HitTimingEvent(
+                                *column,
+                                judgment,
+                                Judgment::timing_error_ms(y - config.hit_y, scroll_speed),
+                            ));
+                            score.hit(judgment, &scoring);
+                            // Hold notes stay around until their tail has been held through
+                            match hold_note {
+                                Some(mut hold_note) => hold_note.held = Some(judgment),
+                                None => commands.entity(target).despawn_recursive(),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Pressing a column with nothing to hit breaks the combo, if the player wants it to
+        if just_pressed && !judged && punish_ghost_taps.0 {
+            miss_event_writer.send(TargetMissEvent(key_column));
+            score.miss();
+        }
+    }
+}
+
+/// How long the run has been going, in milliseconds, for timing a replay's inputs.
+///
+/// Charted runs go by the chart's clock, which waits for the song to load and is kept in time
+/// with it, so inputs line up with the same notes however long the song took to load when the
+/// replay is watched. Random runs have no song, so they tick `clock` along with the frames instead
+fn replay_time_ms(
+    clock: &mut Stopwatch,
+    delta: Duration,
+    chart: &SelectedChart,
+    playback: &ChartPlayback,
+) -> Option<u128> {
+    if chart.0.is_some() {
+        playback
+            .started
+            .then(|| playback.clock.elapsed().as_millis())
+    } else {
+        clock.tick(delta);
+        Some(clock.elapsed().as_millis())
+    }
+}
+
+/// Adds any presses and releases this frame to the replay being recorded
+fn record_replay(
+    column_input: ColumnInput,
+    config: Res<GameConfig>,
+    time: Res<Time>,
+    chart: Res<SelectedChart>,
+    playback: Res<ChartPlayback>,
+    mut replay_recorder: ResMut<ReplayRecorder>,
+) {
+    let recorder = &mut *replay_recorder;
+    let time_ms = match replay_time_ms(&mut recorder.clock, time.delta(), &chart, &playback) {
+        Some(time_ms) => time_ms as u32,
+        None => return,
+    };
+
+    for column in config.columns() {
+        let just_pressed = column_input.just_pressed(column);
+        let pressed = column_input.pressed(column);
+        let was_held = recorder.held.contains(&column);
+
+        if just_pressed || (pressed && !was_held) {
+            recorder.replay.inputs.push(ReplayInput {
+                time_ms,
+                column,
+                pressed: true,
+            });
+        }
+        // A press that was let go within the same frame is still recorded as a tap
+        if !pressed && (was_held || just_pressed) {
+            recorder.replay.inputs.push(ReplayInput {
+                time_ms,
+                column,
+                pressed: false,
+            });
+        }
+
+        if pressed {
+            recorder.held.insert(column);
+        } else {
+            recorder.held.remove(&column);
+        }
+    }
+}
+
+/// Plays back every press and release in the replay that's due by now,
+/// for [`ColumnInput`] to report in place of the player's own
+fn advance_replay(
+    time: Res<Time>,
+    chart: Res<SelectedChart>,
+    playback: Res<ChartPlayback>,
+    mut replay_playback: ResMut<ReplayPlayback>,
+) {
+    let replay_playback = &mut *replay_playback;
+    replay_playback.just_pressed.clear();
+    let now_ms = match replay_time_ms(&mut replay_playback.clock, time.delta(), &chart, &playback) {
+        Some(now_ms) => now_ms,
+        None => return,
+    };
+
+    while let Some(input) = replay_playback
+        .replay
+        .inputs
+        .get(replay_playback.next_input)
+        .filter(|input| u128::from(input.time_ms) <= now_ms)
+    {
+        if input.pressed {
+            replay_playback.held.insert(input.column);
+            replay_playback.just_pressed.insert(input.column);
+        } else {
+            replay_playback.held.remove(&input.column);
+        }
+        replay_playback.next_input += 1;
+    }
+}
+
+/// Keeps the keys shown on the crosshairs up to date, in case they've been rebound mid-run
+fn update_key_labels(
+    key_bindings: Res<KeyBindings>,
+    mut label_query: Query<(&mut Text, &KeyLabel)>,
+) {
+    if key_bindings.is_changed() {
+        for (mut text, KeyLabel(column)) in label_query.iter_mut() {
+            text.sections[0].value = key_bindings.keys_label(*column);
+        }
+    }
+}
+
+/// Outlines the target that's next to be hit, the lowest one in any column that hasn't gone
+/// past the crosshairs. The outline moves on as soon as that target is hit or passes.
+/// This is only for show, targets are judged the same with or without it
+fn highlight_next_target(
+    mut commands: Commands,
+    targets: Query<(Entity, &Transform, &Column, Option<&HoldNote>), With<Target>>,
+    highlights: Query<(Entity, &Parent), With<NextTargetHighlight>>,
+    atlas_handles: Res<TextureAtlasHandles>,
+    config: Res<GameConfig>,
+    hit_window: Res<HitWindow>,
+) {
+    let late_y = hit_window.late_y(&config);
+    let next_target = targets
+        .iter()
+        .filter(|(_, transform, _, hold_note)| {
+            hold_note.is_none_or(|hold_note| hold_note.held.is_none())
+                && config.flip(transform.translation.y) >= late_y
+        })
+        .min_by(|(_, a, ..), (_, b, ..)| {
+            config
+                .flip(a.translation.y)
+                .total_cmp(&config.flip(b.translation.y))
+        });
+
+    let mut highlighted = false;
+    for (highlight, parent) in highlights.iter() {
+        if next_target.is_some_and(|(target, ..)| target == parent.get()) && !highlighted {
+            highlighted = true;
+        } else {
+            commands.entity(highlight).despawn_recursive();
+        }
+    }
+
+    let (atlas_handle, (target, _, column, _)) = match (&atlas_handles.crosshairs, next_target) {
+        (Some(atlas_handle), Some(next_target)) if !highlighted => (atlas_handle, next_target),
+        _ => return,
+    };
+    // The crosshair's ring, drawn a little bigger than the target and behind it
+    let highlight = commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                index: column.sprite_index(),
+                color: Color::WHITE,
+                custom_size: Some(Vec2::splat(280.0)),
+                ..Default::default()
+            },
+            texture_atlas: atlas_handle.clone(),
+            transform: Transform::from_xyz(0.0, 0.0, -0.02),
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(NextTargetHighlight)
+        .id();
+    attach_to_target(&mut commands, target, highlight);
+}
+
+/// Returns true if the player has turned training wheels on
+fn training_wheels_enabled(training_wheels: Res<TrainingWheels>) -> bool {
+    training_wheels.0
+}
+
+/// Gives every target a glow once it falls into the hit window. This is only for show,
+/// [`shoot_targets`] judges targets by their height the same as ever
+fn glow_targets_in_hit_zone(
+    mut commands: Commands,
+    mut targets: Query<(
+        Entity,
+        &mut Target,
+        &Transform,
+        &TextureAtlasSprite,
+        &Handle<TextureAtlas>,
+    )>,
+    config: Res<GameConfig>,
+    hit_window: Res<HitWindow>,
+) {
+    let threshold_y = hit_window.threshold_y(&config);
+    for (entity, mut target, transform, sprite, atlas_handle) in targets.iter_mut() {
+        if target.in_hit_zone || config.flip(transform.translation.y) > threshold_y {
+            continue;
+        }
+        target.in_hit_zone = true;
+
+        let glow = commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index: sprite.index,
+                    color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+                    custom_size: sprite.custom_size,
+                    ..Default::default()
+                },
+                texture_atlas: atlas_handle.clone(),
+                // Behind the target, but in front of a hold note's tail
+                transform: Transform::from_xyz(0.0, 0.0, -0.05),
+                ..Default::default()
+            })
+            .insert(Game)
+            .insert(HitZoneGlow(Timer::new(HIT_ZONE_PULSE_PERIOD, true)))
+            .id();
+        attach_to_target(&mut commands, entity, glow);
+    }
+}
+
+/// Makes an entity a child of a target, or despawns it if the target has been despawned.
+///
+/// A target can be hit or missed on the same frame something decides to add to it,
+/// and adding children to an entity that's gone would panic
+fn attach_to_target(commands: &mut Commands, target: Entity, child: Entity) {
+    commands.add(move |world: &mut World| {
+        if world.get_entity(target).is_some() {
+            world.entity_mut(target).push_children(&[child]);
+        } else {
+            world.despawn(child);
+        }
+    });
+}
+
+/// Ripples each glow out from its target, fading it as it grows.
+/// It's never brighter than the target, so the fading modifiers hide it too
+fn pulse_hit_zone_glows(
+    time: Res<Time>,
+    mut glows: Query<(
+        &mut HitZoneGlow,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+        &Parent,
+    )>,
+    targets: Query<&TextureAtlasSprite, (With<Target>, Without<HitZoneGlow>)>,
+) {
+    for (mut glow, mut transform, mut sprite, parent) in glows.iter_mut() {
+        let progress = glow.0.tick(time.delta()).percent();
+        let target_alpha = targets
+            .get(parent.get())
+            .map_or(1.0, |target| target.color.a());
+
+        transform.scale = Vec3::splat(1.0 + HIT_ZONE_PULSE_SCALE * progress);
+        sprite.color.set_a(0.6 * (1.0 - progress) * target_alpha);
+    }
+}
+
+/// Hits every target perfectly as it reaches its crosshair, in place of [`shoot_targets`].
+/// Hold notes are held until [`update_targets`] sees their tail through
+fn auto_play_targets(
+    mut commands: Commands,
+    mut targets: Query<(Entity, &Transform, &Column, Option<&mut HoldNote>), With<Target>>,
+    config: Res<GameConfig>,
+    scoring: Res<ScoringConfig>,
+    mut hit_event_writer: EventWriter<TargetHitEvent>,
+    mut score: ResMut<Scoreboard>,
+) {
+    for (target, transform, column, hold_note) in targets.iter_mut() {
+        if config.flip(transform.translation.y) > config.hit_y {
+            continue;
+        }
+
+        match hold_note {
+            Some(hold_note) if hold_note.held.is_some() => {}
+            Some(mut hold_note) => {
+                hold_note.held = Some(Judgment::Perfect);
+                hit_event_writer.send(TargetHitEvent(*column, Judgment::Perfect));
+                score.hit(Judgment::Perfect, &scoring);
+            }
+            None => {
+                commands.entity(target).despawn_recursive();
+                hit_event_writer.send(TargetHitEvent(*column, Judgment::Perfect));
+                score.hit(Judgment::Perfect, &scoring);
+            }
+        }
+    }
+}
+
+fn play_hit_sound(
+    mut hit_event_reader: EventReader<TargetHitEvent>,
+    audio: Res<Audio>,
+    volume: Res<Volume>,
+    hit_sounds: Res<HitSounds>,
+    hit_sound_voices: Res<HitSoundVoices>,
+    audio_handles: Res<NoteAudioHandles>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut hit_sound_sinks: ResMut<HitSoundSinks>,
+) {
+    for TargetHitEvent(column, _) in hit_event_reader.iter() {
+        if volume.muted {
+            continue;
+        }
+
+        // The setting is read on every hit, so turning hit sounds off takes effect straight away
+        let sound_column = match *hit_sounds {
+            HitSounds::PerColumn => *column,
+            HitSounds::Pitched => Column(0),
+            HitSounds::Off => continue,
+        };
+        let audio_handle = match audio_handles.get(sound_column) {
+            Some(audio_handle) => audio_handle,
+            None => continue,
+        };
+        let settings = volume.sfx_settings().with_speed(hit_sounds.speed(*column));
+        let sink = audio.play_with_settings(audio_handle.clone(), settings);
+
+        // Voices are counted by the column that was hit, so pitched chords aren't cut short
+        if let HitSoundVoices::Capped(cap) = *hit_sound_voices {
+            let sinks = hit_sound_sinks.0.entry(*column).or_default();
+            while sinks.len() >= cap.max(1) as usize {
+                if let Some(oldest) = sinks.pop_front().and_then(|sink| audio_sinks.get(&sink)) {
+                    oldest.stop();
+                }
+            }
+            sinks.push_back(audio_sinks.get_handle(sink));
+        }
+    }
+}
+
+/// Announces a combo being broken, if it was long enough to be worth mentioning.
+/// It goes by the streak, as the combo is capped well below what's worth announcing
+fn announce_combo_breaks(
+    score: Res<Scoreboard>,
+    mut previous_streak: Local<u32>,
+    mut announcement_writer: EventWriter<AnnouncementEvent>,
+) {
+    if score.streak == 0 && *previous_streak >= ANNOUNCED_COMBO_BREAK {
+        announcement_writer.send(AnnouncementEvent(Announcement::ComboBreak));
+    }
+    *previous_streak = score.streak;
+}
+
+/// Plays each announcement's sound, in every state so the end of a run is heard too
+fn play_announcements(
+    mut announcement_reader: EventReader<AnnouncementEvent>,
+    audio: Res<Audio>,
+    volume: Res<Volume>,
+    announcement_handles: Res<AnnouncementHandles>,
+) {
+    for AnnouncementEvent(announcement) in announcement_reader.iter() {
+        if volume.muted {
+            continue;
+        }
+        if let Some(handle) = announcement_handles.0.get(announcement) {
+            audio.play_with_settings(handle.clone(), volume.sfx_settings());
+        }
+    }
+}
+
+/// Returns true if the player has turned announcements on
+fn announcements_enabled(announcements: Res<Announcements>) -> bool {
+    announcements.0
+}
+
+/// Plays the miss sound when targets are missed, only once per frame however many were missed
+fn play_miss_sound(
+    mut miss_event_reader: EventReader<TargetMissEvent>,
+    audio: Res<Audio>,
+    volume: Res<Volume>,
+    sfx_handles: Res<SoundEffectHandles>,
+) {
+    let missed = miss_event_reader.iter().count() > 0;
+    if !missed || volume.muted {
+        return;
+    }
+
+    if let Some(miss) = &sfx_handles.miss {
+        audio.play_with_settings(miss.clone(), volume.sfx_settings());
+    }
+}
+
+/// Sends a [`BeatEvent`] on every beat, which comes from the chart's BPM and tempo changes,
+/// or the spawn interval if there is no chart or it doesn't have one
+fn track_beats(
+    chart: Res<SelectedChart>,
+    beatmaps: Res<Assets<Beatmap>>,
+    playback: Res<ChartPlayback>,
+    difficulty: Res<Difficulty>,
+    time: Res<Time>,
+    mut clock: ResMut<BeatClock>,
+    mut beat_event_writer: EventWriter<BeatEvent>,
+) {
+    let beatmap = chart.0.as_ref().and_then(|chart| beatmaps.get(chart));
+
+    // Charts keep time with their song, so follow the chart's clock instead of our own.
+    // The beats don't start until the song does, after the lead-in
+    let elapsed = if chart.0.is_some() {
+        if !playback.started || playback.song_time_ms() < 0 {
+            return;
+        }
+        Duration::from_millis(playback.song_time_ms() as u64)
+    } else {
+        clock.stopwatch.tick(time.delta());
+        clock.stopwatch.elapsed()
+    };
+
+    let beats = beatmap
+        .and_then(|beatmap| beatmap.beats_at(elapsed.as_secs_f32() * 1000.0))
+        .unwrap_or_else(|| elapsed.as_secs_f32() / difficulty.spawn_interval().as_secs_f32());
+
+    // Beat 1 is at the very start of the run
+    let beat = beats as u32 + 1;
+    if beat <= clock.beats {
+        return;
+    }
+    clock.beats = beat;
+    beat_event_writer.send(BeatEvent);
+}
+
+/// Plays a quiet tick on every beat
+fn play_metronome(
+    mut beat_event_reader: EventReader<BeatEvent>,
+    audio: Res<Audio>,
+    volume: Res<Volume>,
+    sfx_handles: Res<SoundEffectHandles>,
+) {
+    let beat = beat_event_reader.iter().count() > 0;
+    if !beat || volume.muted {
+        return;
+    }
+    if let Some(tick) = &sfx_handles.tick {
+        let settings = volume.sfx_settings();
+        let quieter = settings.volume * METRONOME_VOLUME;
+        audio.play_with_settings(tick.clone(), settings.with_volume(quieter));
+    }
+}
+
+/// Brightens the backdrop on every beat, then fades it back to black
+fn pulse_backdrop(
+    mut beat_event_reader: EventReader<BeatEvent>,
+    beat_pulse: Res<BeatPulse>,
+    time: Res<Time>,
+    mut backdrops: Query<(&mut Backdrop, &mut Sprite)>,
+) {
+    let beat = beat_event_reader.iter().count() > 0;
+
+    for (mut backdrop, mut sprite) in backdrops.iter_mut() {
+        if beat && beat_pulse.0 {
+            backdrop.0.reset();
+        }
+
+        let progress = backdrop.0.tick(time.delta()).percent();
+        sprite.color.set_a(PULSE_BRIGHTNESS * (1.0 - progress));
+    }
+}
+
+/// Counts every judged target towards the player's accuracy
+fn track_accuracy(
+    mut hit_event_reader: EventReader<TargetHitEvent>,
+    mut miss_event_reader: EventReader<TargetMissEvent>,
+    mut score: ResMut<Scoreboard>,
+) {
+    let hits = hit_event_reader.iter().count() as u32;
+    let misses = miss_event_reader.iter().count() as u32;
+
+    if hits + misses > 0 {
+        score.hits += hits;
+        score.total += hits + misses;
+    }
+}
+
+fn update_scoreboard(
+    score: Res<Scoreboard>,
+    mut score_text_query: Query<&mut Text, With<ScoreDisplay>>,
+) {
+    if score.is_changed() {
+        for mut score_text in score_text_query.iter_mut() {
+            score_text.sections[1].value = format_score(score.score);
+            score_text.sections[3].value = score.accuracy_label();
+        }
+    }
+}
+
+/// Shows the current combo, popping it when it increases and hiding it when it's broken
+fn update_combo_display(
+    score: Res<Scoreboard>,
+    mut last_combo: Local<i32>,
+    time: Res<Time>,
+    mut combo_text_query: Query<(&mut Text, &mut Visibility, &mut Transform), With<ComboDisplay>>,
+) {
+    for (mut combo_text, mut visibility, mut transform) in combo_text_query.iter_mut() {
+        if score.is_changed() {
+            combo_text.sections[0].value = format!("{}x Combo", score.combo);
+            visibility.is_visible = score.combo > 0;
+            if score.combo > *last_combo {
+                transform.scale = Vec3::splat(COMBO_POP_SCALE);
+            }
+        }
+
+        // Shrink back towards the resting size
+        transform.scale = Vec3::ONE.lerp(
+            transform.scale,
+            (-COMBO_POP_DECAY * time.delta_seconds()).exp(),
+        );
+    }
+
+    if score.is_changed() {
+        *last_combo = score.combo;
+    }
+}
+
+/// Shrinks the health bar to match the player's remaining health
+fn update_health_bar(health: Res<Health>, mut bar_query: Query<&mut Transform, With<HealthBar>>) {
+    if health.is_changed() {
+        for mut transform in bar_query.iter_mut() {
+            transform.scale.x = health.fraction();
+        }
+    }
+}
+
+/// How long a chart takes to play through, in milliseconds,
+/// from starting its clock until its last target has passed the bottom of the screen
+fn chart_length_ms(
+    beatmap: &Beatmap,
+    playback: &ChartPlayback,
+    config: &GameConfig,
+    scroll_speed: f32,
+    audio_offset: &AudioOffset,
+) -> f32 {
+    // Notes are timed for the crosshair, so this is the rest of the way down from there
+    let fall_off_ms = (config.hit_y - config.despawn_y) / scroll_speed * 1000.0;
+    playback.lead_in.as_millis() as f32
+        + beatmap.end_ms() as f32
+        + audio_offset.0 as f32
+        + fall_off_ms
+}
+
+/// Draws the density map under the progress bar once the chart has loaded
+fn spawn_density_map(
+    mut commands: Commands,
+    chart: Res<SelectedChart>,
+    beatmaps: Res<Assets<Beatmap>>,
+    bar_query: Query<(), With<DensityBar>>,
+) {
+    // It only needs drawing once per run
+    if !bar_query.is_empty() {
+        return;
+    }
+    let beatmap = match chart.0.as_ref().and_then(|chart| beatmaps.get(chart)) {
+        Some(beatmap) => beatmap,
+        None => return,
+    };
 
-        let logo = commands
-            .spawn_bundle(ImageBundle {
-                image: logo.clone().into(),
-                style: Style {
-                    size: Size::new(Val::Px(300.0), Val::Px(300.0)),
+    let top = PLAYFIELD_HEIGHT / 2.0 - 4.0;
+    let bar_width = PLAYFIELD_WIDTH / DENSITY_MAP_BUCKETS as f32;
+    for (i, density) in beatmap.density(DENSITY_MAP_BUCKETS).into_iter().enumerate() {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.8, 0.8, 0.8, 0.5),
+                    custom_size: Some(Vec2::new(bar_width, density * DENSITY_MAP_HEIGHT)),
+                    anchor: Anchor::TopLeft,
                     ..Default::default()
                 },
+                transform: Transform::from_xyz(
+                    -PLAYFIELD_WIDTH / 2.0 + i as f32 * bar_width,
+                    top,
+                    1.0,
+                ),
                 ..Default::default()
             })
-            .id();
+            .insert(Game)
+            .insert(DensityBar);
+    }
 
-        let start_button = commands
-            .spawn_bundle(ButtonBundle {
-                style: button_style.clone(),
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::WHITE,
+                custom_size: Some(Vec2::new(2.0, DENSITY_MAP_HEIGHT)),
+                anchor: Anchor::TopCenter,
                 ..Default::default()
-            })
-            .with_children(|btn| {
-                btn.spawn_bundle(TextBundle {
-                    text: Text::from_section("Start Game", button_textstyle.clone()),
-                    ..Default::default()
-                });
-            })
-            .insert(StartButton)
-            .insert(OldInteraction(Interaction::None))
-            .id();
+            },
+            transform: Transform::from_xyz(-PLAYFIELD_WIDTH / 2.0, top, 1.1),
+            ..Default::default()
+        })
+        .insert(Game)
+        .insert(DensityMarker);
+}
 
-        let exit_button = commands
-            .spawn_bundle(ButtonBundle {
-                style: button_style,
-                ..Default::default()
-            })
-            .with_children(|btn| {
-                btn.spawn_bundle(TextBundle {
-                    text: Text::from_section("Exit Game", button_textstyle.clone()),
-                    ..Default::default()
-                });
-            })
-            .insert(ExitButton)
-            .insert(OldInteraction(Interaction::None))
-            .id();
+/// Moves the density map's marker along with the song
+fn update_density_marker(
+    chart: Res<SelectedChart>,
+    beatmaps: Res<Assets<Beatmap>>,
+    playback: Res<ChartPlayback>,
+    mut marker_query: Query<&mut Transform, With<DensityMarker>>,
+) {
+    let beatmap = match chart.0.as_ref().and_then(|chart| beatmaps.get(chart)) {
+        Some(beatmap) => beatmap,
+        None => return,
+    };
 
-        commands
-            .entity(menu)
-            .push_children(&[logo, start_button, exit_button]);
+    let progress =
+        (playback.song_time_ms() as f32 / beatmap.end_ms().max(1) as f32).clamp(0.0, 1.0);
+    for mut transform in marker_query.iter_mut() {
+        transform.translation.x = -PLAYFIELD_WIDTH / 2.0 + progress * PLAYFIELD_WIDTH;
     }
 }
 
-/// Returns true if any buttons with the given component are being pressed
-fn button_interact<B: Component>(
-    mut interactions: Query<
-        (&Interaction, &mut OldInteraction),
-        (Changed<Interaction>, With<Button>, With<B>),
-    >,
-) -> bool {
-    for (new_interaction, mut old_interaction) in &mut interactions {
-        if *new_interaction == Interaction::Hovered && old_interaction.0 == Interaction::Clicked {
-            return true;
-        }
-        old_interaction.0 = *new_interaction;
+/// Fills the progress bar as the chart plays
+fn update_progress_bar(
+    chart: Res<SelectedChart>,
+    beatmaps: Res<Assets<Beatmap>>,
+    playback: Res<ChartPlayback>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+    scroll_speed: Res<ScrollSpeed>,
+    audio_offset: Res<AudioOffset>,
+    mut bar_query: Query<&mut Transform, With<ProgressBar>>,
+) {
+    let beatmap = match chart.0.as_ref().and_then(|chart| beatmaps.get(chart)) {
+        Some(beatmap) => beatmap,
+        None => return,
+    };
+
+    let length_ms = chart_length_ms(
+        beatmap,
+        &playback,
+        &config,
+        scroll_speed.apply(*difficulty, &config),
+        &audio_offset,
+    );
+    let progress = (playback.clock.elapsed().as_millis() as f32 / length_ms).clamp(0.0, 1.0);
+    for mut transform in bar_query.iter_mut() {
+        transform.scale.x = progress;
     }
-    false
 }
 
-/// Sets the colour of every button based on player interaction
-fn button_visual_interact(
-    mut query: Query<(&Interaction, &mut UiColor), (Changed<Interaction>, With<Button>)>,
+/// Ends the run once every note in the chart has been spawned and dealt with
+fn finish_chart(
+    mut commands: Commands,
+    chart: Res<SelectedChart>,
+    beatmaps: Res<Assets<Beatmap>>,
+    playback: Res<ChartPlayback>,
+    config: Res<GameConfig>,
+    difficulty: Res<Difficulty>,
+    scroll_speed: Res<ScrollSpeed>,
+    audio_offset: Res<AudioOffset>,
+    health: Res<Health>,
+    modifiers: Res<Modifiers>,
+    targets: Query<(), With<Target>>,
 ) {
-    for (interaction, mut colour) in &mut query {
-        match interaction {
-            Interaction::Clicked => {
-                *colour = UiColor(Color::rgb(0.75, 0.75, 0.75));
-            }
-            Interaction::Hovered => {
-                *colour = UiColor(Color::rgb(0.8, 0.8, 0.8));
-            }
-            Interaction::None => {
-                *colour = UiColor(Color::rgb(1.0, 1.0, 1.0));
-            }
-        }
+    // Running out of health on the last note is still a fail, which game_over_on_death handles
+    if health.current <= 0 && !modifiers.no_fail {
+        return;
+    }
+
+    let beatmap = match chart.0.as_ref().and_then(|chart| beatmaps.get(chart)) {
+        Some(beatmap) => beatmap,
+        None => return,
+    };
+
+    // Targets spawned this frame won't show up in the query yet,
+    // so wait until the last one would have had time to fall off the screen as well
+    let length_ms = chart_length_ms(
+        beatmap,
+        &playback,
+        &config,
+        scroll_speed.apply(*difficulty, &config),
+        &audio_offset,
+    );
+    if playback.next_note >= beatmap.notes.len()
+        && playback.clock.elapsed().as_millis() as f32 >= length_ms
+        && targets.is_empty()
+    {
+        commands.insert_resource(GameResult::Cleared);
+        commands.insert_resource(NextState(GameState::GameOverMenu));
     }
 }
 
-/// Starts the game
-fn on_start_button(mut commands: Commands) {
-    commands.insert_resource(NextState(GameState::Playing));
+/// Ends the run once the player has run out of health, unless they're playing with no fail
+fn game_over_on_death(mut commands: Commands, health: Res<Health>, modifiers: Res<Modifiers>) {
+    if health.current <= 0 && !modifiers.no_fail {
+        commands.insert_resource(GameResult::Failed);
+        commands.insert_resource(NextState(GameState::GameOverMenu));
+    }
 }
 
-/// Exits the game
-fn on_exit_button(mut exit_writer: EventWriter<AppExit>) {
-    exit_writer.send(AppExit);
+/// Pauses the chart's song along with the game
+fn pause_song(playback: Res<ChartPlayback>, audio_sinks: Res<Assets<AudioSink>>) {
+    if let Some(sink) = playback.song_sink(&audio_sinks) {
+        sink.pause();
+    }
 }
 
-/// Sets up the game
-fn setup_game(
+/// Resumes the chart's song when the game is unpaused
+fn resume_song(playback: Res<ChartPlayback>, audio_sinks: Res<Assets<AudioSink>>) {
+    // A song that fell behind while muted is started again once it's unmuted
+    if playback.song_behind {
+        return;
+    }
+    if let Some(sink) = playback.song_sink(&audio_sinks) {
+        sink.play();
+    }
+}
+
+/// Stops the chart's song when the run ends
+fn stop_song(playback: Res<ChartPlayback>, audio_sinks: Res<Assets<AudioSink>>) {
+    if let Some(sink) = playback.song_sink(&audio_sinks) {
+        sink.stop();
+    }
+}
+
+/// Mixes two colours, where `t` is 0 for `from` and 1 for `to`
+fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    Vec4::from(from).lerp(Vec4::from(to), t).into()
+}
+
+/// Flashes a column's crosshair when one of its targets is hit or missed,
+/// and faintly whenever its column is pressed, so the player can see their input registered
+fn flash_crosshairs(
+    mut hit_event_reader: EventReader<TargetHitEvent>,
+    mut miss_event_reader: EventReader<TargetMissEvent>,
+    input: ColumnInput,
+    mut crosshairs: Query<(&Column, &mut FlashTimer), With<Crosshair>>,
+) {
+    // Presses are flashed first, so a hit or miss on the same frame shows over them
+    for (column, mut flash_timer) in crosshairs.iter_mut() {
+        if input.just_pressed(*column) {
+            flash_timer.flash(PRESS_FLASH);
+        }
+    }
+
+    let flashes = hit_event_reader
+        .iter()
+        .map(|TargetHitEvent(column, _)| (*column, HIT_FLASH))
+        .chain(
+            miss_event_reader
+                .iter()
+                .map(|TargetMissEvent(column)| (*column, MISS_FLASH)),
+        );
+
+    for (flash_column, flash) in flashes {
+        for (column, mut flash_timer) in crosshairs.iter_mut() {
+            if *column == flash_column {
+                flash_timer.flash(flash);
+            }
+        }
+    }
+}
+
+/// Bursts particles out of a crosshair when its target is hit, more for better hits
+fn spawn_hit_particles(
     mut commands: Commands,
-    atlas_handles: Res<TextureAtlasHandles>,
-    asset_server: Res<AssetServer>,
+    mut hit_event_reader: EventReader<TargetHitEvent>,
+    config: Res<GameConfig>,
+    theme: Res<Theme>,
 ) {
-    let atlas_handle = atlas_handles.crosshairs.as_ref().unwrap();
+    // Like the camera shake, particles don't touch the GameRng so they can't change the spawns
+    let mut rng = rand::thread_rng();
+    let mut budget = MAX_PARTICLES_PER_FRAME;
+
+    for TargetHitEvent(column, judgment) in hit_event_reader.iter() {
+        let count = judgment.particle_count().min(budget);
+        budget -= count;
+
+        for _ in 0..count {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let speed = rng.gen_range(60.0..180.0);
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: theme.color(*column),
+                        custom_size: Some(Vec2::splat(6.0)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(
+                        config.column_x(*column),
+                        config.flip(config.hit_y),
+                        2.0,
+                    ),
+                    ..Default::default()
+                })
+                .insert(Game)
+                .insert(Particle {
+                    lifetime: Timer::new(PARTICLE_LIFETIME, false),
+                    velocity: Vec3::new(angle.cos(), angle.sin(), 0.0) * speed,
+                });
+        }
+    }
+}
 
-    for column in [Column::Yellow, Column::Red, Column::Blue, Column::Green] {
+/// Spawns a tick beside the crosshair for every hit, above it if the press was early
+/// and below it if it was late, further away the further off the press was
+fn spawn_timing_markers(
+    mut commands: Commands,
+    mut timing_event_reader: EventReader<HitTimingEvent>,
+    config: Res<GameConfig>,
+    hit_window: Res<HitWindow>,
+) {
+    // Keep wildly early or late hits from drawing their marker far away from the crosshair
+    let max_offset = hit_window.good();
+    for HitTimingEvent(column, _, error_ms) in timing_event_reader.iter() {
+        let offset = (-error_ms * TIMING_MARKER_SCALE).clamp(-max_offset, max_offset);
         commands
-            .spawn_bundle(SpriteSheetBundle {
-                transform: Transform::from_xyz(
-                    f32::from(column.index()) * 90.0 - 135.0,
-                    -305.0,
-                    0.0,
-                )
-                .with_scale(Vec3::splat(0.3)),
-                sprite: TextureAtlasSprite {
-                    index: column.index() as usize,
-                    custom_size: Some(Vec2::splat(200.0)),
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::WHITE,
+                    custom_size: Some(Vec2::new(30.0, 3.0)),
                     ..Default::default()
                 },
-                texture_atlas: atlas_handle.clone(),
+                transform: Transform::from_xyz(
+                    config.column_x(*column) + 40.0,
+                    config.flip(config.hit_y + offset),
+                    3.0,
+                ),
                 ..Default::default()
             })
             .insert(Game)
-            .insert(column);
+            .insert(TimingMarker(Timer::new(TIMING_MARKER_LIFETIME, false)));
     }
+}
 
-    let score_textstyle = TextStyle {
-        font: asset_server.load("fonts/comic.ttf"),
-        font_size: 36.0,
-        color: Color::WHITE,
-    };
+/// Fades timing markers, despawning them once their lifetime is over
+fn update_timing_markers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut markers: Query<(Entity, &mut TimingMarker, &mut Sprite)>,
+) {
+    for (entity, mut marker, mut sprite) in markers.iter_mut() {
+        if marker.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        sprite.color.set_a(1.0 - marker.0.percent());
+    }
+}
 
-    commands
-        .spawn_bundle(Text2dBundle {
-            text: Text::from_sections([
-                TextSection {
-                    value: "Score: ".into(),
-                    style: score_textstyle.clone(),
-                },
-                TextSection {
-                    value: "0".into(),
-                    style: score_textstyle,
-                },
-            ]),
-            transform: Transform::from_xyz(-200.0, 300.0, 0.0),
-            ..Default::default()
-        })
-        .insert(Game)
-        .insert(ScoreDisplay);
+/// Returns true if the player has turned timing markers on
+fn timing_markers_enabled(timing_markers: Res<TimingMarkers>) -> bool {
+    timing_markers.0
 }
 
-/// Exit to the start menu if the player pressed escape
-fn menu_on_esc(mut commands: Commands, input: Res<Input<KeyCode>>) {
-    if input.just_pressed(KeyCode::Escape) {
-        commands.insert_resource(NextState(GameState::StartMenu));
+/// Returns true if the player has turned combo ducking on
+fn combo_ducking_enabled(combo_ducking: Res<ComboDucking>) -> bool {
+    combo_ducking.0
+}
+
+/// Gradually turns a chart's song down as the combo climbs, and back up as soon as it breaks.
+///
+/// The song is only ever turned down from the music volume, so it never plays louder than the
+/// player asked for, and muting still silences it
+fn duck_music_on_combo(
+    score: Res<Scoreboard>,
+    volume: Res<Volume>,
+    playback: Res<ChartPlayback>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    time: Res<Time>,
+    mut duck: Local<f32>,
+) {
+    if score.streak == 0 {
+        // A miss restores the song straight away, so the break is heard
+        *duck = 0.0;
+    } else {
+        let target = COMBO_DUCK_DEPTH * score.streak.min(COMBO_DUCK_FULL_COMBO) as f32
+            / COMBO_DUCK_FULL_COMBO as f32;
+        let step = COMBO_DUCK_RATE * time.delta_seconds();
+        *duck = if *duck < target {
+            (*duck + step).min(target)
+        } else {
+            (*duck - step).max(target)
+        };
+    }
+
+    if let Some(sink) = playback.song_sink(&audio_sinks) {
+        sink.set_volume(volume.music_volume() * (1.0 - *duck));
     }
 }
 
-fn spawn_targets(mut commands: Commands, atlas_handles: Res<TextureAtlasHandles>) {
-    let mut rng = rand::thread_rng();
-    let column = rng.gen::<Column>();
+/// Moves and fades particles, despawning them once their lifetime is over
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+    for (entity, mut particle, mut transform, mut sprite) in particles.iter_mut() {
+        if particle.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        transform.translation += particle.velocity * time.delta_seconds();
+        sprite.color.set_a(1.0 - particle.lifetime.percent());
+    }
+}
 
-    let atlas_handle = atlas_handles.targets.as_ref().unwrap();
+/// Starts the camera shaking when a target is missed, unless the player has turned it off
+fn shake_on_miss(
+    mut miss_event_reader: EventReader<TargetMissEvent>,
+    screen_shake: Res<ScreenShake>,
+    mut camera_query: Query<&mut CameraShake>,
+) {
+    if miss_event_reader.iter().count() == 0 || !screen_shake.0 {
+        return;
+    }
 
-    commands
-        .spawn_bundle(SpriteSheetBundle {
-            transform: Transform::from_xyz(f32::from(column.index()) * 90.0 - 135.0, 400.0, 0.0)
-                .with_scale(Vec3::splat(0.3)),
-            sprite: TextureAtlasSprite {
-                index: column.index() as usize,
-                custom_size: Some(Vec2::splat(200.0)),
-                ..Default::default()
-            },
-            texture_atlas: atlas_handle.clone(),
-            ..Default::default()
-        })
-        .insert(Game)
-        .insert(Target)
-        .insert(column);
+    for mut shake in camera_query.iter_mut() {
+        shake.timer.reset();
+    }
 }
 
-fn update_targets(
-    mut commands: Commands,
-    mut targets: Query<(Entity, &mut Transform, &Column), With<Target>>,
+/// Jitters the camera around the centre, less and less until the shake is over.
+///
+/// This uses its own randomness rather than the [`GameRng`], so it can't change which targets spawn
+fn update_camera_shake(
     time: Res<Time>,
-    mut miss_event_writer: EventWriter<TargetMissEvent>,
-    mut score: ResMut<Scoreboard>,
+    mut camera_query: Query<(&mut CameraShake, &mut Transform)>,
 ) {
-    for (target, mut transform, column) in targets.iter_mut() {
-        if transform.translation.y < -350.0 {
-            commands.entity(target).despawn();
-            miss_event_writer.send(TargetMissEvent(*column));
-            score.miss();
+    let mut rng = rand::thread_rng();
+
+    for (mut shake, mut transform) in camera_query.iter_mut() {
+        if shake.timer.finished() {
+            continue;
+        }
+
+        if shake.timer.tick(time.delta()).finished() {
+            transform.translation.x = 0.0;
+            transform.translation.y = 0.0;
         } else {
-            transform.translation.y -= 150.0 * time.delta_seconds();
+            let strength = shake.intensity * (1.0 - shake.timer.percent());
+            transform.translation.x = rng.gen_range(-strength..=strength);
+            transform.translation.y = rng.gen_range(-strength..=strength);
         }
     }
 }
 
-fn shoot_targets(
-    mut commands: Commands,
-    targets: Query<(Entity, &Transform, &Column), With<Target>>,
-    input: Res<Input<KeyCode>>,
-    mut hit_event_writer: EventWriter<TargetHitEvent>,
-    mut score: ResMut<Scoreboard>,
+/// Fades flashing sprites back to their normal colour and size
+fn update_flashes(
+    time: Res<Time>,
+    mut flashing: Query<(&mut FlashTimer, &mut TextureAtlasSprite, &mut Transform)>,
 ) {
-    if input.any_just_pressed([KeyCode::A, KeyCode::H]) {
-        targets
-            .iter()
-            .filter(|(_, transform, column)| {
-                *column == &Column::Yellow && transform.translation.y <= -280.0
-            })
-            .for_each(|(target, _, column)| {
-                commands.entity(target).despawn();
-                hit_event_writer.send(TargetHitEvent(*column));
-                score.hit();
-            });
-    }
+    for (mut flash_timer, mut sprite, mut transform) in flashing.iter_mut() {
+        if flash_timer.timer.finished() {
+            continue;
+        }
 
-    if input.any_just_pressed([KeyCode::S, KeyCode::J]) {
-        targets
-            .iter()
-            .filter(|(_, transform, column)| {
-                *column == &Column::Red && transform.translation.y <= -280.0
-            })
-            .for_each(|(target, _, column)| {
-                commands.entity(target).despawn();
-                hit_event_writer.send(TargetHitEvent(*column));
-                score.hit();
-            });
+        let progress = flash_timer.timer.tick(time.delta()).percent();
+        sprite.color = lerp_color(flash_timer.color, flash_timer.resting_color, progress);
+        transform.scale = Vec3::splat(
+            flash_timer.resting_scale * (flash_timer.scale + (1.0 - flash_timer.scale) * progress),
+        );
     }
+}
 
-    if input.any_just_pressed([KeyCode::D, KeyCode::K]) {
-        targets
-            .iter()
-            .filter(|(_, transform, column)| {
-                *column == &Column::Blue && transform.translation.y <= -280.0
+/// Spawns a judgment label above a crosshair whenever its target is hit or missed
+fn spawn_judgment_text(
+    mut commands: Commands,
+    mut hit_event_reader: EventReader<TargetHitEvent>,
+    mut miss_event_reader: EventReader<TargetMissEvent>,
+    asset_handles: Res<MenuAssetHandles>,
+    config: Res<GameConfig>,
+) {
+    let judgments = hit_event_reader
+        .iter()
+        .map(|TargetHitEvent(column, judgment)| (*column, *judgment))
+        .chain(
+            miss_event_reader
+                .iter()
+                .map(|TargetMissEvent(column)| (*column, Judgment::Miss)),
+        );
+
+    let font = match &asset_handles.font {
+        Some(font) => font,
+        None => return,
+    };
+
+    for (column, judgment) in judgments {
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    judgment.label(),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 24.0,
+                        color: judgment.color(),
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                // Just above the crosshair
+                transform: Transform::from_xyz(
+                    config.column_x(column),
+                    config.flip(config.hit_y + 55.0),
+                    3.0,
+                ),
+                ..Default::default()
             })
-            .for_each(|(target, _, column)| {
-                commands.entity(target).despawn();
-                hit_event_writer.send(TargetHitEvent(*column));
-                score.hit();
-            });
+            .insert(Game)
+            // Float away from the crosshair, towards the middle of the screen
+            .insert(FloatingText::new(
+                Vec3::Y * config.flip(FLOATING_TEXT_SPEED),
+            ));
     }
+}
 
-    if input.any_just_pressed([KeyCode::F, KeyCode::L]) {
-        targets
-            .iter()
-            .filter(|(_, transform, column)| {
-                *column == &Column::Green && transform.translation.y <= -280.0
+/// The colour of the label for hits that were early
+const FAST_COLOR: Color = Color::rgb(0.4, 0.7, 1.0);
+
+/// The colour of the label for hits that were late
+const SLOW_COLOR: Color = Color::rgb(1.0, 0.55, 0.3);
+
+/// Labels every hit that wasn't perfect as fast or slow, just above its judgment,
+/// so the player can tell which way their timing is off
+fn spawn_fast_slow_text(
+    mut commands: Commands,
+    mut timing_event_reader: EventReader<HitTimingEvent>,
+    config: Res<GameConfig>,
+    asset_handles: Res<MenuAssetHandles>,
+) {
+    let font = match &asset_handles.font {
+        Some(font) => font,
+        None => return,
+    };
+
+    for HitTimingEvent(column, judgment, error_ms) in timing_event_reader.iter() {
+        // Perfect hits are close enough already, and labelling them would only add clutter
+        if *judgment == Judgment::Perfect {
+            continue;
+        }
+        let (label, color) = if *error_ms < 0.0 {
+            ("FAST", FAST_COLOR)
+        } else {
+            ("SLOW", SLOW_COLOR)
+        };
+
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    label,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 16.0,
+                        color,
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                transform: Transform::from_xyz(
+                    config.column_x(*column),
+                    config.flip(config.hit_y + 78.0),
+                    3.0,
+                ),
+                ..Default::default()
             })
-            .for_each(|(target, _, column)| {
-                commands.entity(target).despawn();
-                hit_event_writer.send(TargetHitEvent(*column));
-                score.hit();
-            });
+            .insert(Game)
+            .insert(FloatingText::new(
+                Vec3::Y * config.flip(FLOATING_TEXT_SPEED),
+            ));
     }
+}
 
-    //FIXME: Holy code duplication, Batman!
+/// Returns true if the player has turned fast/slow labels on
+fn fast_slow_enabled(fast_slow: Res<FastSlow>) -> bool {
+    fast_slow.0
 }
 
-fn play_hit_sound(
-    mut hit_event_reader: EventReader<TargetHitEvent>,
+/// The colour of the banner shown when a combo milestone is reached
+const MILESTONE_COLOR: Color = Color::rgb(1.0, 0.85, 0.3);
+
+/// Shows a banner and plays every column's sound at once whenever the streak reaches a milestone
+fn celebrate_combo_milestones(
+    mut commands: Commands,
+    score: Res<Scoreboard>,
+    mut previous_streak: Local<u32>,
+    config: Res<GameConfig>,
+    asset_handles: Res<MenuAssetHandles>,
     audio: Res<Audio>,
+    volume: Res<Volume>,
     audio_handles: Res<NoteAudioHandles>,
+    mut announcement_writer: EventWriter<AnnouncementEvent>,
 ) {
-    for TargetHitEvent(column) in hit_event_reader.iter() {
-        if let Some(audio_handle) = match column {
-            Column::Yellow => &audio_handles.yellow,
-            Column::Red => &audio_handles.red,
-            Column::Blue => &audio_handles.blue,
-            Column::Green => &audio_handles.green,
-        } {
-            audio.play(audio_handle.clone());
-        };
+    let milestone = combo_milestone(*previous_streak, score.streak, config.combo_milestone);
+    *previous_streak = score.streak;
+    let milestone = match milestone {
+        Some(milestone) => milestone,
+        None => return,
+    };
+    announcement_writer.send(AnnouncementEvent(Announcement::Milestone));
+
+    if !volume.muted {
+        for audio_handle in &audio_handles.0 {
+            audio.play_with_settings(audio_handle.clone(), volume.sfx_settings());
+        }
+    }
+
+    if let Some(font) = &asset_handles.font {
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    format!("{} Combo!", milestone),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 48.0,
+                        color: MILESTONE_COLOR,
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                // In the middle of the playfield, over the targets
+                transform: Transform::from_xyz(0.0, 0.0, 3.0),
+                ..Default::default()
+            })
+            .insert(Game)
+            .insert(FloatingText::new(Vec3::Y * FLOATING_TEXT_SPEED));
     }
 }
 
-fn update_scoreboard(
-    score: Res<Scoreboard>,
-    mut score_text_query: Query<&mut Text, With<ScoreDisplay>>,
+/// Moves and fades floating text, despawning it once its lifetime is over
+fn update_floating_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut floating: Query<(Entity, &mut FloatingText, &mut Transform, &mut Text)>,
 ) {
-    if score.is_changed() {
-        for mut score_text in score_text_query.iter_mut() {
-            score_text.sections[1].value = score.score.to_string();
+    for (entity, mut floating_text, mut transform, mut text) in floating.iter_mut() {
+        if floating_text.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        transform.translation += floating_text.velocity * time.delta_seconds();
+        let alpha = 1.0 - floating_text.lifetime.percent();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
         }
     }
 }
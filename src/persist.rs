@@ -0,0 +1,150 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Whether the data directory is left out of paths in the log, for streamer mode
+static HIDE_PATHS: AtomicBool = AtomicBool::new(false);
+
+/// The directory the game's settings and save data are kept in
+fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("rhythm_game"))
+}
+
+/// Sets whether the data directory is left out of paths in the log.
+/// It's usually in the player's home directory, which is often named after them
+pub fn set_hide_paths(hide: bool) {
+    HIDE_PATHS.store(hide, Ordering::Relaxed);
+}
+
+/// A path in the data directory, as it should be shown in the log
+fn shown_path(path: &Path) -> String {
+    match path.file_name() {
+        Some(file_name) if HIDE_PATHS.load(Ordering::Relaxed) => {
+            format!("<data dir>/{}", file_name.to_string_lossy())
+        }
+        _ => path.display().to_string(),
+    }
+}
+
+/// Reads a value from a RON file in the data directory,
+/// falling back to the default if it is missing or can't be parsed
+pub fn load<T: DeserializeOwned + Default>(file_name: &str) -> T {
+    try_load(file_name).unwrap_or_default()
+}
+
+/// Reads a value from a RON file in the data directory, if it's there and can be parsed
+pub fn try_load<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    try_load_migrating(file_name, str::to_string)
+}
+
+/// Reads a value from a RON file in the data directory like [`load`]. If it can't be parsed,
+/// it's parsed again after `migrate` has brought it up to date from an older format
+pub fn load_migrating<T: DeserializeOwned + Default>(
+    file_name: &str,
+    migrate: fn(&str) -> String,
+) -> T {
+    try_load_migrating(file_name, migrate).unwrap_or_default()
+}
+
+fn try_load_migrating<T: DeserializeOwned>(
+    file_name: &str,
+    migrate: fn(&str) -> String,
+) -> Option<T> {
+    let path = data_dir()?.join(file_name);
+
+    // A missing file just means nothing has been saved yet
+    let contents = fs::read_to_string(&path).ok()?;
+
+    ron::from_str(&contents)
+        .or_else(|err| ron::from_str(&migrate(&contents)).map_err(|_| err))
+        .map_err(|err| warn!("Couldn't parse {}: {}", shown_path(&path), err))
+        .ok()
+}
+
+/// The names of the files in the data directory that start with `prefix`, sorted by name
+pub fn list(prefix: &str) -> Vec<String> {
+    let entries = match data_dir().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        // Nothing has been saved yet
+        _ => return Vec::new(),
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Writes a value to a RON file in the data directory, creating the directory if needed
+pub fn save<T: Serialize>(file_name: &str, value: &T) {
+    let dir = match data_dir() {
+        Some(dir) => dir,
+        None => {
+            warn!("Couldn't find a data directory to save {} in", file_name);
+            return;
+        }
+    };
+
+    let result = fs::create_dir_all(&dir)
+        .map_err(|err| err.to_string())
+        .and_then(|_| {
+            ron::ser::to_string_pretty(value, Default::default()).map_err(|err| err.to_string())
+        })
+        .and_then(|contents| {
+            fs::write(dir.join(file_name), contents).map_err(|err| err.to_string())
+        });
+
+    if let Err(err) = result {
+        warn!("Couldn't save {}: {}", file_name, err);
+    }
+}
+
+/// Deletes a file from the data directory, if it's there
+pub fn remove(file_name: &str) {
+    let path = match data_dir() {
+        Some(dir) => dir.join(file_name),
+        None => return,
+    };
+
+    match fs::remove_file(&path) {
+        // Nothing to delete is just as good
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            warn!("Couldn't delete {}: {}", shown_path(&path), err)
+        }
+        _ => {}
+    }
+}
+
+/// Appends a row to a CSV file in the data directory,
+/// creating the file with the given header row first if it doesn't exist yet
+pub fn append_csv_row(file_name: &str, header: &str, row: &str) {
+    let dir = match data_dir() {
+        Some(dir) => dir,
+        None => {
+            warn!("Couldn't find a data directory to save {} in", file_name);
+            return;
+        }
+    };
+
+    let path = dir.join(file_name);
+    let result = fs::create_dir_all(&dir).and_then(|_| {
+        let is_new = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if is_new {
+            writeln!(file, "{}", header)?;
+        }
+        writeln!(file, "{}", row)
+    });
+
+    if let Err(err) = result {
+        warn!("Couldn't save {}: {}", file_name, err);
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ReplaySettings;
+
+/// What every run code starts with, so anything else on the clipboard can be told apart.
+/// The number goes up whenever the encoding changes, so old codes are turned away
+const PREFIX: &str = "RG1-";
+
+/// Everything needed to set up a run again, for a friend to try to beat its score.
+///
+/// It's encoded as [`PREFIX`] followed by the RON of this struct in URL-safe base64,
+/// which keeps it to one line that survives being pasted into a chat
+#[derive(Serialize, Deserialize)]
+pub struct RunCode {
+    /// The asset path of the chart that was played, or `None` for a random run
+    pub chart: Option<String>,
+    /// The seed random targets were spawned with
+    pub seed: u64,
+    pub settings: ReplaySettings,
+    /// The score to beat
+    pub score: i32,
+}
+
+impl RunCode {
+    /// The code to share, e.g. "RG1-KGNoYXJ0OiBTb21l..."
+    pub fn encode(&self) -> String {
+        // Every field is plain data, so this can't fail
+        let ron = ron::to_string(self).unwrap_or_default();
+        format!(
+            "{}{}",
+            PREFIX,
+            base64::encode_config(ron, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    /// Reads a code back, ignoring any whitespace it was pasted with.
+    /// Its settings are kept within what the settings menus allow, as anyone could have made it
+    pub fn decode(code: &str) -> Result<Self, String> {
+        let encoded = code
+            .trim()
+            .strip_prefix(PREFIX)
+            .ok_or("it isn't a run code")?;
+        let bytes = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)
+            .map_err(|err| format!("it's been cut short or changed ({})", err))?;
+        let ron = String::from_utf8(bytes).map_err(|err| err.to_string())?;
+        let run_code: Self = ron::from_str(&ron).map_err(|err| err.to_string())?;
+        Ok(Self {
+            settings: run_code.settings.clamped(),
+            ..run_code
+        })
+    }
+}
+
+/// Puts text on the system clipboard
+pub fn copy(text: String) -> Result<(), String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|err| err.to_string())
+}
+
+/// Reads text from the system clipboard
+pub fn paste() -> Result<String, String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|err| err.to_string())
+}
@@ -0,0 +1,41 @@
+use super::*;
+
+#[test]
+fn rebinding_a_column_only_replaces_that_key() {
+    let mut key_bindings = KeyBindings::default();
+
+    key_bindings.bind(Binding::Column(Column(0), 1), KeyCode::Q);
+
+    assert_eq!(key_bindings.keys(Column(0)), [KeyCode::A, KeyCode::Q]);
+    assert_eq!(
+        key_bindings.binding_for(KeyCode::Q),
+        Some(Binding::Column(Column(0), 1))
+    );
+    assert_eq!(key_bindings.binding_for(KeyCode::H), None);
+}
+
+#[test]
+fn binding_a_new_place_adds_a_key() {
+    let mut key_bindings = KeyBindings::default();
+    key_bindings.columns.insert(Column(1), vec![KeyCode::S]);
+
+    key_bindings.bind(Binding::Column(Column(1), 1), KeyCode::W);
+
+    assert_eq!(key_bindings.keys(Column(1)), [KeyCode::S, KeyCode::W]);
+}
+
+#[test]
+fn rebinding_a_gamepad_button_only_replaces_that_button() {
+    let mut key_bindings = KeyBindings::default();
+
+    key_bindings.bind_button(Column(2), 0, GamepadButtonType::RightTrigger);
+
+    assert_eq!(
+        key_bindings.buttons(Column(2)),
+        [GamepadButtonType::RightTrigger, GamepadButtonType::DPadUp]
+    );
+    assert_eq!(
+        key_bindings.button_binding_for(GamepadButtonType::DPadUp),
+        Some(Binding::Column(Column(2), 1))
+    );
+}
@@ -0,0 +1,65 @@
+use super::*;
+
+/// Charges a target that starts just inside the hit window, letting go after `held` frames.
+/// The app is left running long enough for the target to be missed if it wasn't judged
+fn charge_for(held: u32) -> TestApp {
+    let mut app = TestApp::new()
+        .with_system(shoot_targets)
+        .with_system(update_targets);
+    app.app.insert_resource(ChargeScoring(true));
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    app.spawn_target(Column(2), 0, hit_y + 20.0);
+    let key = app.key(Column(2));
+
+    app.press(key);
+    app.step(FRAME);
+    // Pressing only starts the charge
+    assert!(app.sent::<TargetHitEvent>().is_empty());
+    assert_eq!(app.target_count(), 1);
+
+    app.step_frames(held);
+    app.release(key);
+    app.step_frames(40);
+    app
+}
+
+#[test]
+fn letting_go_early_is_an_early_good() {
+    let app = charge_for(0);
+
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetHitEvent>()[0].1, Judgment::Good);
+    assert!(app.sent::<HitTimingEvent>()[0].2 < 0.0);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
+
+#[test]
+fn letting_go_at_the_crosshair_is_perfect() {
+    // The target falls 2.5 pixels a frame, so it's at the crosshair when let go
+    let app = charge_for(7);
+
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetHitEvent>()[0].1, Judgment::Perfect);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
+
+#[test]
+fn letting_go_after_the_hit_window_is_missed_once() {
+    // From past the late bound, through the frame the target is missed, to long after it
+    for held in 20..40 {
+        let app = charge_for(held);
+
+        assert!(
+            app.sent::<TargetHitEvent>().is_empty(),
+            "held {} frames",
+            held
+        );
+        assert_eq!(
+            app.sent::<TargetMissEvent>().len(),
+            1,
+            "held {} frames",
+            held
+        );
+        assert_eq!(app.resource::<Scoreboard>().misses, 1);
+    }
+}
@@ -0,0 +1,192 @@
+use super::*;
+
+fn parse(notes: &str) -> Result<Beatmap, ron::Error> {
+    ron::from_str(&format!("(notes: [{}])", notes))
+}
+
+#[test]
+fn notes_are_read_from_a_column() {
+    let beatmap = parse("(time_ms: 500, column: 2, duration_ms: 250)").unwrap();
+
+    assert_eq!(beatmap.notes.len(), 1);
+    assert_eq!(beatmap.notes[0].time_ms, 500);
+    assert_eq!(beatmap.notes[0].column, Column(2));
+    assert_eq!(beatmap.notes[0].duration_ms, 250);
+}
+
+#[test]
+fn chords_are_split_into_a_note_for_each_column() {
+    let beatmap = parse("(time_ms: 0, columns: [0, 3]), (time_ms: 100, column: 1)").unwrap();
+
+    let notes: Vec<(u32, Column)> = beatmap
+        .notes
+        .iter()
+        .map(|note| (note.time_ms, note.column))
+        .collect();
+    assert_eq!(notes, [(0, Column(0)), (0, Column(3)), (100, Column(1))]);
+}
+
+#[test]
+fn notes_without_a_column_are_rejected() {
+    assert!(parse("(time_ms: 0)").is_err());
+    assert!(parse("(time_ms: 0, columns: [])").is_err());
+}
+
+#[test]
+fn notes_with_a_column_and_columns_are_rejected() {
+    assert!(parse("(time_ms: 0, column: 1, columns: [2, 3])").is_err());
+}
+
+#[test]
+fn bundled_charts_are_read() {
+    for chart in [
+        include_str!("../../assets/charts/example.ron"),
+        include_str!("../../assets/charts/tempo_change.ron"),
+    ] {
+        let beatmap: Beatmap = ron::from_str(chart).unwrap();
+        assert!(!beatmap.notes.is_empty());
+    }
+}
+
+#[test]
+fn note_at_the_start_of_the_song_falls_the_whole_way_and_is_perfectly_hittable() {
+    let mut app = TestApp::new()
+        .with_system(spawn_from_chart)
+        .with_system(update_targets.after(spawn_from_chart));
+    app.select_chart("(notes: [(time_ms: 0, column: 0)])");
+
+    // Step until the song would start, noting where the target first appears
+    let mut spawned_at = None;
+    loop {
+        app.step(FRAME);
+        let mut targets = app.app.world.query_filtered::<Entity, With<Target>>();
+        if let Some(target) = targets.iter(&app.app.world).next() {
+            spawned_at.get_or_insert(app.height(target).unwrap());
+        }
+        if app.resource::<ChartPlayback>().song_time_ms() >= 0 {
+            break;
+        }
+    }
+
+    let config = app.resource::<GameConfig>();
+    let (spawn_y, hit_y) = (config.spawn_y, config.hit_y);
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), config);
+    let frame_fall = scroll_speed * FRAME.as_secs_f32();
+    assert!(spawned_at.unwrap() >= spawn_y - frame_fall);
+
+    let mut targets = app.app.world.query_filtered::<Entity, With<Target>>();
+    let target = targets.iter(&app.app.world).next().unwrap();
+    // Where the target is, taken back to exactly when the song starts
+    let song_time_ms = app.resource::<ChartPlayback>().song_time_ms() as f32;
+    let when_due = app.height(target).unwrap() + scroll_speed * song_time_ms / 1000.0;
+    assert!(
+        (when_due - hit_y).abs() <= frame_fall,
+        "{} from the crosshair",
+        when_due - hit_y
+    );
+    let hit_window = *app.resource::<HitWindow>();
+    assert_eq!(
+        Judgment::from_distance(when_due - hit_y, hit_window),
+        Judgment::Perfect
+    );
+}
+
+#[test]
+fn tempo_changes_leave_notes_reaching_the_crosshair_at_their_authored_times() {
+    let chart = include_str!("../../assets/charts/tempo_change.ron");
+    let mut app = TestApp::new()
+        .with_system(spawn_from_chart)
+        .with_system(update_targets.after(spawn_from_chart));
+    let handle = app.select_chart(chart);
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), app.resource::<GameConfig>());
+
+    // When each target passed the crosshair, worked out from where it was on either side of it
+    let mut heights = HashMap::new();
+    let mut crossed = Vec::new();
+    let mut song_time_ms = 0;
+    for _ in 0..60 * 20 {
+        app.step(FRAME);
+        let now_ms = app.resource::<ChartPlayback>().song_time_ms();
+        let mut targets = app
+            .app
+            .world
+            .query_filtered::<(Entity, &Column), With<Target>>();
+        let targets: Vec<_> = targets
+            .iter(&app.app.world)
+            .map(|(target, column)| (target, *column))
+            .collect();
+        for (target, column) in targets {
+            let height = app.height(target).unwrap();
+            if let Some(before) = heights.insert(target, height) {
+                if before >= hit_y && height < hit_y {
+                    let ms = song_time_ms as f32 + (before - hit_y) / scroll_speed * 1000.0;
+                    crossed.push((ms, column));
+                }
+            }
+        }
+        song_time_ms = now_ms;
+    }
+
+    let notes = &app
+        .resource::<Assets<Beatmap>>()
+        .get(&handle)
+        .unwrap()
+        .notes;
+    assert_eq!(crossed.len(), notes.len());
+    crossed.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.index().cmp(&b.1.index())));
+    let mut notes: Vec<_> = notes
+        .iter()
+        .map(|note| (note.time_ms, note.column))
+        .collect();
+    notes.sort_by_key(|&(time_ms, column)| (time_ms, column.index()));
+    let frame_ms = FRAME.as_secs_f32() * 1000.0;
+    for ((crossed_ms, crossed_column), (time_ms, column)) in crossed.into_iter().zip(notes) {
+        assert_eq!(crossed_column, column);
+        assert!(
+            (crossed_ms - time_ms as f32).abs() <= frame_ms,
+            "the note at {}ms reached the crosshair at {}ms",
+            time_ms,
+            crossed_ms
+        );
+    }
+}
+
+#[test]
+fn a_long_frame_leaves_charted_notes_in_time_with_the_chart() {
+    let mut app = TestApp::new()
+        .with_system(spawn_from_chart)
+        .with_system(update_targets.after(spawn_from_chart));
+    app.select_chart("(notes: [(time_ms: 3000, column: 0)])");
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), app.resource::<GameConfig>());
+
+    // Wait for the note to be on its way, then stall for longer than the cap on random runs
+    let mut targets = app.app.world.query_filtered::<Entity, With<Target>>();
+    let target = loop {
+        app.step(FRAME);
+        if let Some(target) = targets.iter(&app.app.world).next() {
+            break target;
+        }
+    };
+    app.step(Duration::from_millis(300));
+    while app.resource::<ChartPlayback>().song_time_ms() < 3000 {
+        app.step(FRAME);
+    }
+
+    // Where the target is, taken back to exactly when the note is due
+    let late_ms = (app.resource::<ChartPlayback>().song_time_ms() - 3000) as f32;
+    let when_due = app.height(target).unwrap() + scroll_speed * late_ms / 1000.0;
+    let frame_fall = scroll_speed * FRAME.as_secs_f32();
+    assert!(
+        (when_due - hit_y).abs() <= frame_fall,
+        "{} from the crosshair",
+        when_due - hit_y
+    );
+}
@@ -0,0 +1,66 @@
+use super::*;
+
+/// Plays a hit sound for every hit, with each column's sounds capped at `voices`
+fn with_voices(voices: HitSoundVoices) -> TestApp {
+    let mut app = TestApp::new().with_system(play_hit_sound);
+    app.app
+        .insert_resource(voices)
+        .insert_resource(HitSounds::default())
+        .insert_resource(NoteAudioHandles(vec![Handle::default(); 4]))
+        .init_resource::<HitSoundSinks>();
+    app
+}
+
+/// Hits each column in a frame
+fn hit(app: &mut TestApp, columns: impl IntoIterator<Item = Column>) {
+    for column in columns {
+        app.app
+            .world
+            .send_event(TargetHitEvent(column, Judgment::Perfect));
+    }
+    app.step(FRAME);
+}
+
+/// How many of a column's hit sounds haven't been cut off
+fn voices(app: &TestApp, column: Column) -> usize {
+    app.resource::<HitSoundSinks>()
+        .0
+        .get(&column)
+        .map_or(0, VecDeque::len)
+}
+
+#[test]
+fn hit_sounds_are_not_cut_off_by_default() {
+    let mut app = with_voices(HitSoundVoices::default());
+
+    for _ in 0..8 {
+        hit(&mut app, [Column(0)]);
+    }
+
+    assert!(app.resource::<HitSoundSinks>().0.is_empty());
+}
+
+#[test]
+fn a_chord_plays_a_sound_for_every_column() {
+    // Even with only one sound allowed per column
+    let mut app = with_voices(HitSoundVoices::Capped(1));
+
+    hit(&mut app, (0..4).map(Column));
+
+    for column in (0..4).map(Column) {
+        assert_eq!(voices(&app, column), 1);
+    }
+}
+
+#[test]
+fn a_stream_in_one_column_is_capped() {
+    let mut app = with_voices(HitSoundVoices::Capped(2));
+
+    for _ in 0..8 {
+        hit(&mut app, [Column(1)]);
+    }
+
+    assert_eq!(voices(&app, Column(1)), 2);
+    // The other columns are still free to play their own sounds
+    assert_eq!(voices(&app, Column(0)), 0);
+}
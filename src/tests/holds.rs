@@ -0,0 +1,47 @@
+use super::*;
+
+/// An app with a hold note whose head has just been shot at the crosshair, with its key still down
+fn app_holding(duration_ms: u32) -> (TestApp, Entity, KeyCode) {
+    let mut app = TestApp::new()
+        .with_system(shoot_targets)
+        .with_system(update_targets);
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    let target = app.spawn_target(Column(1), duration_ms, hit_y);
+    let key = app.key(Column(1));
+
+    app.press(key);
+    app.step(FRAME);
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    (app, target, key)
+}
+
+#[test]
+fn letting_go_of_a_hold_note_early_is_a_miss() {
+    let (mut app, target, key) = app_holding(1000);
+    app.step_frames(5);
+
+    app.release(key);
+    app.step(FRAME);
+
+    assert_eq!(app.height(target), None);
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetMissEvent>().len(), 1);
+    assert_eq!(app.resource::<Scoreboard>().combo, 0);
+}
+
+#[test]
+fn holding_a_hold_note_through_its_tail_is_a_hit() {
+    let (mut app, target, key) = app_holding(200);
+
+    // The 200ms tail has passed the crosshair after 12 frames
+    app.step_frames(14);
+
+    assert_eq!(app.height(target), None);
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 2);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+
+    // Letting go afterwards doesn't count against it
+    app.release(key);
+    app.step(FRAME);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
@@ -0,0 +1,182 @@
+use bevy::ecs::system::SystemState;
+
+use super::*;
+
+/// How a press is judged with a target `distance` pixels above the crosshair,
+/// or `None` if the press didn't hit anything
+fn judge_at(distance: f32) -> Option<Judgment> {
+    let mut app = TestApp::new().with_system(shoot_targets);
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    app.spawn_target(Column(0), 0, hit_y + distance);
+
+    let key = app.key(Column(0));
+    app.tap(key);
+
+    app.sent::<TargetHitEvent>()
+        .first()
+        .map(|TargetHitEvent(_, judgment)| *judgment)
+}
+
+#[test]
+fn perfect_window_includes_its_edges() {
+    let perfect = HitWindow::Normal.perfect();
+    assert_eq!(judge_at(0.0), Some(Judgment::Perfect));
+    assert_eq!(judge_at(perfect), Some(Judgment::Perfect));
+    assert_eq!(judge_at(-perfect), Some(Judgment::Perfect));
+}
+
+#[test]
+fn good_window_starts_just_past_perfect() {
+    let perfect = HitWindow::Normal.perfect();
+    let good = HitWindow::Normal.good();
+    assert_eq!(judge_at(perfect + 0.5), Some(Judgment::Good));
+    assert_eq!(judge_at(-perfect - 0.5), Some(Judgment::Good));
+    assert_eq!(judge_at(good), Some(Judgment::Good));
+    assert_eq!(judge_at(-good), Some(Judgment::Good));
+}
+
+#[test]
+fn targets_past_the_good_window_are_out_of_reach() {
+    let good = HitWindow::Normal.good();
+    assert_eq!(judge_at(good + 0.5), None);
+    assert_eq!(judge_at(-good - 0.5), None);
+}
+
+#[test]
+fn distances_past_the_good_window_are_misses() {
+    for hit_window in [HitWindow::Strict, HitWindow::Normal, HitWindow::Relaxed] {
+        let good = hit_window.good();
+        assert_eq!(Judgment::from_distance(good, hit_window), Judgment::Good);
+        assert_eq!(
+            Judgment::from_distance(good + 0.01, hit_window),
+            Judgment::Miss
+        );
+        assert_eq!(
+            Judgment::from_distance(-good - 0.01, hit_window),
+            Judgment::Miss
+        );
+    }
+}
+
+/// The timing error of a press made exactly as a target reaches the crosshair, halfway through
+/// a frame, when every frame takes `delta`
+fn timing_error_at_frame_rate(delta: Duration, update_first: bool) -> f32 {
+    let app = TestApp::new();
+    let mut app = if update_first {
+        app.with_system(update_targets.before(shoot_targets))
+            .with_system(shoot_targets)
+    } else {
+        app.with_system(update_targets.after(shoot_targets))
+            .with_system(shoot_targets)
+    };
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), app.resource::<GameConfig>());
+    let hit_y = app.resource::<GameConfig>().hit_y;
+
+    // It falls for a few frames, then reaches the crosshair halfway through the next one
+    let frames = 4;
+    let height = hit_y + scroll_speed * delta.as_secs_f32() * (frames as f32 + 0.5);
+    app.spawn_target(Column(0), 0, height);
+    for _ in 0..frames {
+        app.step(delta);
+    }
+    let key = app.key(Column(0));
+    app.press(key);
+    app.step(delta);
+
+    let HitTimingEvent(_, _, error_ms) = app.sent::<HitTimingEvent>()[0];
+    error_ms
+}
+
+#[test]
+fn presses_are_judged_the_same_at_any_frame_rate() {
+    for frame_rate in [30, 60, 144, 240] {
+        let delta = Duration::from_secs_f64(1.0 / f64::from(frame_rate));
+        for update_first in [true, false] {
+            let error_ms = timing_error_at_frame_rate(delta, update_first);
+            assert!(
+                error_ms.abs() < 0.5,
+                "{}ms off at {} frames per second",
+                error_ms,
+                frame_rate
+            );
+        }
+    }
+}
+
+#[test]
+fn presses_are_judged_through_uneven_frames() {
+    let mut app = TestApp::new()
+        .with_system(update_targets.before(shoot_targets))
+        .with_system(shoot_targets);
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), app.resource::<GameConfig>());
+    let hit_y = app.resource::<GameConfig>().hit_y;
+
+    // A stutter of long and short frames, then a press halfway through a normal one,
+    // right as the target reaches the crosshair
+    let stutter = [8, 40, 16, 33, 7, 25, 16].map(Duration::from_millis);
+    let falling: Duration = stutter.iter().sum::<Duration>() + FRAME / 2;
+    app.spawn_target(Column(0), 0, hit_y + scroll_speed * falling.as_secs_f32());
+    for delta in stutter {
+        app.step(delta);
+    }
+    let key = app.key(Column(0));
+    app.tap(key);
+
+    let HitTimingEvent(_, judgment, error_ms) = app.sent::<HitTimingEvent>()[0];
+    assert_eq!(judgment, Judgment::Perfect);
+    assert!(error_ms.abs() < 0.5, "{}ms off", error_ms);
+}
+
+/// An app with the judgment line raised by `raised` pixels, laid out the way a run would be
+fn with_judgment_line(raised: i32) -> TestApp {
+    let mut app = TestApp::new()
+        .with_system(shoot_targets)
+        .with_system(update_targets);
+    app.app
+        .insert_resource(JudgmentLine(raised))
+        .init_resource::<UpScroll>()
+        .init_resource::<ColumnShapes>();
+    let mut layout =
+        SystemState::<(PlayfieldSettings, ResMut<GameConfig>)>::new(&mut app.app.world);
+    let (playfield, mut config) = layout.get_mut(&mut app.app.world);
+    playfield.apply(&mut config);
+    app
+}
+
+#[test]
+fn target_at_a_raised_judgment_line_is_perfect() {
+    let mut app = with_judgment_line(100);
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    assert_eq!(hit_y, GameConfig::default().hit_y + 100.0);
+    app.spawn_target(Column(0), 0, hit_y);
+
+    let key = app.key(Column(0));
+    app.tap(key);
+
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetHitEvent>()[0].1, Judgment::Perfect);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
+
+#[test]
+fn target_at_the_old_judgment_line_is_too_late_once_it_is_raised() {
+    let mut app = with_judgment_line(100);
+    let config = app.resource::<GameConfig>();
+    // Above where it would be missed, but too far past the crosshair to be hit
+    let height = (config.late_hit_y + config.despawn_y) / 2.0;
+    assert!(height > GameConfig::default().hit_y);
+    let target = app.spawn_target(Column(0), 0, height);
+
+    let key = app.key(Column(0));
+    app.tap(key);
+    assert!(app.sent::<TargetHitEvent>().is_empty());
+
+    // It's missed at the raised despawn line, well before the old one
+    app.step_frames(10);
+    assert_eq!(app.height(target), None);
+    assert_eq!(app.sent::<TargetMissEvent>().len(), 1);
+}
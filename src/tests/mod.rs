@@ -0,0 +1,261 @@
+//! A headless version of the game for testing its systems, without a window, audio or rendering.
+//!
+//! Each test builds a [`TestApp`] with only the systems it's testing, then steps it forward a
+//! frame at a time with whatever time and input it needs
+
+mod bindings;
+mod charging;
+mod charts;
+mod hit_sounds;
+mod holds;
+mod judgment;
+mod practice;
+mod replays;
+mod results;
+mod saves;
+mod scoring;
+mod sessions;
+mod settings;
+mod shooting;
+mod songs;
+mod states;
+mod targets;
+
+use std::time::Duration;
+
+use bevy::{
+    asset::AssetPlugin,
+    ecs::{schedule::IntoSystemDescriptor, system::CommandQueue},
+    tasks::{IoTaskPool, TaskPool},
+};
+
+use super::*;
+
+/// How long a frame takes at 60 frames per second
+pub const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// A silent WAV file, at a thousand one-byte frames a second so each millisecond is a byte
+pub fn silent_wav(length_ms: u32) -> Vec<u8> {
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(4 + 24 + 8 + length_ms).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, in mono, at 1000 frames a second and 1000 bytes a second, one 8 bit byte a frame
+    for field in [1u16, 1] {
+        wav.extend_from_slice(&field.to_le_bytes());
+    }
+    for field in [1000u32, 1000] {
+        wav.extend_from_slice(&field.to_le_bytes());
+    }
+    for field in [1u16, 8] {
+        wav.extend_from_slice(&field.to_le_bytes());
+    }
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&length_ms.to_le_bytes());
+    wav.resize(wav.len() + length_ms as usize, 128);
+    wav
+}
+
+/// Every event of a type that's been sent since the app was built
+pub struct Sent<E>(pub Vec<E>);
+
+impl<E> Default for Sent<E> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+/// Keeps every event of a type, so tests can look back at them after the frame they were sent in
+fn record_events<E: Clone + Send + Sync + 'static>(
+    mut event_reader: EventReader<E>,
+    mut sent: ResMut<Sent<E>>,
+) {
+    sent.0.extend(event_reader.iter().cloned());
+}
+
+/// An [`App`] with the game's resources and events, but none of its plugins or systems.
+///
+/// The resources start out as they would for a new player, and the clock only moves when
+/// the app is stepped, so tests run the same however fast the machine running them is
+pub struct TestApp {
+    pub app: App,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        let mut time = Time::default();
+        let startup = time.startup();
+        time.update_with_instant(startup);
+
+        // The asset server loads on this pool, which the core plugin would otherwise set up
+        IoTaskPool::init(TaskPool::default);
+
+        let mut app = App::new();
+        app.add_plugin(AssetPlugin)
+            .add_asset::<Beatmap>()
+            .add_asset::<AudioSource>()
+            .add_asset::<AudioSink>()
+            .init_resource::<Audio>()
+            .insert_resource(time)
+            .insert_resource(GameConfig::default())
+            .insert_resource(Theme::default())
+            .insert_resource(KeyBindings::default())
+            .insert_resource(ScoringConfig::default())
+            .insert_resource(ScrollSpeed::default())
+            .insert_resource(Volume::default())
+            .init_resource::<Difficulty>()
+            .init_resource::<HitWindow>()
+            .init_resource::<AudioOffset>()
+            .init_resource::<Modifiers>()
+            .init_resource::<EndlessMode>()
+            .init_resource::<ChargeScoring>()
+            .init_resource::<Lanes>()
+            .init_resource::<PunishGhostTaps>()
+            .init_resource::<PointerInput>()
+            .init_resource::<SurvivalTime>()
+            .init_resource::<Scoreboard>()
+            .init_resource::<Health>()
+            .init_resource::<ChartPlayback>()
+            .init_resource::<SelectedChart>()
+            .init_resource::<PracticeLoop>()
+            .init_resource::<ColumnMapping>()
+            .insert_resource(TextureAtlasHandles {
+                crosshairs: Some(Handle::default()),
+                targets: Some(Handle::default()),
+            })
+            .init_resource::<ColumnInputState>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Input<MouseButton>>()
+            .init_resource::<Gamepads>()
+            .init_resource::<Touches>()
+            .init_resource::<Windows>();
+
+        let mut test_app = Self { app };
+        test_app
+            .record::<TargetHitEvent>()
+            .record::<TargetMissEvent>()
+            .record::<HitTimingEvent>();
+        test_app
+    }
+
+    /// Adds an event to the app, and keeps every one that's sent in [`Sent`]
+    pub fn record<E: Clone + Send + Sync + 'static>(&mut self) -> &mut Self {
+        self.app
+            .add_event::<E>()
+            .init_resource::<Sent<E>>()
+            .add_system_to_stage(CoreStage::Last, record_events::<E>);
+        self
+    }
+
+    /// Adds a system under test, to run every frame
+    pub fn with_system<Params>(mut self, system: impl IntoSystemDescriptor<Params>) -> Self {
+        self.app.add_system(system);
+        self
+    }
+
+    /// Runs one frame that took `delta`
+    pub fn step(&mut self, delta: Duration) {
+        let mut time = self.app.world.resource_mut::<Time>();
+        let last_update = time.last_update().unwrap_or_else(|| time.startup());
+        time.update_with_instant(last_update + delta);
+
+        self.app.update();
+
+        // Like the input plugin would at the start of the next frame
+        self.app.world.resource_mut::<Input<KeyCode>>().clear();
+    }
+
+    /// Runs `frames` frames at 60 frames per second
+    pub fn step_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.step(FRAME);
+        }
+    }
+
+    /// Holds a key down, from the next frame on
+    pub fn press(&mut self, key: KeyCode) {
+        self.app.world.resource_mut::<Input<KeyCode>>().press(key);
+    }
+
+    /// Lets go of a key, from the next frame on
+    pub fn release(&mut self, key: KeyCode) {
+        self.app.world.resource_mut::<Input<KeyCode>>().release(key);
+    }
+
+    /// Presses a key for one frame, then lets go of it for another
+    pub fn tap(&mut self, key: KeyCode) {
+        self.press(key);
+        self.step(FRAME);
+        self.release(key);
+        self.step(FRAME);
+    }
+
+    /// The first key bound to a column
+    pub fn key(&self, column: Column) -> KeyCode {
+        self.resource::<KeyBindings>().keys(column)[0]
+    }
+
+    /// Spawns a target the way the game does, at a height in the falling frame.
+    /// A `duration_ms` above 0 makes it a hold note
+    pub fn spawn_target(&mut self, column: Column, duration_ms: u32, height: f32) -> Entity {
+        let world = &mut self.app.world;
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        let config = world.resource::<GameConfig>();
+        let scroll_speed = world
+            .resource::<ScrollSpeed>()
+            .apply(*world.resource::<Difficulty>(), config);
+        let target = spawn_target(
+            &mut commands,
+            &Handle::default(),
+            config,
+            world.resource::<Theme>(),
+            column,
+            duration_ms,
+            scroll_speed,
+            height,
+        );
+        queue.apply(world);
+        target
+    }
+
+    /// Picks a chart to play, written the way it would be in a chart file
+    pub fn select_chart(&mut self, chart: &str) -> Handle<Beatmap> {
+        let beatmap: Beatmap = ron::from_str(chart).expect("the chart should parse");
+        let handle = self
+            .app
+            .world
+            .resource_mut::<Assets<Beatmap>>()
+            .add(beatmap);
+        self.app
+            .insert_resource(SelectedChart(Some(handle.clone())));
+        handle
+    }
+
+    /// How high a target is in the falling frame, or `None` if it's been despawned
+    pub fn height(&self, target: Entity) -> Option<f32> {
+        let transform = self.app.world.get::<Transform>(target)?;
+        Some(self.resource::<GameConfig>().flip(transform.translation.y))
+    }
+
+    /// How many targets are left
+    pub fn target_count(&mut self) -> usize {
+        self.app
+            .world
+            .query::<&Target>()
+            .iter(&self.app.world)
+            .count()
+    }
+
+    pub fn resource<R: Send + Sync + 'static>(&self) -> &R {
+        self.app.world.resource::<R>()
+    }
+
+    /// Every event of a type that's been sent so far
+    pub fn sent<E: Send + Sync + 'static>(&self) -> &[E] {
+        &self.resource::<Sent<E>>().0
+    }
+}
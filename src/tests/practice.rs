@@ -0,0 +1,53 @@
+use super::*;
+
+/// A chart with a note every second for five seconds, being practiced
+fn practicing(start_ms: u32, end_ms: u32) -> TestApp {
+    let mut app = TestApp::new().with_system(spawn_from_chart);
+    app.select_chart(
+        "(notes: [
+            (time_ms: 0, column: 0),
+            (time_ms: 1000, column: 1),
+            (time_ms: 2000, column: 2),
+            (time_ms: 3000, column: 3),
+            (time_ms: 4000, column: 0),
+        ])",
+    );
+    app.app.insert_resource(PracticeLoop {
+        enabled: true,
+        start_ms,
+        end_ms,
+    });
+    app
+}
+
+#[test]
+fn practice_loop_is_kept_within_the_chart() {
+    let mut app = practicing(8000, 20_000);
+    app.step(FRAME);
+
+    let practice = app.resource::<PracticeLoop>();
+    assert_eq!(practice.end_ms, 4001);
+    assert_eq!(practice.start_ms, 4001 - PRACTICE_STEP_MS);
+}
+
+#[test]
+fn practice_loop_spawns_only_its_own_notes() {
+    let mut app = practicing(1000, 3000);
+
+    // Long enough to go round the two second loop once, and start it again
+    app.step_frames(60 * 3);
+
+    let playback = app.resource::<ChartPlayback>();
+    assert!(playback.started);
+    let columns: Vec<Column> = app
+        .app
+        .world
+        .query::<&Column>()
+        .iter(&app.app.world)
+        .copied()
+        .collect();
+    assert!(!columns.is_empty());
+    assert!(columns
+        .iter()
+        .all(|&column| column == Column(1) || column == Column(2)));
+}
@@ -0,0 +1,149 @@
+use bevy::ecs::system::SystemState;
+
+use super::*;
+
+/// The settings the app's run would be recorded with
+fn snapshot(app: &mut TestApp) -> ReplaySettings {
+    let mut run_settings = SystemState::<RunSettings>::new(&mut app.app.world);
+    run_settings.get(&app.app.world).snapshot()
+}
+
+/// An app recording a replay of a charted run that's `clock_ms` in
+fn recording_chart(clock_ms: u64) -> TestApp {
+    let mut app = TestApp::new().with_system(record_replay);
+    app.select_chart("(notes: [(time_ms: 0, column: 0)])");
+    let mut playback = ChartPlayback {
+        started: true,
+        ..Default::default()
+    };
+    playback.clock.set_elapsed(Duration::from_millis(clock_ms));
+    app.app.insert_resource(playback);
+
+    let settings = snapshot(&mut app);
+    app.app.insert_resource(ReplayRecorder {
+        replay: Replay {
+            chart: None,
+            seed: 0,
+            settings,
+            inputs: Vec::new(),
+        },
+        clock: Stopwatch::new(),
+        held: HashSet::new(),
+    });
+    app
+}
+
+#[test]
+fn charted_inputs_are_timed_by_the_chart() {
+    // However long the song took to load, the chart's clock says how far into it the run is
+    let mut app = recording_chart(1500);
+    let key = app.key(Column(0));
+    app.press(key);
+    app.step_frames(3);
+
+    app.app
+        .world
+        .resource_mut::<ChartPlayback>()
+        .clock
+        .set_elapsed(Duration::from_millis(2000));
+    app.release(key);
+    app.step(FRAME);
+
+    let inputs = &app.resource::<ReplayRecorder>().replay.inputs;
+    assert_eq!(inputs.len(), 2);
+    assert_eq!((inputs[0].time_ms, inputs[0].pressed), (1500, true));
+    assert_eq!((inputs[1].time_ms, inputs[1].pressed), (2000, false));
+}
+
+#[test]
+fn charted_inputs_wait_for_the_chart_to_start() {
+    let mut app = recording_chart(0);
+    app.app.world.resource_mut::<ChartPlayback>().started = false;
+    let key = app.key(Column(0));
+    app.tap(key);
+
+    assert!(app.resource::<ReplayRecorder>().replay.inputs.is_empty());
+}
+
+#[test]
+fn replays_are_watched_with_the_ghost_tap_and_scoring_settings_they_were_played_with() {
+    let mut recorded = TestApp::new();
+    recorded.app.insert_resource(PunishGhostTaps(true));
+    recorded.app.insert_resource(ScoringConfig {
+        combo_cap: 10,
+        perfect_points: 3,
+        ..Default::default()
+    });
+    let saved = ron::to_string(&snapshot(&mut recorded)).unwrap();
+
+    let mut watched = TestApp::new();
+    let settings: ReplaySettings = ron::from_str(&saved).unwrap();
+    let mut queue = CommandQueue::default();
+    settings.apply(&mut Commands::new(&mut queue, &watched.app.world));
+    queue.apply(&mut watched.app.world);
+
+    assert!(watched.resource::<PunishGhostTaps>().0);
+    let scoring = watched.resource::<ScoringConfig>();
+    assert_eq!(scoring.combo_cap, 10);
+    assert_eq!(scoring.perfect_points, 3);
+    assert_eq!(scoring.good_points, ScoringConfig::default().good_points);
+}
+
+#[test]
+fn run_codes_are_kept_within_the_settings_menus() {
+    let mut app = TestApp::new();
+    let mut settings = snapshot(&mut app);
+    settings.scroll_speed.travel_time_ms = 0;
+    settings.scroll_speed.multiplier = f32::NAN;
+    settings.audio_offset = AudioOffset(i32::MIN);
+    settings.lanes = Lanes(u8::MAX);
+    settings.scoring = ScoringConfig {
+        combo_cap: i32::MAX,
+        combo_step: 0,
+        perfect_points: i32::MAX,
+        good_points: -5,
+    };
+    let code = RunCode {
+        chart: None,
+        seed: 42,
+        settings,
+        score: 0,
+    }
+    .encode();
+
+    let settings = RunCode::decode(&code).unwrap().settings;
+    assert_eq!(settings.scroll_speed.travel_time_ms, TRAVEL_TIME_RANGE.0);
+    assert_eq!(settings.scroll_speed.multiplier, 1.0);
+    assert_eq!(settings.audio_offset.0, -AUDIO_OFFSET_LIMIT);
+    assert_eq!(settings.lanes.0, MAX_LANES);
+    assert_eq!(settings.scoring.combo_cap, SCORING_LIMIT);
+    assert_eq!(settings.scoring.combo_step, 1);
+    assert_eq!(settings.scoring.perfect_points, SCORING_LIMIT);
+    assert_eq!(settings.scoring.good_points, 0);
+
+    let travel_time = ScrollSpeed {
+        mode: ScrollMode::TravelTime,
+        ..settings.scroll_speed
+    };
+    let speed = travel_time.apply(Difficulty::default(), app.resource::<GameConfig>());
+    assert!(speed.is_finite());
+}
+
+#[test]
+fn run_codes_with_too_few_lanes_get_the_fewest_there_can_be() {
+    let mut app = TestApp::new();
+    let mut settings = snapshot(&mut app);
+    settings.lanes = Lanes(0);
+    let code = RunCode {
+        chart: None,
+        seed: 42,
+        settings,
+        score: 0,
+    }
+    .encode();
+
+    assert_eq!(
+        RunCode::decode(&code).unwrap().settings.lanes.0,
+        Lanes::default().0
+    );
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+/// An app where a one-note chart has just been played to the end
+fn app_at_end_of_chart() -> TestApp {
+    let mut app = TestApp::new()
+        .with_system(finish_chart)
+        .with_system(game_over_on_death);
+    app.select_chart("(notes: [(time_ms: 0, column: 0)])");
+    let mut playback = app.app.world.resource_mut::<ChartPlayback>();
+    playback.next_note = 1;
+    playback.clock.set_elapsed(Duration::from_secs(60));
+    app
+}
+
+fn result(app: &TestApp) -> Option<GameResult> {
+    app.app.world.get_resource::<GameResult>().copied()
+}
+
+#[test]
+fn reaching_the_end_of_a_chart_clears_it() {
+    let mut app = app_at_end_of_chart();
+    app.step(FRAME);
+
+    assert!(matches!(result(&app), Some(GameResult::Cleared)));
+}
+
+#[test]
+fn running_out_of_health_on_the_last_note_fails() {
+    let mut app = app_at_end_of_chart();
+    app.app.world.resource_mut::<Health>().current = 0;
+    app.step(FRAME);
+
+    assert!(matches!(result(&app), Some(GameResult::Failed)));
+}
@@ -0,0 +1,104 @@
+use super::*;
+
+#[test]
+fn columns_named_by_colour_are_numbered() {
+    assert_eq!(
+        number_colour_columns("{Yellow: [A], Red: [S], Blue: [D], Green: [F]}"),
+        "{0: [A], 1: [S], 2: [D], 3: [F]}"
+    );
+    assert_eq!(
+        number_colour_columns("[(time_ms: 0, column: Blue), (time_ms: 10, columns: [Red, Green])]"),
+        "[(time_ms: 0, column: 2), (time_ms: 10, columns: [1, 3])]"
+    );
+}
+
+#[test]
+fn numbering_columns_leaves_everything_else_alone() {
+    let chart =
+        r#"(title: Some("Red \"Blue\" Green"), background: Some(Color(0.1, 1e3, 0.2)), notes: [])"#;
+    assert_eq!(number_colour_columns(chart), chart);
+}
+
+#[test]
+fn key_bindings_saved_with_colour_names_keep_their_keys() {
+    let saved =
+        "(columns: {Yellow: [Q], Red: [W], Blue: [E], Green: [R]}, gamepad: {Yellow: [South]})";
+    assert!(ron::from_str::<KeyBindings>(saved).is_err());
+
+    let key_bindings: KeyBindings = ron::from_str(&number_colour_columns(saved)).unwrap();
+    assert_eq!(key_bindings.keys(Column(0)), [KeyCode::Q]);
+    assert_eq!(key_bindings.keys(Column(3)), [KeyCode::R]);
+    assert_eq!(key_bindings.buttons(Column(0)), [GamepadButtonType::South]);
+}
+
+#[test]
+fn key_bindings_saved_with_four_columns_get_defaults_for_the_rest() {
+    let saved = "(columns: {0: [G], 1: [S], 2: [D], 3: [F]}, gamepad: {})";
+    let key_bindings = ron::from_str::<KeyBindings>(saved)
+        .unwrap()
+        .with_default_columns();
+
+    assert_eq!(key_bindings.keys(Column(0)), [KeyCode::G]);
+    // G is already taken, so only the other default is bound
+    assert_eq!(key_bindings.keys(Column(4)), [KeyCode::Semicolon]);
+    assert_eq!(
+        key_bindings.keys(Column(5)),
+        [KeyCode::B, KeyCode::Apostrophe]
+    );
+    assert_eq!(
+        key_bindings.buttons(Column(0)),
+        [GamepadButtonType::West, GamepadButtonType::DPadLeft]
+    );
+}
+
+#[test]
+fn extra_lanes_fit_on_the_playfield() {
+    let mut app = TestApp::new();
+    app.app.insert_resource(Lanes(MAX_LANES));
+    app.app.init_resource::<UpScroll>();
+    app.app.init_resource::<ColumnShapes>();
+    app.app.init_resource::<JudgmentLine>();
+    app.app.add_system(
+        |playfield: PlayfieldSettings, mut config: ResMut<GameConfig>| playfield.apply(&mut config),
+    );
+    app.step(FRAME);
+
+    let config = app.resource::<GameConfig>();
+    assert_eq!(config.columns().count(), usize::from(MAX_LANES));
+    let edge = config.column_x(Column(MAX_LANES - 1)) + config.column_spacing / 2.0;
+    assert!(edge <= PLAYFIELD_WIDTH / 2.0);
+}
+
+#[test]
+fn scroll_speeds_saved_as_a_bare_multiplier_keep_it() {
+    let scroll_speed: ScrollSpeed = ron::from_str("(1.5)").unwrap();
+    assert!(scroll_speed.mode == ScrollMode::Multiplier);
+    assert_eq!(scroll_speed.multiplier, 1.5);
+    assert_eq!(
+        scroll_speed.travel_time_ms,
+        ScrollSpeed::default().travel_time_ms
+    );
+}
+
+#[test]
+fn scroll_speeds_still_round_trip() {
+    let mut scroll_speed = ScrollSpeed {
+        mode: ScrollMode::TravelTime,
+        ..Default::default()
+    };
+    scroll_speed.adjust(true);
+
+    let loaded: ScrollSpeed = ron::from_str(&ron::to_string(&scroll_speed).unwrap()).unwrap();
+    assert!(loaded.mode == ScrollMode::TravelTime);
+    assert_eq!(loaded.travel_time_ms, scroll_speed.travel_time_ms);
+    assert_eq!(loaded.multiplier, 1.0);
+}
+
+#[test]
+fn replays_saved_with_a_bare_scroll_speed_multiplier_keep_it() {
+    let saved = "(difficulty: Hard, scroll_speed: (2.0), hit_window: Normal, audio_offset: (0), \
+        modifiers: (hidden: false, sudden: false, no_fail: false, adaptive: false))";
+    let settings: ReplaySettings = ron::from_str(saved).unwrap();
+    assert!(settings.scroll_speed.mode == ScrollMode::Multiplier);
+    assert_eq!(settings.scroll_speed.multiplier, 2.0);
+}
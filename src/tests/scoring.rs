@@ -0,0 +1,133 @@
+use super::*;
+
+/// Builds a combo by shooting `hits` targets at the crosshair one after another
+fn app_with_combo(hits: u32) -> TestApp {
+    let mut app = TestApp::new()
+        .with_system(shoot_targets)
+        .with_system(update_targets);
+    add_to_combo(&mut app, hits);
+    app
+}
+
+/// Adds to the combo by shooting `hits` more targets at the crosshair
+fn add_to_combo(app: &mut TestApp, hits: u32) {
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    let key = app.key(Column(0));
+    for _ in 0..hits {
+        app.spawn_target(Column(0), 0, hit_y);
+        app.tap(key);
+    }
+}
+
+/// How much the score changes when a target falls past the crosshair
+fn miss_delta(app: &mut TestApp) -> i32 {
+    let despawn_y = app.resource::<GameConfig>().despawn_y;
+    let before = app.resource::<Scoreboard>().score;
+    app.spawn_target(Column(3), 0, despawn_y + 1.0);
+    app.step_frames(2);
+    assert_eq!(app.sent::<TargetMissEvent>().len(), 1);
+    app.resource::<Scoreboard>().score - before
+}
+
+#[test]
+fn miss_after_a_five_combo_costs_the_combo() {
+    let mut app = app_with_combo(5);
+    assert_eq!(app.resource::<Scoreboard>().combo, 5);
+
+    assert_eq!(miss_delta(&mut app), -6);
+    assert_eq!(app.resource::<Scoreboard>().combo, 0);
+}
+
+#[test]
+fn miss_without_a_combo_costs_one_point() {
+    let mut app = app_with_combo(0);
+
+    assert_eq!(miss_delta(&mut app), -1);
+}
+
+#[test]
+fn combo_milestones_are_celebrated_exactly_at_the_threshold() {
+    let mut app = app_with_combo(0).with_system(celebrate_combo_milestones.after(shoot_targets));
+    app.record::<AnnouncementEvent>();
+    app.app
+        .init_resource::<MenuAssetHandles>()
+        .init_resource::<NoteAudioHandles>();
+    app.app.world.resource_mut::<GameConfig>().combo_milestone = 5;
+
+    let mut celebrated = Vec::new();
+    for _ in 0..12 {
+        add_to_combo(&mut app, 1);
+        celebrated.push(app.sent::<AnnouncementEvent>().len());
+    }
+
+    // After each hit, from the 1st to the 12th
+    assert_eq!(celebrated, [0, 0, 0, 0, 1, 1, 1, 1, 1, 2, 2, 2]);
+    assert!(app
+        .sent::<AnnouncementEvent>()
+        .iter()
+        .all(|event| event.0 == Announcement::Milestone));
+}
+
+/// Hits `hits` targets in a row then misses one, returning what was announced
+fn break_streak(hits: u32) -> Vec<Announcement> {
+    let mut app = app_with_combo(0).with_system(
+        announce_combo_breaks
+            .after(shoot_targets)
+            .after(update_targets),
+    );
+    app.record::<AnnouncementEvent>();
+    add_to_combo(&mut app, hits);
+    assert_eq!(app.resource::<Scoreboard>().streak, hits);
+    miss_delta(&mut app);
+
+    app.sent::<AnnouncementEvent>()
+        .iter()
+        .map(|event| event.0)
+        .collect()
+}
+
+#[test]
+fn breaking_a_long_streak_is_announced() {
+    assert_eq!(
+        break_streak(ANNOUNCED_COMBO_BREAK),
+        [Announcement::ComboBreak]
+    );
+}
+
+#[test]
+fn breaking_a_short_streak_is_not_announced() {
+    assert!(break_streak(ANNOUNCED_COMBO_BREAK - 1).is_empty());
+}
+
+/// The score from hitting `hits` targets perfectly, one after another, with a scoring curve
+fn full_combo_score(scoring: ScoringConfig, hits: u32) -> i32 {
+    let mut app = app_with_combo(0);
+    app.app.insert_resource(scoring);
+    add_to_combo(&mut app, hits);
+
+    let score = app.resource::<Scoreboard>();
+    assert_eq!(score.perfects, hits);
+    assert_eq!(score.max_combo, hits);
+    score.score
+}
+
+#[test]
+fn full_combo_follows_the_default_curve() {
+    // The combo climbs 1, 2, 3, 4, 5 then stays at the cap, and each perfect is worth double it
+    assert_eq!(
+        full_combo_score(ScoringConfig::default(), 10),
+        2 * (1 + 2 + 3 + 4 + 5 * 6)
+    );
+}
+
+#[test]
+fn full_combo_follows_a_configured_curve() {
+    let scoring = ScoringConfig {
+        combo_cap: 7,
+        combo_step: 3,
+        perfect_points: 4,
+        good_points: 1,
+    };
+    // The combo climbs 3, 6 then stays at the cap of 7
+    assert_eq!(full_combo_score(scoring, 5), 4 * (3 + 6 + 7 * 3));
+}
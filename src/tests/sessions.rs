@@ -0,0 +1,93 @@
+use bevy::ecs::system::SystemState;
+
+use super::*;
+
+/// Sets up a run resumed from a session that had targets in `columns`
+fn resuming(columns: impl IntoIterator<Item = u8>) -> TestApp {
+    let mut app = TestApp::new().with_system(setup_game);
+    let mut run_settings = SystemState::<RunSettings>::new(&mut app.app.world);
+    let settings = run_settings.get(&app.app.world).snapshot();
+    let session = SavedSession {
+        chart: None,
+        settings,
+        score: Scoreboard::default(),
+        health: Health::default(),
+        chart_ms: 0,
+        next_note: 0,
+        spawn_interval_ms: 1000,
+        spawn_elapsed_ms: 0,
+        column_mapping: ColumnMapping::default(),
+        survived_ms: 0,
+        targets: columns
+            .into_iter()
+            .map(|column| SavedTarget {
+                column: Column(column),
+                height: 100.0,
+                duration_ms: 0,
+            })
+            .collect(),
+    };
+    app.app
+        .insert_resource(ResumedSession(session))
+        .insert_resource(StartMode::default())
+        .insert_resource(KeyLabels(true))
+        // Auto play keeps the run from being recorded, which would clear a saved session
+        .insert_resource(AutoPlay(true))
+        .init_resource::<UpScroll>()
+        .init_resource::<ColumnShapes>()
+        .init_resource::<JudgmentLine>();
+    app
+}
+
+#[test]
+fn resumed_targets_carry_on_from_where_they_were() {
+    let mut app = resuming([0, 3]);
+    app.step(FRAME);
+
+    let world = &mut app.app.world;
+    let mut columns: Vec<u8> = world
+        .query_filtered::<&Column, With<Target>>()
+        .iter(world)
+        .map(|column| column.0)
+        .collect();
+    columns.sort_unstable();
+    assert_eq!(columns, [0, 3]);
+}
+
+#[test]
+fn resumed_targets_off_the_playfield_are_skipped() {
+    // Saved with six lanes, but resumed with four
+    let mut app = resuming([0, 4, 5]);
+    app.step(FRAME);
+
+    assert_eq!(app.target_count(), 1);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
+
+#[test]
+fn resumed_runs_start_their_song_part_way_through() {
+    let mut app = TestApp::new().with_system(spawn_from_chart);
+    app.select_chart("(notes: [(time_ms: 5000, column: 0)])");
+    let song = app
+        .app
+        .world
+        .resource_mut::<Assets<AudioSource>>()
+        .add(AudioSource {
+            bytes: silent_wav(10_000).into(),
+        });
+    // As a run two seconds into the song would be restored
+    let mut playback = ChartPlayback {
+        started: true,
+        song: Some(song),
+        resumed: true,
+        ..Default::default()
+    };
+    playback.clock.set_elapsed(Duration::from_millis(2000));
+    app.app.insert_resource(playback);
+    app.step(FRAME);
+
+    // The song is cut to start where the chart was, instead of the chart going back to the start
+    let playback = app.resource::<ChartPlayback>();
+    assert!(playback.song_sink.is_some());
+    assert!(playback.song_time_ms() >= 2000);
+}
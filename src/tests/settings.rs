@@ -0,0 +1,101 @@
+use super::*;
+
+/// The settings menu, as it's first shown
+fn settings_menu() -> TestApp {
+    let mut app = TestApp::new().with_system(update_settings_page);
+    app.app
+        .add_startup_system(setup_settings_menu)
+        .insert_resource(MenuAssetHandles {
+            logo: None,
+            font: Some(Handle::default()),
+        })
+        .init_resource::<HitSounds>()
+        .init_resource::<HitSoundVoices>()
+        .insert_resource(AutoPlay(false))
+        .init_resource::<Metronome>()
+        .init_resource::<StartMode>()
+        .init_resource::<ComboDucking>()
+        .init_resource::<Announcements>()
+        .init_resource::<StreamerMode>()
+        .init_resource::<TrainingWheels>()
+        .init_resource::<ScreenShake>()
+        .init_resource::<KeyLabels>()
+        .init_resource::<UpScroll>()
+        .init_resource::<ColumnShapes>()
+        .init_resource::<BeatPulse>()
+        .init_resource::<TimingMarkers>()
+        .init_resource::<FastSlow>()
+        .init_resource::<JudgmentLine>()
+        .init_resource::<FrameRate>();
+    app.step(FRAME);
+    app
+}
+
+/// Whether each page is shown, in the order they're flicked through
+fn shown_pages(app: &mut TestApp) -> Vec<(SettingsPage, bool)> {
+    let world = &mut app.app.world;
+    let mut pages: Vec<_> = world
+        .query::<(&Style, &SettingsPageNode)>()
+        .iter(world)
+        .map(|(style, SettingsPageNode(page))| (*page, style.display == Display::Flex))
+        .collect();
+    pages.sort_by_key(|(page, _)| SettingsPage::ALL.iter().position(|other| other == page));
+    pages
+}
+
+#[test]
+fn settings_menu_starts_on_its_first_page() {
+    let mut app = settings_menu();
+
+    assert_eq!(
+        shown_pages(&mut app),
+        [
+            (SettingsPage::Audio, true),
+            (SettingsPage::Display, false),
+            (SettingsPage::Guides, false),
+            (SettingsPage::Gameplay, false),
+        ]
+    );
+}
+
+#[test]
+fn settings_pages_fit_in_the_window() {
+    let mut app = settings_menu();
+
+    // Along with the title, the page buttons and the back button, this many rows fit in the
+    // default window with room to spare
+    let world = &mut app.app.world;
+    for (page, children) in world.query::<(&SettingsPageNode, &Children)>().iter(world) {
+        assert!(
+            children.len() <= 8,
+            "{:?} has {} rows",
+            page.0,
+            children.len()
+        );
+    }
+}
+
+#[test]
+fn turning_the_page_shows_only_that_page() {
+    let mut app = settings_menu();
+
+    app.app.insert_resource(SettingsPage::Guides);
+    app.step(FRAME);
+
+    assert_eq!(
+        shown_pages(&mut app),
+        [
+            (SettingsPage::Audio, false),
+            (SettingsPage::Display, false),
+            (SettingsPage::Guides, true),
+            (SettingsPage::Gameplay, false),
+        ]
+    );
+}
+
+#[test]
+fn settings_pages_stop_at_the_first_and_last() {
+    assert_eq!(SettingsPage::Audio.step(-1), SettingsPage::Audio);
+    assert_eq!(SettingsPage::Audio.step(1), SettingsPage::Display);
+    assert_eq!(SettingsPage::Gameplay.step(1), SettingsPage::Gameplay);
+}
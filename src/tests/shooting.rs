@@ -0,0 +1,190 @@
+use super::*;
+
+#[test]
+fn press_only_shoots_its_own_column_within_reach() {
+    let mut app = TestApp::new().with_system(shoot_targets);
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    let in_reach = app.spawn_target(Column(0), 0, hit_y);
+    let out_of_reach = app.spawn_target(Column(0), 0, 200.0);
+    let other_column = app.spawn_target(Column(1), 0, hit_y);
+
+    let key = app.key(Column(0));
+    app.tap(key);
+
+    assert_eq!(app.height(in_reach), None);
+    assert_eq!(app.height(out_of_reach), Some(200.0));
+    assert_eq!(app.height(other_column), Some(hit_y));
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetHitEvent>()[0].0, Column(0));
+}
+
+/// Presses a column with only a target far above the crosshair, out of reach
+fn ghost_tap(punish_ghost_taps: bool) -> TestApp {
+    let mut app = TestApp::new().with_system(shoot_targets);
+    app.app.insert_resource(PunishGhostTaps(punish_ghost_taps));
+    app.spawn_target(Column(2), 0, 200.0);
+
+    let key = app.key(Column(2));
+    app.tap(key);
+    app
+}
+
+#[test]
+fn ghost_tap_is_a_miss_when_punished() {
+    let mut app = ghost_tap(true);
+
+    assert_eq!(app.target_count(), 1);
+    assert_eq!(app.sent::<TargetMissEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetMissEvent>()[0].0, Column(2));
+    assert_eq!(app.resource::<Scoreboard>().score, -miss_penalty(0));
+    assert_eq!(app.resource::<Scoreboard>().misses, 1);
+}
+
+#[test]
+fn ghost_tap_is_forgiven_by_default() {
+    let mut app = ghost_tap(false);
+
+    assert_eq!(app.target_count(), 1);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+    assert_eq!(app.resource::<Scoreboard>().score, 0);
+    assert_eq!(app.resource::<Scoreboard>().misses, 0);
+}
+
+#[test]
+fn holding_a_key_down_only_hits_once() {
+    let mut app = TestApp::new()
+        .with_system(shoot_targets)
+        .with_system(update_targets);
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    let target = app.spawn_target(Column(3), 0, hit_y + 5.0);
+    let key = app.key(Column(3));
+
+    app.press(key);
+    app.step_frames(30);
+
+    assert_eq!(app.height(target), None);
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+
+    // A target that reaches the crosshair while the key's still down needs a fresh press
+    let next = app.spawn_target(Column(3), 0, hit_y + 5.0);
+    app.step_frames(4);
+    assert!(app.height(next).is_some());
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+}
+
+#[test]
+fn late_press_within_the_good_window_is_a_hit() {
+    let mut app = TestApp::new()
+        .with_system(shoot_targets)
+        .with_system(update_targets);
+    let config = app.resource::<GameConfig>();
+    // Past the crosshair, but still above the late bound
+    let late = (config.hit_y + config.late_hit_y) / 2.0;
+    app.spawn_target(Column(0), 0, late);
+
+    let key = app.key(Column(0));
+    app.tap(key);
+
+    assert_eq!(app.target_count(), 0);
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetHitEvent>()[0].1, Judgment::Good);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
+
+#[test]
+fn press_after_the_late_bound_leaves_the_target_to_be_missed() {
+    let mut app = TestApp::new()
+        .with_system(shoot_targets)
+        .with_system(update_targets);
+    let config = app.resource::<GameConfig>();
+    // Below the late bound, but not yet far enough down to be despawned
+    let too_late = (config.late_hit_y + config.despawn_y) / 2.0;
+    let target = app.spawn_target(Column(0), 0, too_late);
+
+    let key = app.key(Column(0));
+    app.tap(key);
+
+    assert!(app.height(target).is_some());
+    assert!(app.sent::<TargetHitEvent>().is_empty());
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+
+    app.step_frames(10);
+
+    assert_eq!(app.height(target), None);
+    assert!(app.sent::<TargetHitEvent>().is_empty());
+    assert_eq!(app.sent::<TargetMissEvent>().len(), 1);
+    assert_eq!(app.resource::<Scoreboard>().misses, 1);
+}
+
+/// How long [`shoot_targets`] takes a frame with a crowded playfield, for comparing changes to it.
+/// It's timed rather than checked, so it's only run when asked for, with
+/// `cargo test shoot_targets_timing -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn shoot_targets_timing() {
+    const TARGETS: u16 = 2000;
+    const FRAMES: u32 = 100;
+
+    let mut app = TestApp::new();
+    // Far above the crosshairs, so presses judge nothing and every frame does the same work
+    for i in 0..TARGETS {
+        app.spawn_target(Column((i % 4) as u8), 0, 200.0 + f32::from(i));
+    }
+    let keys: Vec<KeyCode> = (0..4).map(|column| app.key(Column(column))).collect();
+
+    // Only the system itself is timed, without the rest of the app's frame
+    let world = &mut app.app.world;
+    let mut system = IntoSystem::into_system(shoot_targets);
+    system.initialize(world);
+
+    // The fastest of many goes is kept, as it's the one least slowed by anything else running
+    let mut fastest = Duration::MAX;
+    for _ in 0..50 {
+        let start = Instant::now();
+        // Every column is pressed on one frame and let go on the next
+        for frame in 0..FRAMES {
+            let mut keyboard = world.resource_mut::<Input<KeyCode>>();
+            keyboard.clear();
+            for &key in &keys {
+                if frame % 2 == 0 {
+                    keyboard.press(key);
+                } else {
+                    keyboard.release(key);
+                }
+            }
+            system.run((), world);
+        }
+        fastest = fastest.min(start.elapsed() / FRAMES);
+    }
+    system.apply_buffers(world);
+
+    assert_eq!(app.target_count(), usize::from(TARGETS));
+    println!("{} targets: {:?} a frame", TARGETS, fastest);
+}
+
+#[test]
+fn press_on_a_long_frame_is_judged_where_the_target_moved_to() {
+    let mut app = TestApp::new()
+        .with_system(update_targets)
+        .with_system(shoot_targets.after(update_targets));
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), app.resource::<GameConfig>());
+    // A long frame only moves it as far as a capped frame, which brings it down to the crosshair.
+    // It moves for a frame first, so it's known to move again on the long frame
+    let fall = scroll_speed * MAX_FRAME_DELTA.as_secs_f32();
+    let target = app.spawn_target(
+        Column(0),
+        0,
+        hit_y + fall + scroll_speed * FRAME.as_secs_f32(),
+    );
+    app.step(FRAME);
+
+    app.press(app.key(Column(0)));
+    app.step(Duration::from_secs(2));
+
+    assert_eq!(app.height(target), None);
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetHitEvent>()[0].1, Judgment::Perfect);
+}
@@ -0,0 +1,45 @@
+use bevy::ecs::system::SystemState;
+
+use super::*;
+
+#[test]
+fn clips_start_part_way_through_the_song() {
+    let clip = wav::clip(&silent_wav(1000), 400, u32::MAX).unwrap();
+
+    assert_eq!(wav::length_ms(&clip), Some(600));
+}
+
+#[test]
+fn clips_from_past_the_end_of_the_song_are_silent() {
+    let clip = wav::clip(&silent_wav(1000), 2000, u32::MAX).unwrap();
+
+    assert_eq!(wav::length_ms(&clip), Some(0));
+}
+
+#[test]
+fn playing_from_past_the_end_of_the_song_plays_nothing() {
+    // Like a practice loop set after the song has ended
+    let mut app = TestApp::new();
+    let song = app
+        .app
+        .world
+        .resource_mut::<Assets<AudioSource>>()
+        .add(AudioSource {
+            bytes: silent_wav(1000).into(),
+        });
+    let mut song_player = SystemState::<SongPlayer>::new(&mut app.app.world);
+    assert!(song_player
+        .get_mut(&mut app.app.world)
+        .play_from(&song, 5000)
+        .is_some());
+
+    let sources = app.resource::<Assets<AudioSource>>();
+    let lengths: Vec<_> = sources
+        .iter()
+        .map(|(_, source)| wav::length_ms(&source.bytes))
+        .collect();
+    // The song, and a clip of it with nothing in it
+    assert_eq!(lengths.len(), 2);
+    assert!(lengths.contains(&Some(1000)));
+    assert!(lengths.contains(&Some(0)));
+}
@@ -0,0 +1,73 @@
+use super::*;
+
+#[test]
+fn quitting_to_the_start_menu_despawns_every_target() {
+    let mut app = TestApp::new().with_system(update_targets);
+    app.app
+        .add_loopless_state(GameState::Playing)
+        .add_exit_system(
+            GameState::Playing,
+            despawn_with::<Game>.run_unless_resource_exists::<ResumeState>(),
+        );
+    for (column, height) in [(0, 300.0), (1, 0.0), (2, -200.0), (3, -300.0)] {
+        app.spawn_target(Column(column), 0, height);
+    }
+    app.spawn_target(Column(1), 500, 100.0);
+    app.step(FRAME);
+    assert_eq!(app.target_count(), 5);
+
+    app.app.insert_resource(NextState(GameState::StartMenu));
+    app.step(FRAME);
+
+    assert_eq!(app.target_count(), 0);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
+
+#[test]
+fn the_hud_is_shown_without_crosshair_textures() {
+    let mut app = TestApp::new().with_system(setup_game);
+    app.app
+        .insert_resource(TextureAtlasHandles {
+            crosshairs: None,
+            targets: Some(Handle::default()),
+        })
+        .insert_resource(StartMode::default())
+        .insert_resource(KeyLabels(true))
+        // Auto play keeps the run from being recorded, which would clear a saved session
+        .insert_resource(AutoPlay(true))
+        .init_resource::<UpScroll>()
+        .init_resource::<ColumnShapes>()
+        .init_resource::<JudgmentLine>();
+    app.step(FRAME);
+
+    let world = &mut app.app.world;
+    assert_eq!(world.query::<&Crosshair>().iter(world).count(), 0);
+    assert_eq!(world.query::<&KeyLabel>().iter(world).count(), 4);
+    assert_eq!(world.query::<&ScoreDisplay>().iter(world).count(), 1);
+    assert_eq!(world.query::<&HealthBar>().iter(world).count(), 1);
+    assert_eq!(world.query::<&CountdownDisplay>().iter(world).count(), 1);
+}
+
+#[test]
+fn state_changes_straight_away_then_fades_in() {
+    let mut app = TestApp::new()
+        .with_system(fade_in_on_state_change)
+        .with_system(update_fade_overlay);
+    app.app.add_loopless_state(GameState::StartMenu);
+    app.step(FRAME);
+
+    app.app.insert_resource(NextState(GameState::Playing));
+    app.step(FRAME);
+
+    // Nothing is held back for a fade, so the old state's systems stop at once
+    assert_eq!(
+        app.resource::<CurrentState<GameState>>().0,
+        GameState::Playing
+    );
+    let world = &mut app.app.world;
+    assert_eq!(world.query::<&FadeOverlay>().iter(world).count(), 1);
+
+    app.step(FADE_DURATION);
+    let world = &mut app.app.world;
+    assert_eq!(world.query::<&FadeOverlay>().iter(world).count(), 0);
+}
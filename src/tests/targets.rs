@@ -0,0 +1,106 @@
+use super::*;
+
+#[test]
+fn target_falling_past_the_crosshair_is_missed() {
+    let mut app = TestApp::new().with_system(update_targets);
+    let despawn_y = app.resource::<GameConfig>().despawn_y;
+    let target = app.spawn_target(Column(2), 0, despawn_y + 5.0);
+
+    // Falling at 150 pixels a second, it's past the despawn line within a few frames
+    app.step_frames(5);
+
+    assert_eq!(app.height(target), None);
+    assert_eq!(app.sent::<TargetMissEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetMissEvent>()[0].0, Column(2));
+    assert_eq!(app.resource::<Scoreboard>().score, -miss_penalty(0));
+    assert_eq!(app.resource::<Scoreboard>().misses, 1);
+    assert_eq!(
+        app.resource::<Health>().current,
+        MAX_HEALTH - MISS_HEALTH_PENALTY
+    );
+}
+
+#[test]
+fn target_above_the_crosshair_keeps_falling() {
+    let mut app = TestApp::new().with_system(update_targets);
+    let target = app.spawn_target(Column(0), 0, 100.0);
+
+    app.step(Duration::from_millis(100));
+
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), app.resource::<GameConfig>());
+    let height = app.height(target).unwrap();
+    assert!((height - (100.0 - scroll_speed * 0.1)).abs() < 0.01);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
+
+#[test]
+fn pressing_a_column_shoots_its_target_at_the_crosshair() {
+    let mut app = TestApp::new().with_system(shoot_targets);
+    let hit_y = app.resource::<GameConfig>().hit_y;
+    app.spawn_target(Column(1), 0, hit_y);
+    let key = app.key(Column(1));
+
+    app.tap(key);
+
+    assert_eq!(app.target_count(), 0);
+    assert_eq!(app.sent::<TargetHitEvent>().len(), 1);
+    assert_eq!(app.sent::<TargetHitEvent>()[0].1, Judgment::Perfect);
+    assert_eq!(app.resource::<Scoreboard>().perfects, 1);
+}
+
+#[test]
+fn blocked_random_column_moves_along_without_drawing_another_number() {
+    let mut app = TestApp::new().with_system(spawn_targets);
+    app.app
+        .insert_resource(GameRng::from_seed(42))
+        .insert_resource(SpawnTimer(Timer::new(FRAME, true)));
+    let mut expected_rng = StdRng::seed_from_u64(42);
+    let picked = app
+        .resource::<GameConfig>()
+        .random_column(&mut expected_rng);
+
+    // Every column but the one two along from the pick already has a target where new ones spawn
+    let free = Column((picked.0 + 2) % 4);
+    let spawn_y = app.resource::<GameConfig>().spawn_y;
+    for column in (0..4).map(Column).filter(|&column| column != free) {
+        app.spawn_target(column, 0, spawn_y);
+    }
+    app.step(FRAME);
+
+    let mut columns: Vec<u8> = app
+        .app
+        .world
+        .query::<&Column>()
+        .iter(&app.app.world)
+        .map(|column| column.0)
+        .collect();
+    columns.sort_unstable();
+    assert_eq!(columns, [0, 1, 2, 3]);
+
+    // Only the pick was drawn, so the rest of the run's numbers are the same as they'd have been
+    let next = app.app.world.resource_mut::<GameRng>().rng.gen::<u64>();
+    assert_eq!(next, expected_rng.gen::<u64>());
+}
+
+#[test]
+fn a_long_frame_moves_targets_no_further_than_the_longest_allowed() {
+    let mut app = TestApp::new().with_system(update_targets);
+    let config = app.resource::<GameConfig>();
+    let (spawn_y, threshold_y) = (config.spawn_y, config.hit_threshold_y);
+    let scroll_speed = app
+        .resource::<ScrollSpeed>()
+        .apply(Difficulty::default(), app.resource::<GameConfig>());
+    let fresh = app.spawn_target(Column(0), 0, spawn_y);
+    // Just above the hit window, where a whole playfield's fall would take it far past the crosshair
+    let nearly_due = app.spawn_target(Column(1), 0, threshold_y + 1.0);
+
+    // Long enough to fall the whole playfield several times over
+    app.step(Duration::from_secs(30));
+
+    let fall = scroll_speed * MAX_FRAME_DELTA.as_secs_f32();
+    assert!((app.height(fresh).unwrap() - (spawn_y - fall)).abs() < 0.01);
+    assert!((app.height(nearly_due).unwrap() - (threshold_y + 1.0 - fall)).abs() < 0.01);
+    assert!(app.sent::<TargetMissEvent>().is_empty());
+}
@@ -0,0 +1,85 @@
+/// Reads a little-endian `u32` from the start of `bytes`
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+/// Reads a little-endian `u16` from the start of `bytes`
+fn read_u16(bytes: &[u8]) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(..2)?.try_into().ok()?))
+}
+
+/// Whether `bytes` are a WAV file, going by its header
+pub fn is_wav(bytes: &[u8]) -> bool {
+    bytes.get(..4) == Some(b"RIFF") && bytes.get(8..12) == Some(b"WAVE")
+}
+
+/// The format and audio chunks of a WAV file, or `None` if the bytes aren't one
+fn chunks(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    if !is_wav(bytes) {
+        return None;
+    }
+
+    // The format has to come before the audio, but other chunks can be anywhere
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = read_u32(&bytes[offset + 4..])? as usize;
+        let body = bytes.get(offset + 8..(offset + 8 + size).min(bytes.len()))?;
+        match id {
+            b"fmt " => format = Some(body),
+            b"data" => {
+                data = Some(body);
+                break;
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        offset += 8 + size + size % 2;
+    }
+    Some((format?, data?))
+}
+
+/// How long a WAV file's audio lasts, in milliseconds.
+/// Returns `None` if the bytes aren't a WAV file
+pub fn length_ms(bytes: &[u8]) -> Option<u32> {
+    let (format, data) = chunks(bytes)?;
+    let byte_rate = u64::from(read_u32(format.get(8..)?)?).max(1);
+    Some((data.len() as u64 * 1000 / byte_rate) as u32)
+}
+
+/// Cuts up to `length_ms` of audio out of a WAV file, starting `start_ms` in, as a WAV file of
+/// its own. Audio can only be played from the start, so this is how to play from part way through.
+///
+/// Starting past the end of the audio gives a clip with no audio in it.
+/// Returns `None` if the bytes aren't a WAV file
+pub fn clip(bytes: &[u8], start_ms: u32, length_ms: u32) -> Option<Vec<u8>> {
+    let (format, data) = chunks(bytes)?;
+
+    let byte_rate = u64::from(read_u32(format.get(8..)?)?);
+    let block_align = usize::from(read_u16(format.get(12..)?)?).max(1);
+    // Cuts have to land between frames, or the channels would be swapped around
+    let to_bytes =
+        |ms: u32| (byte_rate * u64::from(ms) / 1000) as usize / block_align * block_align;
+
+    let start = to_bytes(start_ms).min(data.len());
+    let end = (start + to_bytes(length_ms)).min(data.len());
+    let audio = &data[start..end];
+
+    let format_size = format.len() + format.len() % 2;
+    let mut clip = Vec::with_capacity(20 + format_size + 8 + audio.len());
+    clip.extend_from_slice(b"RIFF");
+    clip.extend_from_slice(&((4 + 8 + format_size + 8 + audio.len()) as u32).to_le_bytes());
+    clip.extend_from_slice(b"WAVE");
+    clip.extend_from_slice(b"fmt ");
+    clip.extend_from_slice(&(format.len() as u32).to_le_bytes());
+    clip.extend_from_slice(format);
+    if format.len() % 2 == 1 {
+        clip.push(0);
+    }
+    clip.extend_from_slice(b"data");
+    clip.extend_from_slice(&(audio.len() as u32).to_le_bytes());
+    clip.extend_from_slice(audio);
+    Some(clip)
+}